@@ -0,0 +1,47 @@
+//! Compare the time `parse` on a fresh copy of a parser and `parse_slice` on the same parser
+//! take over the same command line
+//!
+//! Run with `cargo bench`.
+
+extern crate arg_parser;
+#[macro_use]
+extern crate criterion;
+
+use arg_parser::ArgParser;
+use criterion::Criterion;
+use std::hint::black_box;
+
+const ARGS: [&str; 12] = [
+    "-vv", "--output=a.out", "-j", "4", "--include", "src,lib", "if=in", "of=out", "a", "b", "--", "-c",
+];
+
+fn parser() -> ArgParser {
+    ArgParser::new(6)
+        .add_flag(&["v", "verbose"])
+        .add_opt("o", "output")
+        .add_opt("j", "jobs")
+        .add_opt_multi_csv("I", "include")
+        .add_setting("if")
+        .add_setting("of")
+}
+
+fn parse(c: &mut Criterion) {
+    let spec = parser();
+    c.bench_function("parse", |b| b.iter(|| {
+        let mut parser = spec.clone_spec();
+        let args = ARGS.iter().map(|arg| arg.to_string());
+        let _ = parser.parse(black_box(std::iter::once(String::new()).chain(args)));
+        black_box(parser.found('v'));
+    }));
+}
+
+fn parse_slice(c: &mut Criterion) {
+    let mut parser = parser();
+    c.bench_function("parse_slice", |b| b.iter(|| {
+        let _ = parser.parse_slice(black_box(&ARGS));
+        black_box(parser.found('v'));
+    }));
+}
+
+criterion_group!(benches, parse, parse_slice);
+criterion_main!(benches);
@@ -0,0 +1,224 @@
+//! `#[derive(ArgParse)]` for `arg_parser`, enabled by its `derive` feature
+//!
+//! Each field of the struct is marked with the kind of parameter it holds, and the derive
+//! generates an associated `parse` building the `ArgParser` and filling the struct:
+//!
+//! ```text
+//! #[derive(ArgParse)]
+//! struct Opts {
+//!     #[flag(short = 'v', long = "verbose")]
+//!     verbose: bool,
+//!     #[opt(long = "output")]
+//!     output: Option<String>,
+//!     #[setting]
+//!     level: Option<String>,
+//! }
+//!
+//! let opts = Opts::parse(std::env::args())?;
+//! ```
+//!
+//! A parameter given neither a short nor a long name is named after its field.
+
+extern crate proc_macro;
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+/// The kinds of parameters a field can hold
+enum Kind {
+    /// A `bool`, true when the flag was found
+    Flag,
+    /// An `Option<String>`, the value of the opt
+    Opt,
+    /// An `Option<String>`, the value of the setting
+    Setting,
+}
+
+/// A field of the struct along with the parameter filling it
+struct Field {
+    name: String,
+    kind: Kind,
+    /// The short name as a char literal, e.g. `'v'`
+    short: Option<String>,
+    /// The long name as a string literal, e.g. `"verbose"`
+    long: Option<String>,
+}
+
+impl Field {
+    /// The short name as a string literal, e.g. `"v"`
+    fn short_str(&self) -> Option<String> {
+        self.short.as_ref().map(|short| format!("\"{}\"", short.trim_matches('\'')))
+    }
+
+    /// The literal to look the parameter up with, preferring the long name
+    fn key(&self) -> String {
+        match (&self.long, &self.short) {
+            (Some(long), _) => long.clone(),
+            (None, Some(short)) => format!("&{}", short),
+            (None, None) => unreachable!("fields are given a long name by default"),
+        }
+    }
+}
+
+#[proc_macro_derive(ArgParse, attributes(flag, opt, setting))]
+pub fn derive_arg_parse(input: TokenStream) -> TokenStream {
+    let code = match expand(input) {
+        Ok(code) => code,
+        Err(message) => format!("compile_error!({:?});", message),
+    };
+    code.parse().expect("generated code is valid Rust")
+}
+
+/// Generate the `parse` of the struct given as `input`
+fn expand(input: TokenStream) -> Result<String, String> {
+    let mut tokens = input.into_iter().peekable();
+    let mut name = None;
+    let mut body = None;
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Ident(ref ident) if ident.to_string() == "struct" => match tokens.next() {
+                Some(TokenTree::Ident(ident)) => name = Some(ident.to_string()),
+                _ => return Err(String::from("ArgParse expects a struct name")),
+            },
+            TokenTree::Ident(ref ident) if ident.to_string() == "enum" || ident.to_string() == "union" => {
+                return Err(String::from("ArgParse can only be derived for structs"));
+            }
+            TokenTree::Punct(ref punct) if punct.as_char() == '<' && name.is_some() => {
+                return Err(String::from("ArgParse doesn't support generic structs"));
+            }
+            TokenTree::Group(ref group) if name.is_some() => {
+                if group.delimiter() != Delimiter::Brace {
+                    return Err(String::from("ArgParse expects a struct with named fields"));
+                }
+                body = Some(group.stream());
+            }
+            _ => (),
+        }
+    }
+    let name = name.ok_or_else(|| String::from("ArgParse can only be derived for structs"))?;
+    let body = body.ok_or_else(|| String::from("ArgParse expects a struct with named fields"))?;
+    let fields = split_fields(body).into_iter().map(parse_field).collect::<Result<Vec<Field>, String>>()?;
+
+    let mut builder = String::new();
+    let mut values = String::new();
+    for field in fields.iter() {
+        match field.kind {
+            Kind::Flag => {
+                let names: Vec<String> = field.short_str().into_iter().chain(field.long.clone()).collect();
+                builder += &format!(".add_flag(&[{}])", names.join(", "));
+                values += &format!("{}: parser.found({}),", field.name, field.key());
+            }
+            Kind::Opt => {
+                let short = field.short_str().unwrap_or_else(|| String::from("\"\""));
+                let long = field.long.clone().unwrap_or_else(|| String::from("\"\""));
+                builder += &format!(".add_opt({}, {})", short, long);
+                values += &format!("{}: parser.get_opt({}),", field.name, field.key());
+            }
+            Kind::Setting => {
+                builder += &format!(".add_setting({})", field.key());
+                values += &format!("{}: parser.get_setting({}),", field.name, field.key());
+            }
+        }
+    }
+    Ok(format!("
+        impl {name} {{
+            pub fn parse<A: Iterator<Item = String>>(args: A) -> Result<Self, Vec<::arg_parser::ArgParseError>> {{
+                let mut parser = ::arg_parser::ArgParser::new({count}){builder};
+                parser.parse(args)?;
+                Ok({name} {{ {values} }})
+            }}
+        }}",
+        name = name, count = fields.len(), builder = builder, values = values))
+}
+
+/// Split the body of a struct on the commas between its fields
+fn split_fields(body: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut fields = vec![Vec::new()];
+    // Commas within the generics of a field's type don't end it
+    let mut depth = 0;
+    for token in body {
+        match token {
+            TokenTree::Punct(ref punct) if punct.as_char() == '<' => depth += 1,
+            TokenTree::Punct(ref punct) if punct.as_char() == '>' => depth -= 1,
+            TokenTree::Punct(ref punct) if punct.as_char() == ',' && depth == 0 => {
+                fields.push(Vec::new());
+                continue;
+            }
+            _ => (),
+        }
+        if let Some(field) = fields.last_mut() {
+            field.push(token);
+        }
+    }
+    fields.retain(|field| !field.is_empty());
+    fields
+}
+
+/// Read a field's name and the attribute telling which parameter fills it
+fn parse_field(tokens: Vec<TokenTree>) -> Result<Field, String> {
+    let mut kind = None;
+    let mut short = None;
+    let mut long = None;
+    let mut name = None;
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Punct(ref punct) if punct.as_char() == '#' => {
+                let attr = match tokens.next() {
+                    Some(TokenTree::Group(group)) => group.stream().into_iter().collect::<Vec<TokenTree>>(),
+                    _ => continue,
+                };
+                let found = match attr.first() {
+                    Some(TokenTree::Ident(ident)) if ident.to_string() == "flag" => Kind::Flag,
+                    Some(TokenTree::Ident(ident)) if ident.to_string() == "opt" => Kind::Opt,
+                    Some(TokenTree::Ident(ident)) if ident.to_string() == "setting" => Kind::Setting,
+                    _ => continue,
+                };
+                if kind.replace(found).is_some() {
+                    return Err(String::from("a field can only hold one parameter"));
+                }
+                if let Some(TokenTree::Group(args)) = attr.get(1) {
+                    parse_names(args.stream(), &mut short, &mut long)?;
+                }
+            }
+            TokenTree::Punct(ref punct) if punct.as_char() == ':' => break,
+            TokenTree::Ident(ref ident) if ident.to_string() != "pub" => name = Some(ident.to_string()),
+            _ => (),
+        }
+    }
+    let name = name.ok_or_else(|| String::from("ArgParse expects named fields"))?;
+    let kind = kind.ok_or_else(|| format!("field `{}` needs a #[flag], #[opt] or #[setting] attribute", name))?;
+    if kind == Kind::Setting && short.is_some() {
+        return Err(format!("setting `{}` can't have a short name", name));
+    }
+    if short.is_none() && long.is_none() {
+        long = Some(format!("\"{}\"", name.trim_start_matches("r#")));
+    }
+    Ok(Field { name, kind, short, long })
+}
+
+/// Read the `short = 'v', long = "verbose"` arguments of a field's attribute
+fn parse_names(args: TokenStream, short: &mut Option<String>, long: &mut Option<String>) -> Result<(), String> {
+    let mut args = args.into_iter();
+    while let Some(token) = args.next() {
+        let key = match token {
+            TokenTree::Ident(ident) => ident.to_string(),
+            TokenTree::Punct(ref punct) if punct.as_char() == ',' => continue,
+            other => return Err(format!("unexpected `{}` in attribute", other)),
+        };
+        let value = match (args.next(), args.next()) {
+            (Some(TokenTree::Punct(ref punct)), Some(TokenTree::Literal(value))) if punct.as_char() == '=' => {
+                value.to_string()
+            }
+            _ => return Err(format!("expected `{} = <literal>`", key)),
+        };
+        match key.as_str() {
+            "short" if value.starts_with('\'') => *short = Some(value),
+            "long" if value.starts_with('"') => *long = Some(value),
+            "short" => return Err(String::from("`short` takes a char, e.g. short = 'v'")),
+            "long" => return Err(String::from("`long` takes a string, e.g. long = \"verbose\"")),
+            _ => return Err(format!("unknown name `{}`, expected `short` or `long`", key)),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,36 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate arg_parser;
+
+use arg_parser::{format_time, to_human_readable_string, ArgParser};
+
+fuzz_target!(|data: &[u8]| {
+    // Every NUL-separated chunk of the input becomes one argument
+    let args = data.split(|&b| b == 0)
+        .map(|arg| String::from_utf8_lossy(arg).into_owned());
+
+    let mut parser = ArgParser::new(4)
+        .add_flag(&["a", "all"])
+        .add_opt("o", "output")
+        .add_opt_keyval("l", "label")
+        .add_setting("if");
+    let _ = parser.parse(args.clone());
+    let _ = parser.found_invalid();
+
+    // The cap holds even when a single cluster like `-xyz` brings several invalid params
+    let mut capped = parser.clone_spec().max_invalid(2);
+    let _ = capped.parse(args);
+    if let Err(invalid) = capped.found_invalid() {
+        assert!(invalid.params().len() + invalid.operands().len() <= 2);
+    }
+
+    if data.len() >= 16 {
+        let mut ts = [0; 8];
+        let mut tz = [0; 8];
+        ts.copy_from_slice(&data[..8]);
+        tz.copy_from_slice(&data[8..16]);
+        let _ = format_time(i64::from_le_bytes(ts), i64::from_le_bytes(tz));
+        let _ = to_human_readable_string(u64::from_le_bytes(ts));
+    }
+});
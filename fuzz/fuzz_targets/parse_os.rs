@@ -0,0 +1,53 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate arg_parser;
+
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+
+use arg_parser::{ArgParser, ParserStyle, SettingType, Severity};
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks the toggles, every NUL-separated chunk of the rest becomes one raw,
+    // possibly non UTF-8, argument
+    let (toggles, data) = match data.split_first() {
+        Some((&toggles, data)) => (toggles, data),
+        None => return,
+    };
+    let args = data.split(|&b| b == 0).map(|arg| OsString::from_vec(arg.to_vec()));
+    let style = match toggles % 3 {
+        0 => ParserStyle::Gnu,
+        1 => ParserStyle::Windows,
+        _ => ParserStyle::Mixed,
+    };
+
+    let mut parser = ArgParser::new(10)
+        .add_counted_flag(&["v", "verbose"])
+        .add_opt("o", "output")
+        .add_opt_implicit("c", "color", "auto")
+        .add_opt_multi_csv("x", "exclude")
+        .add_opt_greedy_words("g", "greedy")
+        .add_hybrid("d", "debug")
+        .add_setting_typed("count", SettingType::U64)
+        .add_deprecated("é", "output")
+        .value_separators(&['=', ':', '€'])
+        .style(style)
+        .case_insensitive_long(toggles & 4 != 0)
+        .expand_references(toggles & 8 != 0)
+        .strict(toggles & 16 != 0)
+        .stop_at_first_positional(toggles & 32 != 0)
+        .unknown_severity(if toggles & 64 != 0 { Severity::Warn } else { Severity::Error })
+        .retain_input(true);
+    let _ = parser.parse_os(Some(OsString::new()).into_iter().chain(args));
+    let _ = parser.found_invalid().map(|_| ()).map_err(|err| err.to_string());
+    let _ = parser.validate();
+    for diagnostic in parser.diagnostics().to_vec() {
+        let _ = parser.highlight(&diagnostic);
+    }
+    let _ = parser.args_os();
+    let _ = parser.get_opt_os("output");
+    let _ = parser.get_opt_list("exclude");
+    let _ = parser.events();
+    let _ = parser.to_parsed();
+});
@@ -0,0 +1,283 @@
+//! Formatting of times and sizes for command line output, with the `fmt` feature
+//!
+//! The parser itself only needs `parse_human_readable`, which is why it, and the
+//! `HUMAN_UNITS` it reads, stay available without the feature.
+
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use super::{parse_human_readable, HUMAN_UNITS};
+
+pub fn format_system_time(time: SystemTime) -> String {
+    format_system_time_tz(time, 0)
+}
+
+/// Format a `SystemTime` like `format_system_time`, shifted by `tz_offset` hours east of UTC.
+pub fn format_system_time_tz(time: SystemTime, tz_offset: i64) -> String {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => format_time(duration.as_secs() as i64, tz_offset),
+        Err(_) => "duration since epoch err".to_string(),
+    }
+}
+
+/// Format a `SystemTime` as an ISO-8601 string like `2017-03-01T12:30:00+05:00`.
+///
+/// `tz_offset` is in hours east of UTC; a zero offset is rendered as `Z`.
+pub fn format_system_time_iso(time: SystemTime, tz_offset: i64) -> String {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => {
+            let (c, e, f, h, m, s) = get_time_tuple(duration.as_secs() as i64, tz_offset);
+            let offset = if tz_offset == 0 {
+                "Z".to_owned()
+            } else {
+                let sign = if tz_offset < 0 { '-' } else { '+' };
+                format!("{}{:>02}:00", sign, tz_offset.unsigned_abs())
+            };
+            format!("{:>04}-{:>02}-{:>02}T{:>02}:{:>02}:{:>02}{}", c, e, f, h, m, s, offset)
+        }
+        Err(_) => "duration since epoch err".to_string(),
+    }
+}
+
+// Sweet algorithm from http://ptspts.blogspot.com/2009/11/how-to-convert-unix-timestamp-to-civil.html
+pub fn get_time_tuple(ts: i64, tz_offset: i64) -> (i64, i64, i64, i64, i64, i64) {
+    // Widen so that no timestamp or offset can overflow (and panic) during the conversion
+    let ts = ts as i128 + tz_offset as i128 * 3600;
+    // Before the epoch, the time of day still counts up from the start of the day
+    let s = ts.rem_euclid(86400);
+    let ts = ts.div_euclid(86400);
+    let h = s / 3600;
+    let m = s / 60 % 60;
+    let s = s % 60;
+    // Floored, so that days before 1900 are counted right too
+    let x = (ts * 4 + 102032).div_euclid(146097) + 15;
+    let b = ts + 2442113 + x - x.div_euclid(4);
+    let mut c = (b * 20 - 2442).div_euclid(7305);
+    let d = b - 365 * c - c.div_euclid(4);
+    let mut e = d * 1000 / 30601;
+    let f = d - e * 30 - e * 601 / 1000;
+    if e < 14 {
+        c -= 4716;
+        e -= 1;
+    } else {
+        c -= 4715;
+        e -= 13;
+    }
+    (c as i64, e as i64, f as i64, h as i64, m as i64, s as i64)
+}
+
+pub fn format_time(ts: i64, tz_offset: i64) -> String {
+    let (c, e, f, h, m, s) = get_time_tuple(ts, tz_offset);
+    format!("{:>04}-{:>02}-{:>02} {:>02}:{:>02}:{:>02}", c, e, f, h, m, s)
+}
+
+/// Parse a timezone offset into hours east of UTC, as taken by `format_system_time_tz`: `Z`,
+/// `UTC` or `GMT`, or a signed number of hours like `+2`, `-05`, `+05:00` or `UTC+2`.
+///
+/// The sign is the ISO-8601 one, unlike the POSIX `TZ` variable where `UTC+2` is west of UTC.
+/// Returns None for offsets that aren't whole hours, or of a day or more.
+pub fn parse_tz_offset(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let value = value.strip_prefix("UTC").or_else(|| value.strip_prefix("GMT")).unwrap_or(value);
+    if value.is_empty() || value == "Z" {
+        return Some(0);
+    }
+    let (sign, value) = match (value.strip_prefix('+'), value.strip_prefix('-')) {
+        (Some(rest), _) => (1, rest),
+        (_, Some(rest)) => (-1, rest),
+        _ => return None,
+    };
+    if !value.is_ascii() {
+        return None;
+    }
+    let (hours, minutes) = match value.split_once(':') {
+        Some(split) => split,
+        None if value.len() == 4 => value.split_at(2),
+        None => (value, "00"),
+    };
+    let digits = |part: &str| !part.is_empty() && part.len() <= 2 && part.bytes().all(|byte| byte.is_ascii_digit());
+    if !digits(hours) || minutes != "00" {
+        return None;
+    }
+    let hours: i64 = hours.parse().ok()?;
+    if hours < 24 { Some(sign * hours) } else { None }
+}
+
+/// Read a timezone offset from the environment variable `var`, as parsed by `parse_tz_offset`,
+/// e.g. `format_system_time_tz(time, env_tz_offset("TZ_OFFSET").unwrap_or(0))`. Returns None
+/// if it isn't set or can't be parsed.
+pub fn env_tz_offset(var: &str) -> Option<i64> {
+    env::var(var).ok().and_then(|value| parse_tz_offset(&value))
+}
+
+pub fn to_human_readable_string(size: u64) -> String {
+    to_human_readable_styled(size, false, false)
+}
+
+/// Format a size like `to_human_readable_string`, with a space between the number and the unit
+/// if `space` is set, and the unit spelled as bytes (`B`, `KiB`, `MiB`...) if `byte_suffix` is
+/// set. Both give e.g. `1.5 KiB` and `512 B`.
+pub fn to_human_readable_styled(size: u64, space: bool, byte_suffix: bool) -> String {
+    let separator = if space { " " } else { "" };
+    if size < 1024 {
+        return if byte_suffix {
+            format!("{}{}B", size, separator)
+        } else {
+            format!("{}", size)
+        };
+    }
+
+    let (scaled, unit) = scale(size, 1024);
+    format!("{:.1}{}{}{}",
+            scaled,
+            separator,
+            HUMAN_UNITS[unit],
+            if byte_suffix { "iB" } else { "" })
+}
+
+/// Format a size like `to_human_readable_styled`, in powers of 1000 (`k`, `M`, `G`...) as for
+/// disks and network rates, e.g. `1.5 kB`. `parse_human_readable` reads it back when spelled
+/// as bytes.
+pub fn to_human_readable_si(size: u64, space: bool, byte_suffix: bool) -> String {
+    let separator = if space { " " } else { "" };
+    let suffix = if byte_suffix { "B" } else { "" };
+    if size < 1000 {
+        return format!("{}{}{}", size, if byte_suffix { separator } else { "" }, suffix);
+    }
+    let (scaled, unit) = scale(size, 1000);
+    let unit = if unit == 1 { "k" } else { HUMAN_UNITS[unit] };
+    format!("{:.1}{}{}{}", scaled, separator, unit, suffix)
+}
+
+/// Divide a size by `base` until it is below it, returning it with the index of its unit in
+/// `HUMAN_UNITS`
+fn scale(size: u64, base: u32) -> (f64, usize) {
+    let base = base as f64;
+    let last = HUMAN_UNITS.len() - 1;
+    let mut scaled = size as f64;
+    let mut unit = 0;
+    while scaled >= base && unit < last {
+        scaled /= base;
+        unit += 1;
+    }
+    // Sizes just below the next unit would round up to `base`
+    if (scaled * 10.0).round() >= base * 10.0 && unit < last {
+        scaled /= base;
+        unit += 1;
+    }
+    (scaled, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HUMAN_UNITS, env_tz_offset, format_system_time_iso, format_system_time_tz, format_time,
+                parse_human_readable, parse_tz_offset, to_human_readable_si, to_human_readable_string,
+                to_human_readable_styled};
+    use std::env;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn iso_system_time() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_488_371_400);
+        assert_eq!(format_system_time_iso(time, 0), "2017-03-01T12:30:00Z");
+        assert_eq!(format_system_time_iso(time, 5), "2017-03-01T17:30:00+05:00");
+    }
+
+    #[test]
+    fn helpers_do_not_panic() {
+        for &ts in &[i64::MIN, -1, 0, 1, i64::MAX] {
+            for &tz in &[i64::MIN, -12, 0, 14, i64::MAX] {
+                let _ = format_time(ts, tz);
+                let _ = format_system_time_iso(UNIX_EPOCH, tz);
+            }
+        }
+        assert_eq!(to_human_readable_string(u64::MAX), "16.0E");
+    }
+
+    #[test]
+    fn human_units() {
+        assert_eq!(HUMAN_UNITS.len(), 7);
+        for (exponent, unit) in HUMAN_UNITS.iter().enumerate().skip(1) {
+            let size = 1024u64.pow(exponent as u32);
+            assert_eq!(to_human_readable_string(size), format!("1.0{}", unit));
+        }
+    }
+
+    #[test]
+    fn human_readable_styled() {
+        assert_eq!(to_human_readable_styled(1536, false, false), "1.5K");
+        assert_eq!(to_human_readable_styled(1536, true, false), "1.5 K");
+        assert_eq!(to_human_readable_styled(1536, false, true), "1.5KiB");
+        assert_eq!(to_human_readable_styled(1536, true, true), "1.5 KiB");
+        assert_eq!(to_human_readable_styled(512, false, false), "512");
+        assert_eq!(to_human_readable_styled(512, true, true), "512 B");
+        assert_eq!(to_human_readable_styled(512, false, true), "512B");
+        assert_eq!(to_human_readable_styled(u64::MAX, true, true), "16.0 EiB");
+        assert_eq!(parse_human_readable(&to_human_readable_styled(3 << 20, true, true)), Some(3 << 20));
+    }
+
+    #[test]
+    fn human_readable_boundaries() {
+        assert_eq!(to_human_readable_string(1023), "1023");
+        assert_eq!(to_human_readable_string(1024), "1.0K");
+        assert_eq!(to_human_readable_string(1024 * 1024 - 1), "1.0M");
+        assert_eq!(to_human_readable_string(1048576), "1.0M");
+        assert_eq!(to_human_readable_string(1048576 + 52429), "1.1M");
+        assert_eq!(to_human_readable_string(1 << 60), "1.0E");
+        assert_eq!(to_human_readable_string(u64::MAX), "16.0E");
+    }
+
+    #[test]
+    fn time_offsets() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_488_371_400);
+        assert_eq!(format_system_time_tz(time, 0), "2017-03-01 12:30:00");
+        assert_eq!(format_system_time_tz(time, 2), "2017-03-01 14:30:00");
+        assert_eq!(format_system_time_tz(time, -13), "2017-02-28 23:30:00");
+        assert_eq!(format_time(0, -2), "1969-12-31 22:00:00");
+        assert_eq!(format_time(-1, 0), "1969-12-31 23:59:59");
+        assert_eq!(format_time(-86400 * 365 + 3600, 2), "1969-01-01 03:00:00");
+    }
+
+    #[test]
+    fn pre_epoch_time() {
+        assert_eq!(format_time(-1, 0), "1969-12-31 23:59:59");
+        assert_eq!(format_time(-86401, 0), "1969-12-30 23:59:59");
+        assert_eq!(format_time(-2208988800, 0), "1900-01-01 00:00:00");
+        assert_eq!(format_time(-2208988801, 0), "1899-12-31 23:59:59");
+        assert_eq!(format_time(-5364662400, 0), "1800-01-01 00:00:00");
+        assert_eq!(format_time(-12219292800, 0), "1582-10-15 00:00:00");
+        assert_eq!(format_time(-62135596800, 0), "0001-01-01 00:00:00");
+        assert_eq!(format_time(-2208988800, -1), "1899-12-31 23:00:00");
+    }
+
+    #[test]
+    fn human_readable_si() {
+        assert_eq!(to_human_readable_si(999, true, true), "999 B");
+        assert_eq!(to_human_readable_si(999, false, false), "999");
+        assert_eq!(to_human_readable_si(1500, false, false), "1.5k");
+        assert_eq!(to_human_readable_si(1500, true, true), "1.5 kB");
+        assert_eq!(to_human_readable_si(999_960, true, true), "1.0 MB");
+        assert_eq!(to_human_readable_si(u64::MAX, false, true), "18.4EB");
+        assert_eq!(parse_human_readable(&to_human_readable_si(3_000_000, true, true)), Some(3_000_000));
+    }
+
+    #[test]
+    fn tz_offsets() {
+        assert_eq!(parse_tz_offset("Z"), Some(0));
+        assert_eq!(parse_tz_offset("UTC"), Some(0));
+        assert_eq!(parse_tz_offset("+2"), Some(2));
+        assert_eq!(parse_tz_offset("-05:00"), Some(-5));
+        assert_eq!(parse_tz_offset("+0530"), None);
+        assert_eq!(parse_tz_offset("UTC+10"), Some(10));
+        assert_eq!(parse_tz_offset("GMT-3"), Some(-3));
+        assert_eq!(parse_tz_offset("+24"), None);
+        assert_eq!(parse_tz_offset("3"), None);
+        assert_eq!(parse_tz_offset("+"), None);
+        assert_eq!(parse_tz_offset("é"), None);
+        assert_eq!(parse_tz_offset("+1é1"), None);
+        assert_eq!(parse_tz_offset("-é"), None);
+        env::set_var("ARG_PARSER_TEST_TZ", "+01:00");
+        assert_eq!(env_tz_offset("ARG_PARSER_TEST_TZ"), Some(1));
+        assert_eq!(env_tz_offset("ARG_PARSER_TEST_TZ_UNSET"), None);
+    }
+}
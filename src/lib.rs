@@ -1,9 +1,42 @@
-use std::borrow::Borrow;
+#[cfg(feature = "derive")]
+extern crate arg_parser_derive;
+// Lets the code generated by the derive name this crate as `::arg_parser` from within it too
+#[cfg(feature = "derive")]
+extern crate self as arg_parser;
+
+#[cfg(feature = "derive")]
+pub use arg_parser_derive::ArgParse;
+
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+#[cfg(feature = "fmt")]
+pub mod fmt;
+// Kept at the root, where they were before the `fmt` module
+#[cfg(feature = "fmt")]
+pub use fmt::{format_system_time, format_system_time_iso, format_system_time_tz, format_time, get_time_tuple,
+              to_human_readable_string, to_human_readable_styled};
+
+pub mod testing;
+
+use std::borrow::{Borrow, Cow};
 use std::cell::{RefCell, RefMut};
 use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+#[cfg(feature = "prompt")]
+use std::io::{self, BufRead, Write};
+use std::process;
 use std::hash::{Hash, Hasher};
+use std::iter::Peekable;
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::rc::Rc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::str::{Chars, FromStr};
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 /// The parameter styles for short, e.g. `-s`,
@@ -13,36 +46,527 @@ pub enum Param {
     Long(String),
 }
 
-impl Borrow<str> for Param {
-    fn borrow(&self) -> &str {
-        if let Param::Long(ref string) = *self {
-            string
-        } else {
-            ""
+// A `Param` hashes like its `ParamRef`, style included, so that maps keyed by `Param` can be
+// looked up by any `ParamName` without allocating, and `Short('a')` never meets `Long("a")`.
+impl Hash for Param {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.param_ref().hash(state)
+    }
+}
+
+impl<'a> Borrow<dyn ParamName + 'a> for Param {
+    fn borrow(&self) -> &(dyn ParamName + 'a) {
+        self
+    }
+}
+
+impl Hash for dyn ParamName + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.param_ref().hash(state)
+    }
+}
+
+impl PartialEq for dyn ParamName + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.param_ref() == other.param_ref()
+    }
+}
+
+impl Eq for dyn ParamName + '_ {}
+
+/// Lookups by any `ParamName` in the maps keyed by `Param`
+trait ParamMap<V> {
+    fn named<P: ParamName>(&self, name: P) -> Option<&V>;
+    fn named_mut<P: ParamName>(&mut self, name: P) -> Option<&mut V>;
+    fn named_key_value<P: ParamName>(&self, name: P) -> Option<(&Param, &V)>;
+
+    fn has_named<P: ParamName>(&self, name: P) -> bool {
+        self.named(name).is_some()
+    }
+}
+
+impl<V> ParamMap<V> for HashMap<Param, V> {
+    fn named<P: ParamName>(&self, name: P) -> Option<&V> {
+        self.get(&name as &dyn ParamName)
+    }
+
+    fn named_mut<P: ParamName>(&mut self, name: P) -> Option<&mut V> {
+        self.get_mut(&name as &dyn ParamName)
+    }
+
+    fn named_key_value<P: ParamName>(&self, name: P) -> Option<(&Param, &V)> {
+        self.get_key_value(&name as &dyn ParamName)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+/// A borrowed `Param`
+pub enum ParamRef<'a> {
+    Short(char),
+    Long(&'a str),
+}
+
+/// Anything naming a parameter: a `char` for a short one, a `str` or `String` for a long one, or
+/// a `Param`, whether owned or borrowed.
+pub trait ParamName {
+    fn param_ref(&self) -> ParamRef<'_>;
+}
+
+impl ParamName for char {
+    fn param_ref(&self) -> ParamRef<'_> {
+        ParamRef::Short(*self)
+    }
+}
+
+impl ParamName for str {
+    fn param_ref(&self) -> ParamRef<'_> {
+        ParamRef::Long(self)
+    }
+}
+
+impl ParamName for String {
+    fn param_ref(&self) -> ParamRef<'_> {
+        ParamRef::Long(self)
+    }
+}
+
+impl ParamName for Param {
+    fn param_ref(&self) -> ParamRef<'_> {
+        match self {
+            Param::Short(ch) => ParamRef::Short(*ch),
+            Param::Long(name) => ParamRef::Long(name),
         }
     }
 }
 
-impl Borrow<char> for Param {
-    fn borrow(&self) -> &char {
-        if let Param::Short(ref ch) = *self {
-            ch
-        } else {
-            const CH: &'static char = &'\0';
-            CH
+impl ParamName for ParamRef<'_> {
+    fn param_ref(&self) -> ParamRef<'_> {
+        *self
+    }
+}
+
+impl<T: ParamName + ?Sized> ParamName for &T {
+    fn param_ref(&self) -> ParamRef<'_> {
+        (**self).param_ref()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// Where the current value of a parameter comes from
+pub enum ValueSource {
+    /// Given on the command line
+    Cli,
+    /// Read from an environment variable
+    Env,
+    /// Read from the file given to `load_config`, with the `config` feature
+    Config,
+    /// The value it was registered with
+    Default,
+    /// Neither given nor defaulted, or not registered at all
+    Unset,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// The kinds of parameters, as told by `definitions`
+pub enum ParamKind {
+    /// Given on its own, e.g. `-v`
+    Flag,
+    /// Given with a value, e.g. `-o file`, including repeated and `key=value` opts
+    Opt,
+    /// Given as `name=value`
+    Setting,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// What is registered about a parameter, as returned by `definitions`
+pub struct ParamInfo {
+    /// The first long name, if any
+    pub long: Option<String>,
+    /// The first short name, if any
+    pub short: Option<char>,
+    pub kind: ParamKind,
+    /// The value it was registered with, if any
+    pub default: Option<String>,
+    /// The title of the section it was registered under with `group`, if any
+    pub group: Option<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// The value of a found parameter, as yielded by `iter_set`
+pub enum ParsedValue {
+    /// A flag, found
+    Flag(bool),
+    /// The last value given to an opt
+    Opt(String),
+    /// The last value given to a setting
+    Setting(String),
+    /// Every value of a repeated opt, `key=value` pairs included
+    Multi(Vec<String>),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// A parameter or positional as it appeared on the command line, as yielded by `events`
+pub enum ParsedItem {
+    /// A flag, under the name it was given by
+    Flag(Param),
+    /// An opt and the value it was given, once per value of a multi-valued opt
+    Opt(Param, String),
+    /// A setting and the value it was given
+    Setting(String, String),
+    /// An arg that isn't a parameter, the args after `--` included
+    Positional(String),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// How seriously a problem found while parsing is taken
+pub enum Severity {
+    /// Recorded in `warnings`, parsing is still successful
+    Warn,
+    /// Recorded as invalid, making `found_invalid` fail
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// The prefixes parameters are recognized by, as set by `style`
+pub enum ParserStyle {
+    /// `-v` and `--out=file`
+    Gnu,
+    /// `/v` and `/out:file`, args starting with `-` being positionals
+    Windows,
+    /// Both, `/name` only being a parameter if `name` is registered so that paths stay positionals
+    Mixed,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// The toggles changing how a parser reads its args, as set by the builder methods
+pub struct ParserConfig {
+    /// Whether `${name}` references in values are expanded after parsing
+    pub expand_references: bool,
+    /// The number of positionals after which the rest of the args are taken verbatim
+    pub verbatim_after: Option<usize>,
+    /// The number of invalid parameters after which parsing stops
+    pub max_invalid: Option<usize>,
+    /// Whether overriding an opt's value records a warning
+    pub warn_on_override: bool,
+    /// How unknown parameters are reported
+    pub unknown_severity: Severity,
+    /// The characters that can separate a long opt from its value
+    pub value_separators: Vec<char>,
+    /// An arg ending the options like `--` does
+    pub end_marker: Option<String>,
+    /// Whether long names match whatever their case
+    pub case_insensitive_long: bool,
+    /// Whether `_` and `-` are the same within long names
+    pub underscores_as_dashes: bool,
+    /// The globs an unknown parameter is collected by, instead of being invalid
+    pub unknown_patterns: Vec<String>,
+    /// Whether every unknown parameter is collected, instead of being invalid
+    pub collect_unknown: bool,
+    /// The number of positionals beyond which `validate` reports them as unexpected
+    pub max_positionals: Option<usize>,
+    /// Whether `parse` keeps the args it was given, program name included
+    pub retain_input: bool,
+    /// Whether long opts only take their value as `--opt=value`, not from the next arg
+    pub strict_long_values: bool,
+    /// The prefixes parameters are recognized by
+    pub style: ParserStyle,
+    /// Whether `parse` stops at the first problem, unknown parameters always being one
+    pub strict: bool,
+    /// Whether the first positional and every arg after it are taken verbatim
+    pub stop_at_first_positional: bool,
+    /// Whether `@file` args are replaced by the args read from `file`
+    pub response_files: bool,
+    /// Whether a long name can be abbreviated to any unambiguous prefix
+    pub abbreviations: bool,
+    /// The status `parse_or_exit` exits with when the command line is wrong
+    pub usage_exit_code: i32,
+    /// The home directory `add_opt_expanduser` expands `~/` to, instead of `$HOME`
+    pub home_dir: Option<String>,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        ParserConfig {
+            expand_references: false,
+            verbatim_after: None,
+            max_invalid: None,
+            warn_on_override: false,
+            unknown_severity: Severity::Error,
+            value_separators: vec!['='],
+            end_marker: None,
+            case_insensitive_long: false,
+            underscores_as_dashes: false,
+            unknown_patterns: Vec::new(),
+            collect_unknown: false,
+            max_positionals: None,
+            retain_input: false,
+            strict_long_values: false,
+            style: ParserStyle::Gnu,
+            strict: false,
+            stop_at_first_positional: false,
+            response_files: false,
+            abbreviations: false,
+            usage_exit_code: 2,
+            home_dir: None,
         }
     }
 }
 
-impl Hash for Param {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        match *self {
-            Param::Short(ref c) => c.hash(state),
-            Param::Long(ref s) => s.hash(state),
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// The invalid parameters found while parsing, returned by `found_invalid`
+pub struct InvalidParams {
+    params: Vec<Param>,
+    /// The registered long names close to the unknown ones, e.g. `--verbose` for `--verbos`
+    suggestions: Vec<(Param, String)>,
+    /// The names of the `name=value` args that aren't registered settings
+    operands: Vec<String>,
+    /// The error as displayed, built from the parser's `Messages`
+    message: String,
+}
+
+impl InvalidParams {
+    /// Get the invalid parameters, in the order they were found
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    /// Get the names of the `name=value` args whose name isn't a registered setting, as in
+    /// `count` for `count=3`, in the order they were found
+    pub fn operands(&self) -> &[String] {
+        &self.operands
+    }
+
+    /// Get the registered name closest to an invalid parameter, if one is close enough, e.g.
+    /// `--verbose` for `--verbos`
+    pub fn suggestion<P: ParamName>(&self, param: P) -> Option<&str> {
+        let param = param.param_ref();
+        self.suggestions.iter()
+            .find(|(invalid, _)| invalid.param_ref() == param)
+            .map(|(_, suggestion)| suggestion.as_str())
+    }
+}
+
+impl std::fmt::Display for InvalidParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for InvalidParams {}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// The kinds of values a setting registered with `add_setting_typed` accepts
+pub enum SettingType {
+    /// A number like `512`
+    U64,
+    /// A number like `-5`
+    I64,
+    /// A boolean, as accepted by `get_setting_bool`
+    Bool,
+    /// A size like `4K`, as accepted by `parse_human_readable`
+    Bytes,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// How many args a declared positional takes
+pub enum Arity {
+    /// Exactly one, reported by `validate` if missing
+    One,
+    /// One if there's any left
+    Optional,
+    /// All the ones left
+    Variadic,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// The required parameters missing and the positionals in excess, returned by `validate`
+pub struct ValidationError {
+    missing: Vec<Param>,
+    missing_positionals: Vec<String>,
+    unexpected: Vec<String>,
+    /// The values an opt doesn't accept, described along with what it does accept
+    invalid: Vec<String>,
+    constraints: Vec<String>,
+    /// The error as displayed, built from the parser's `Messages`
+    message: String,
+}
+
+impl ValidationError {
+    /// Get the required opts and settings that weren't given, in registration order
+    pub fn missing(&self) -> &[Param] {
+        &self.missing
+    }
+
+    /// Get the names of the positionals declared with `add_positional` that weren't given
+    pub fn missing_positionals(&self) -> &[String] {
+        &self.missing_positionals
+    }
+
+    /// Get the positionals given beyond the declared ones or the number set with
+    /// `max_positionals`
+    pub fn unexpected(&self) -> &[String] {
+        &self.unexpected
+    }
+
+    /// Get a description of each value an opt doesn't accept, like `Invalid value 'medium' for
+    /// '--mode', expected one of: fast, slow`
+    pub fn invalid(&self) -> &[String] {
+        &self.invalid
+    }
+
+    /// Get a description of each constraint between parameters that was broken, like
+    /// `'--output' requires '--format'`
+    pub fn constraints(&self) -> &[String] {
+        &self.constraints
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ValidationError {}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// The problem `parse_strict` stopped at, naming the parameter at fault as on the command line
+pub enum ParseError {
+    /// A parameter that isn't registered
+    Unknown(String),
+    /// An opt that ended the args without a value
+    MissingValue(String),
+    /// A registered parameter given a value it can't hold, or used in a way it can't be
+    InvalidValue(String),
+    /// A positional beyond `max_positionals`, with `strict`
+    Unexpected(String),
+    /// An abbreviation of several long parameters, along with their names
+    Ambiguous(String, Vec<String>),
+}
+
+impl ParseError {
+    /// Describe the error with `messages`, as `Display` does with the English ones
+    pub fn message(&self, messages: &dyn Messages) -> String {
+        match self {
+            ParseError::Unknown(arg) => messages.unknown_parameter(arg),
+            ParseError::MissingValue(arg) => messages.missing_value(arg),
+            ParseError::InvalidValue(arg) => messages.invalid_value(arg),
+            ParseError::Unexpected(arg) => messages.unexpected_argument(arg),
+            ParseError::Ambiguous(arg, candidates) => messages.ambiguous_parameter(arg, candidates),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.message(&English))
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// A problem found by `parse`, naming the parameter at fault as on the command line
+pub enum ArgParseError {
+    /// A short parameter that isn't registered, like `-x`
+    UnknownFlag(String),
+    /// A long parameter that isn't registered, like `--bogus`
+    UnknownOpt(String),
+    /// A `name=value` arg whose name isn't a registered setting
+    InvalidSetting(String),
+    /// An opt that ended the args without a value
+    MissingValue(String),
+    /// A registered parameter given a value it can't hold, or used in a way it can't be
+    InvalidValue(String),
+    /// A positional beyond `max_positionals`, with `strict`
+    UnexpectedArg(String),
+    /// An abbreviation of several long parameters, like `--ver` for `--verbose` and `--version`,
+    /// along with their names
+    AmbiguousOpt(String, Vec<String>),
+}
+
+impl ArgParseError {
+    /// Describe the error with `messages`, as `Display` does with the English ones
+    pub fn message(&self, messages: &dyn Messages) -> String {
+        match self {
+            ArgParseError::UnknownFlag(name) | ArgParseError::UnknownOpt(name) => messages.unknown_parameter(name),
+            ArgParseError::InvalidSetting(name) => messages.unknown_setting(name),
+            ArgParseError::MissingValue(name) => messages.missing_value(name),
+            ArgParseError::InvalidValue(name) => messages.invalid_value(name),
+            ArgParseError::UnexpectedArg(arg) => messages.unexpected_argument(arg),
+            ArgParseError::AmbiguousOpt(name, candidates) => messages.ambiguous_parameter(name, candidates),
+        }
+    }
+}
+
+impl std::fmt::Display for ArgParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.message(&English))
+    }
+}
+
+impl Error for ArgParseError {}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// Why `parse_opt` or `parse_setting` couldn't give a value, naming the parameter as on the
+/// command line
+pub enum ParseValueError {
+    /// A parameter that wasn't given and has no default
+    Missing(String),
+    /// A value that isn't valid for the type asked for, with the error of its parse
+    Invalid {
+        name: String,
+        value: String,
+        error: String,
+    },
+}
+
+impl ParseValueError {
+    /// Describe the error with `messages`, as `Display` does with the English ones
+    pub fn message(&self, messages: &dyn Messages) -> String {
+        match self {
+            ParseValueError::Missing(name) => messages.missing_value(name),
+            ParseValueError::Invalid { name, value, error } => messages.failed_value(value, name, error),
         }
     }
 }
 
+impl std::fmt::Display for ParseValueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.message(&English))
+    }
+}
+
+impl Error for ParseValueError {}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// The kinds of problems found while parsing
+pub enum DiagnosticKind {
+    /// A parameter that isn't registered
+    Unknown,
+    /// An opt that ended the args without a value
+    MissingValue,
+    /// A registered parameter given a value it can't hold, or used in a way it can't be
+    InvalidValue,
+    /// A positional beyond `max_positionals`, with `strict`
+    Unexpected,
+    /// A long parameter abbreviating several registered ones
+    Ambiguous,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// A problem found while parsing, pointing at the arg it was found in
+pub struct Diagnostic {
+    /// The position of the arg in the args given to `parse`, the program name being 0
+    pub index: usize,
+    /// The arg as it was given
+    pub token: String,
+    /// The parameter at fault within the arg
+    pub param: Param,
+    pub message: String,
+    pub kind: DiagnosticKind,
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 /// The Right Hand Side type
 struct Rhs<T> {
@@ -55,464 +579,7868 @@ struct Rhs<T> {
 impl<T> Rhs<T> {
     fn new(value: T) -> Self {
         Rhs {
-            value: value,
+            value,
             occurrences: 0,
         }
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-/// The Value for each parameter
-enum Value {
-    Flag(Rhs<Rc<RefCell<bool>>>),
-    /// The RHS String value is shared between both short and long parameters
-    Opt {
-        rhs: Rhs<Rc<RefCell<String>>>,
-        found: Rc<RefCell<bool>>
-    },
-    Setting {
-        rhs: Rhs<Rc<RefCell<String>>>,
-        found: Rc<RefCell<bool>>
-    },
+/// A registered parameter together with all of its aliases
+struct Entry<'a> {
+    shorts: Vec<char>,
+    longs: Vec<&'a str>,
+    value: &'a Value,
 }
 
-impl Value {
-    fn new_opt(value: Rc<RefCell<String>>, found: Rc<RefCell<bool>>) -> Self {
-        Value::Opt {
-            rhs: Rhs::new(value),
-            found,
+impl Entry<'_> {
+    /// The name the parameter is best known by: its first long name, else its first short one
+    fn name(&self) -> String {
+        match (self.longs.first(), self.shorts.first()) {
+            (Some(long), _) => (*long).to_owned(),
+            (None, Some(short)) => short.to_string(),
+            (None, None) => String::new(),
         }
     }
+}
 
-    fn new_setting(value: Rc<RefCell<String>>, found: Rc<RefCell<bool>>) -> Self {
-        Value::Setting {
-            rhs: Rhs::new(value),
-            found,
+/// Check whether an arg is written as a negative number, like `-5` or `-0.5`
+fn is_negative_number(arg: &str) -> bool {
+    match arg.strip_prefix('-') {
+        Some(number) => {
+            number.chars().any(|ch| ch.is_ascii_digit())
+                && number.chars().all(|ch| ch.is_ascii_digit() || ch == '.')
+                && number.matches('.').count() <= 1
         }
+        None => false,
     }
 }
 
-/// Our homebrewed Arg Parser
-#[derive(Clone, Debug, Default)]
-pub struct ArgParser {
-    params: HashMap<Param, Value>,
-    invalid: Vec<Param>,
-    garbage: (RefCell<bool>, RefCell<String>),
-    pub args: Vec<String>,
+/// Add an occurrence's value to a multi-valued opt, split on commas if `csv` is set, recording
+/// an event at `index` in `events` for every value it adds
+fn push_multi(rhs: &mut Rhs<Rc<RefCell<Vec<String>>>>, value: String, csv: bool, index: usize, param: Param,
+              events: &mut Vec<(usize, ParsedItem)>) {
+    let mut values = (*rhs.value).borrow_mut();
+    let before = values.len();
+    if csv {
+        values.extend(value.split(',').filter(|segment| !segment.is_empty()).map(String::from));
+    } else {
+        values.push(value);
+    }
+    events.extend(values[before..].iter().map(|value| (index, ParsedItem::Opt(param.clone(), value.clone()))));
+    drop(values);
+    rhs.occurrences += 1;
 }
 
-impl ArgParser {
-    /// Create a new ArgParser object
-    ///
-    /// The capacity specifies the initial capacity of the number parameters.
-    /// Always good to set it at the number of flags and opts total.
-    pub fn new(capacity: usize) -> Self {
-        ArgParser {
-            params: HashMap::with_capacity(capacity),
-            invalid: Vec::new(),
-            garbage: (RefCell::new(false), RefCell::new(String::with_capacity(0))),
-            args: Vec::new(),
+/// The event of an opt given `value` by the arg at `index`, as recorded in `events`
+fn opt_event(index: usize, param: Param, value: &Rc<RefCell<String>>) -> (usize, ParsedItem) {
+    (index, ParsedItem::Opt(param, (**value).borrow().clone()))
+}
+
+/// Split a value on `delimiter`, leaving out the empty items. A backslash escapes the delimiter
+/// or another backslash.
+fn split_delimited(value: &str, delimiter: char) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        match chars.clone().next() {
+            Some(next) if ch == '\\' && (next == delimiter || next == '\\') => {
+                item.push(next);
+                chars.next();
+            }
+            _ if ch == delimiter => items.extend(Some(std::mem::take(&mut item)).filter(|item| !item.is_empty())),
+            _ => item.push(ch),
         }
     }
+    items.extend(Some(item).filter(|item| !item.is_empty()));
+    items
+}
 
-    /// Builder method for adding both short and long flags
-    ///
-    /// Flags are just parameters that have no assigned values. They are used
-    /// for when certain features or options have been enabled for the application
-    ///
-    /// For example
-    /// > ls -l --human-readable
-    ///   ^  ^  ^
-    ///   |  |  |
-    ///   |  |  `-- A long flag to enable human readable numbers.
-    ///   |  `-- A short flag to enable the long format.
-    ///   `-- The command to list files.
-    pub fn add_flag(mut self, flags: &[&str]) -> Self {
-        let value = Rc::new(RefCell::new(bool::default()));
-        for flag in flags.iter() {
-            if flag.len() == 1 {
-                if let Some(short) = flag.chars().next() {
-                    self.params.insert(Param::Short(short), Value::Flag(Rhs::new(value.clone())));
-                }
-            } else if !flag.is_empty() {
-                self.params.insert(Param::Long((*flag).to_owned()), Value::Flag(Rhs::new(value.clone())));
+/// Take the value attached to a short opt in its cluster, as in `-ovalue` or `-o=value`
+fn attached_value(chars: Chars) -> Option<String> {
+    let rest: String = chars.collect();
+    match rest.strip_prefix('=') {
+        Some(value) => Some(value.to_owned()),
+        None if !rest.is_empty() => Some(rest),
+        None => None,
+    }
+}
+
+/// Get the original bytes of `value`, taken from `arg` given to `parse_os`: either all of it, or
+/// its end after the parameter, the lossy conversion of `arg` ending with `value`
+fn raw_value(arg: &OsString, value: &str) -> Option<OsString> {
+    let lossy = arg.to_string_lossy();
+    if lossy == value {
+        return Some(arg.clone());
+    }
+    if value.is_empty() || !lossy.ends_with(value) {
+        return None;
+    }
+    raw_suffix(arg, lossy.len() - value.len())
+}
+
+/// Get the end of `arg` starting at byte `start` of its lossy conversion, each invalid
+/// sequence of which is a 3 bytes long `U+FFFD`
+#[cfg(unix)]
+fn raw_suffix(arg: &OsString, start: usize) -> Option<OsString> {
+    let bytes = arg.as_bytes();
+    let (mut raw, mut lossy) = (0, 0);
+    loop {
+        let (valid, invalid) = match std::str::from_utf8(&bytes[raw..]) {
+            Ok(valid) => (valid, None),
+            Err(error) => {
+                let valid = std::str::from_utf8(&bytes[raw..raw + error.valid_up_to()]).ok()?;
+                (valid, Some(error.error_len().unwrap_or(bytes.len() - raw - error.valid_up_to())))
+            }
+        };
+        let offset = start - lossy;
+        if offset <= valid.len() {
+            if !valid.is_char_boundary(offset) {
+                return None;
             }
+            return Some(OsString::from_vec(bytes[raw + offset..].to_vec()));
+        }
+        raw += valid.len() + invalid?;
+        lossy += valid.len() + '\u{fffd}'.len_utf8();
+        if lossy > start {
+            return None;
         }
-        self
     }
+}
 
-    /// Builder method for adding both short and long opts
-    ///
-    /// Opts are parameters that hold assigned values. They are used
-    /// for when certain features or options have been enabled for the application
-    ///
-    /// For example
-    /// > ls -T 4 --color=always
-    ///   ^  ^    ^
-    ///   |  |    |
-    ///   |  |    `-- A long opt to enable the use of color with value `always`.
-    ///   |  `-- A short opt to set tab size to the value `4`.
-    ///   `-- The command to list files.
-    pub fn add_opt(mut self, short: &str, long: &str) -> Self {
-        let value = Rc::new(RefCell::new("".to_owned()));
-        let found = Rc::new(RefCell::new(false));
-        if let Some(short) = short.chars().next() {
-            self.params.insert(Param::Short(short), Value::new_opt(value.clone(), found.clone()));
+#[cfg(not(unix))]
+fn raw_suffix(_arg: &OsString, _start: usize) -> Option<OsString> {
+    None
+}
+
+/// Take the args up to the next one that looks like an option, joined by single spaces
+fn take_words<'a, I: Iterator<Item = Cow<'a, str>>>(args: &mut Peekable<I>) -> Option<String> {
+    let mut words: Option<String> = None;
+    while let Some(word) = args.next_if(|arg| !(arg.starts_with('-') && arg.len() > 1)) {
+        match words {
+            Some(ref mut words) => {
+                words.push(' ');
+                words.push_str(&word);
+            }
+            None => words = Some(word.into_owned()),
         }
-        if !long.is_empty() {
-            self.params.insert(Param::Long(long.to_owned()), Value::new_opt(value, found));
+    }
+    words
+}
+
+/// Count the single-character insertions, deletions and substitutions turning `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
         }
-        self
+        previous = current;
     }
+    previous[b.len()]
+}
 
-    pub fn add_opt_default(mut self, short: &str, long: &str, default: &str) -> Self {
-        let value = Rc::new(RefCell::new(default.to_owned()));
-        let found = Rc::new(RefCell::new(false));
-        if let Some(short) = short.chars().next() {
-            self.params.insert(Param::Short(short), Value::new_opt(value.clone(), found.clone()));
+/// Match `text` against a glob where `*` stands for any run of characters and `?` for any one
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut pattern = pattern.chars();
+    match pattern.next() {
+        None => text.is_empty(),
+        Some('*') => {
+            let rest = pattern.as_str();
+            text.char_indices().map(|(i, _)| i).chain(Some(text.len())).any(|i| glob_match(rest, &text[i..]))
         }
-        if !long.is_empty() {
-            self.params.insert(Param::Long(long.to_owned()), Value::new_opt(value, found));
+        Some(expected) => {
+            let mut chars = text.chars();
+            match chars.next() {
+                Some(ch) if expected == '?' || expected == ch => glob_match(pattern.as_str(), chars.as_str()),
+                _ => false,
+            }
         }
-        self
     }
+}
 
-    /// Builder method for adding settings
-    ///
-    /// Settings are parameters that hold assigned values. They are used
-    /// in some applications such as dd
-    ///
-    /// For example
-    /// > dd if=/path/file
-    ///   ^  ^
-    ///   |  |
-    ///   |  |
-    ///   |  `-- The setting set to /path/file
-    ///   `-- The command to list files.
-    pub fn add_setting(mut self, setting: &str) -> Self {
-        let value = Rc::new(RefCell::new("".to_owned()));
-        let found = Rc::new(RefCell::new(false));
-        if !setting.is_empty() {
-            self.params.insert(Param::Long(setting.to_owned()), Value::new_setting(value, found));
-        }
-        self
-    }
+/// Quote a value for a POSIX shell
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
 
-    pub fn add_setting_default(mut self, setting: &str, default: &str) -> Self {
-        let value = Rc::new(RefCell::new(default.to_owned()));
-        let found = Rc::new(RefCell::new(false));
-        if !setting.is_empty() {
-            self.params.insert(Param::Long(setting.to_owned()), Value::new_setting(value, found));
-        }
-        self
+/// Turn the echo of the terminal on stdin on or off, telling whether it worked
+#[cfg(all(feature = "prompt", unix))]
+fn set_echo(enabled: bool) -> bool {
+    process::Command::new("stty")
+        .arg(if enabled { "echo" } else { "-echo" })
+        .stdin(process::Stdio::inherit())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(all(feature = "prompt", not(unix)))]
+fn set_echo(_enabled: bool) -> bool {
+    false
+}
+
+/// Leniently interpret a value as a boolean, e.g. `true`/`false`, `yes`/`no`, `on`/`off`, `1`/`0`
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
     }
+}
 
-    /// Start parsing user inputted args for which flags and opts are used at
-    /// runtime. The rest of the args that are not associated to opts get added
-    /// to `ArgParser.args`.
-    pub fn parse<A: Iterator<Item = String>>(&mut self, args: A) {
-        let mut args = args.skip(1);
-        while let Some(arg) = args.next() {
-            if arg.starts_with("--") {
-                // Remove both dashes
-                let arg = &arg[2..];
-                if arg.is_empty() {
-                    //Arg `--` means we are done parsing args, collect the rest
-                    self.args.extend(args);
-                    break;
-                }
-                if let Some(i) = arg.find('=') {
-                    let (lhs, rhs) = arg.split_at(i);
-                    let rhs = &rhs[1..]; // slice off the `=` char
-                    match self.params.get_mut(lhs) {
-                        Some(&mut Value::Opt { rhs: ref mut opt_rhs, ref mut found }) => {
-                            if (*opt_rhs.value).borrow().is_empty() {
-                                opt_rhs.occurrences = 1;
-                            } else {
-                                opt_rhs.occurrences += 1;
-                            }
-                            (*opt_rhs.value).borrow_mut().clear();
-                            (*opt_rhs.value).borrow_mut().push_str(rhs);
-                            *(*found).borrow_mut() = true;
-                        }
-                        _ => self.invalid.push(Param::Long(lhs.to_owned())),
-                    }
-                } else {
-                    match self.params.get_mut(arg) {
-                        Some(&mut Value::Flag(ref mut rhs)) => {
-                            *(*rhs.value).borrow_mut() = true;
-                            rhs.occurrences += 1;
-                        }
-                        Some(&mut Value::Opt { ref mut rhs, ref mut found }) => {
-                            rhs.occurrences += 1;
-                            *(*found).borrow_mut() = true;
-                        }
-                        _ => self.invalid.push(Param::Long(arg.to_owned())),
-                    }
-                }
-            } else if arg.starts_with("-") && arg != "-" {
-                let mut chars = arg[1..].chars();
-                while let Some(ch) = chars.next() {
-                    match self.params.get_mut(&ch) {
-                        Some(&mut Value::Flag(ref mut rhs)) => {
-                            *(*rhs.value).borrow_mut() = true;
-                            rhs.occurrences += 1;
-                        }
-                        Some(&mut Value::Opt { ref mut rhs, ref mut found }) => {
-                            let rest: String = chars.collect();
-                            if !rest.is_empty() {
-                                *(*rhs.value).borrow_mut() = rest;
-                                *(*found).borrow_mut() = true;
-                            } else {
-                                *(*rhs.value).borrow_mut() = args.next()
-                                    .map(|a| {
-                                             *(*found).borrow_mut() = true;
-                                             a
-                                         })
-                                    .unwrap_or("".to_owned());
-                            }
-                            break;
-                        }
-                        Some(&mut Value::Setting { .. }) => self.invalid.push(Param::Short(ch)),
-                        None => self.invalid.push(Param::Short(ch)),
-                    }
-                }
-            } else if arg.contains("=") {
-                if arg.is_empty() {
-                    //Arg `--` means we are done parsing args, collect the rest
-                    self.args.extend(args);
-                    break;
+/// Split a command line into words the way a shell would.
+///
+/// Single quotes keep everything literally, double quotes allow `\"` and `\\` escapes, and
+/// outside of quotes a backslash escapes the next character, e.g. a space. An unterminated quote
+/// is an error. A leading UTF-8 BOM is dropped and `\r` separates words like any whitespace, so
+/// text saved on Windows splits the same.
+fn split_shell(line: &str) -> Result<Vec<String>, String> {
+    let line = line.strip_prefix('\u{feff}').unwrap_or(line);
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => word.push(ch),
+            (Some(_), '\\') => match chars.peek() {
+                Some(&escaped) if escaped == '"' || escaped == '\\' => {
+                    word.push(escaped);
+                    chars.next();
                 }
-                if let Some(i) = arg.find('=') {
-                    let (lhs, rhs) = arg.split_at(i);
-                    let rhs = &rhs[1..]; // slice off the `=` char
-                    match self.params.get_mut(lhs) {
-                        Some(&mut Value::Setting { rhs: ref mut opt_rhs, ref mut found }) => {
-                            if (*opt_rhs.value).borrow().is_empty() {
-                                opt_rhs.occurrences = 1;
-                            } else {
-                                opt_rhs.occurrences += 1;
-                            }
-                            (*opt_rhs.value).borrow_mut().clear();
-                            (*opt_rhs.value).borrow_mut().push_str(rhs);
-                            *(*found).borrow_mut() = true;
-                        }
-                        _ => self.invalid.push(Param::Long(lhs.to_owned())),
-                    }
+                _ => word.push(ch),
+            },
+            (Some(_), _) => word.push(ch),
+            (None, '\\') => {
+                word.push(chars.next().unwrap_or(ch));
+                in_word = true;
+            }
+            (None, '\'') | (None, '"') => {
+                quote = Some(ch);
+                in_word = true;
+            }
+            (None, _) if ch.is_whitespace() => {
+                if in_word {
+                    words.push(word.split_off(0));
+                    in_word = false;
                 }
-            } else {
-                self.args.push(arg);
+            }
+            (None, _) => {
+                word.push(ch);
+                in_word = true;
             }
         }
     }
+    if let Some(quote) = quote {
+        let kind = if quote == '"' { "double" } else { "single" };
+        return Err(format!("Unterminated {} quote in '{}'", kind, line));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
 
-    /// Get the number of times a flag or opt has been found after parsing.
-    pub fn count<P: Hash + Eq + ?Sized>(&self, name: &P) -> usize
-        where Param: Borrow<P>
-    {
-        match self.params.get(name) {
-            Some(&Value::Flag(ref rhs)) => rhs.occurrences,
-            Some(&Value::Opt { ref rhs, .. }) => rhs.occurrences,
-            _ => 0,
-        }
+/// Strip the dashes the names of an opt may have been registered with, so that
+/// `add_opt("-o", "--output")` is the same as `add_opt("o", "output")`
+fn strip_dashes<'a>(short: &'a str, long: &'a str) -> (&'a str, &'a str) {
+    (short.trim_start_matches('-'), long.trim_start_matches('-'))
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// The sections of `usage` and `help_text` other than those registered with `group`
+pub enum Section {
+    Flags,
+    Options,
+    Settings,
+    /// The commands and subcommands, only listed by `help_text`
+    Commands,
+}
+
+/// The messages a parser builds its diagnostics, warnings, errors, usage and help from, given
+/// to `ArgParser::messages`
+///
+/// Every method has an English default, so a translation only overrides the ones it needs.
+pub trait Messages {
+    /// A parameter that isn't registered, named as given
+    fn unknown_parameter(&self, name: &str) -> String {
+        format!("Unknown parameter '{}'", name)
     }
 
-    /// Check if a flag or opt has been found after initialization.
-    pub fn found<P: Hash + Eq + ?Sized>(&self, name: &P) -> bool
-        where Param: Borrow<P>
-    {
-        match self.params.get(name) {
-            Some(&Value::Flag(ref rhs)) => *(*rhs.value).borrow_mut(),
-            Some(&Value::Opt { ref found, .. }) => *(**found).borrow(),
-            Some(&Value::Setting { ref found, .. }) => *(**found).borrow(),
-            _ => false,
-        }
+    /// A registered parameter given a value it can't hold
+    fn invalid_value(&self, name: &str) -> String {
+        format!("Invalid value for '{}'", name)
     }
 
-    /// Modify the state of a flag. Use `true` if the flag is to be enabled. Use `false` to
-    /// disable its use.
-    pub fn flag<F: Hash + Eq + ?Sized>(&mut self, flag: &F) -> RefMut<bool>
-        where Param: Borrow<F>
-    {
-        if let Some(&mut Value::Flag(ref mut rhs)) = self.params.get_mut(flag) {
-            return (*rhs.value).borrow_mut();
-        }
-        self.garbage.0.borrow_mut()
+    /// An opt that ended the args without a value
+    fn missing_value(&self, name: &str) -> String {
+        format!("Missing value for '{}'", name)
     }
 
-    /// Modify the state value of an opt. Use `Some(String)` to set if the opt is to be enabled and
-    /// has been assigned a value from `String`. Use `None` to disable the opt's use.
-    pub fn opt<O: Hash + Eq + ?Sized>(&mut self, opt: &O) -> RefMut<String>
-        where Param: Borrow<O>
-    {
-        if let Some(&mut Value::Opt { ref mut rhs, .. }) = self.params.get_mut(opt) {
-            return (*rhs.value).borrow_mut();
-        }
-        self.garbage.1.borrow_mut()
+    /// A `name=value` arg whose name isn't a registered setting
+    fn unknown_operand(&self, name: &str) -> String {
+        format!("Unknown operand '{}'", name)
     }
 
-    /// Get the value of an Opt. If it has been set or defaulted, it will return a `Some(String)`
-    /// value otherwise it will return None.
-    pub fn get_opt<O: Hash + Eq + ?Sized>(&self, opt: &O) -> Option<String>
-        where Param: Borrow<O>
-    {
-        if let Some(&Value::Opt { ref rhs, ref found }) = self.params.get(opt) {
-            if *(**found).borrow() {
-                return Some((*rhs.value).borrow().clone());
-            }
-        }
-        None
+    /// The first line of a `found_invalid` error, listing the invalid parameters
+    fn invalid_parameters(&self, names: &[String]) -> String {
+        let names: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+        let title = if names.len() > 1 { "Invalid parameters" } else { "Invalid parameter" };
+        format!("{} {}", title, names.join(" and "))
     }
 
-    /// Get the value of an Setting. If it has been set or defaulted, it will return a `Some(String)`
-    /// value otherwise it will return None.
-    pub fn get_setting<O: Hash + Eq + ?Sized>(&self, setting: &O) -> Option<String>
-        where Param: Borrow<O>
-    {
-        if let Some(&Value::Setting { ref rhs, ref found }) = self.params.get(setting) {
-            if *(**found).borrow() {
-                return Some((*rhs.value).borrow().clone());
-            }
+    /// A `name=value` arg whose name isn't a registered setting, as a parse error
+    fn unknown_setting(&self, name: &str) -> String {
+        format!("Unknown setting '{}'", name)
+    }
+
+    /// A positional beyond those accepted
+    fn unexpected_argument(&self, arg: &str) -> String {
+        format!("Unexpected argument '{}'", arg)
+    }
+
+    /// An abbreviation of several long parameters, with their names
+    fn ambiguous_parameter(&self, name: &str, candidates: &[String]) -> String {
+        let candidates: Vec<String> = candidates.iter().map(|candidate| format!("'{}'", candidate)).collect();
+        format!("Ambiguous parameter '{}', could be {}", name, candidates.join(" or "))
+    }
+
+    /// A parameter found invalid, in `validate_args`
+    fn invalid_parameter(&self, name: &str) -> String {
+        format!("Invalid parameter '{}'", name)
+    }
+
+    /// A value an opt or setting doesn't accept
+    fn rejected_value(&self, value: &str, name: &str) -> String {
+        format!("Invalid value '{}' for '{}'", value, name)
+    }
+
+    /// A value that isn't one of the choices of an opt, listed
+    fn invalid_choice(&self, value: &str, name: &str, choices: &[String]) -> String {
+        format!("Invalid value '{}' for '{}', expected one of: {}", value, name, choices.join(", "))
+    }
+
+    /// A value the check of an opt failed, with the check's error
+    fn failed_check(&self, name: &str, error: &str) -> String {
+        format!("Invalid value for '{}': {}", name, error)
+    }
+
+    /// A value that couldn't be parsed as the type asked for, with the parse's error
+    fn failed_value(&self, value: &str, name: &str, error: &str) -> String {
+        format!("Invalid value '{}' for '{}': {}", value, name, error)
+    }
+
+    /// A required opt or setting that wasn't given
+    fn missing_required(&self, name: &str) -> String {
+        format!("Missing required parameter '{}'", name)
+    }
+
+    /// A positional declared with `add_positional` that wasn't given
+    fn missing_argument(&self, name: &str) -> String {
+        format!("Missing argument '<{}>'", name)
+    }
+
+    /// Parameters of an exclusive group given together
+    fn conflicting(&self, names: &[String]) -> String {
+        let names: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+        format!("Conflicting parameters {}", names.join(" and "))
+    }
+
+    /// A parameter given without the one it `requires`
+    fn requires(&self, name: &str, required: &str) -> String {
+        format!("'{}' requires '{}'", name, required)
+    }
+
+    /// A `one_of` group none of which was given
+    fn one_of_required(&self, names: &[String]) -> String {
+        let mut names: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+        let last = names.pop().unwrap_or_default();
+        let names = if names.is_empty() { last } else { format!("{} or {}", names.join(", "), last) };
+        format!("One of {} is required", names)
+    }
+
+    /// An unstable parameter given without opting in, by the long flag `flag` or else the
+    /// environment variable `var` if either is set
+    fn unstable(&self, name: &str, flag: Option<&str>, var: Option<&str>) -> String {
+        match (flag, var) {
+            (Some(flag), _) => format!("'{}' is unstable, pass '--{}' to use it", name, flag),
+            (None, Some(var)) => format!("'{}' is unstable, set {} to use it", name, var),
+            (None, None) => format!("'{}' is unstable", name),
         }
-        None
     }
 
-    pub fn found_invalid(&self) -> Result<(), String> {
-        if self.invalid.is_empty() {
-            return Ok(());
+    /// A deprecated name given, along with the one to use instead
+    fn deprecated(&self, old: &str, new: &str) -> String {
+        format!("'{}' is deprecated, use '{}' instead", old, new)
+    }
+
+    /// An opt given again, replacing its previous value
+    fn overridden(&self, name: &str, previous: &str, value: &str) -> String {
+        format!("{} overridden: '{}' replaced by '{}'", name, previous, value)
+    }
+
+    /// A response file given inside too many others
+    fn response_file_too_deep(&self, path: &str) -> String {
+        format!("Response file '{}' is nested too deeply", path)
+    }
+
+    /// A response file that couldn't be read or split, with the error
+    fn unreadable_response_file(&self, path: &str, error: &str) -> String {
+        format!("Can't read response file '{}': {}", path, error)
+    }
+
+    /// A registered name close to an unknown one
+    fn did_you_mean(&self, suggestion: &str, name: &str) -> String {
+        format!("Did you mean '{}' instead of '{}'?", suggestion, name)
+    }
+
+    /// The start of the first `usage` line
+    fn usage(&self, program: &str) -> String {
+        format!("Usage: {}", program)
+    }
+
+    /// The title of a `usage` or `help_text` section
+    fn section_title(&self, section: Section) -> String {
+        String::from(match section {
+            Section::Flags => "Flags",
+            Section::Options => "Options",
+            Section::Settings => "Settings",
+            Section::Commands => "Commands",
+        })
+    }
+
+    /// The default value of an opt or setting, shown after its description
+    fn default_value(&self, default: &str) -> String {
+        format!("[default: {}]", default)
+    }
+}
+
+/// The English `Messages`, used unless `ArgParser::messages` is given others
+pub struct English;
+
+impl Messages for English {}
+
+/// Shared `Messages`, so that clones of a parser speak the same language
+#[derive(Clone)]
+struct MessagesRef(Rc<dyn Messages>);
+
+impl std::fmt::Debug for MessagesRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Messages")
+    }
+}
+
+/// The sections of `usage`, in order
+const USAGE_SECTIONS: [Section; 3] = [Section::Flags, Section::Options, Section::Settings];
+
+/// A section of `usage`: its title, whether it was registered with `group`, and a line for each
+/// of its parameters, with their names and help
+struct UsageSection {
+    title: String,
+    group: bool,
+    lines: Vec<(String, Option<String>)>,
+}
+
+/// The `USAGE_SECTIONS`, followed by those registered with `group`
+type UsageSections = Vec<UsageSection>;
+
+/// Escape text for troff, so dashes print as such and no line is taken for a request
+fn roff_escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
+
+/// Read the `key = value` lines of a TOML or INI file, with the line number of each. Keys under
+/// a `[section]` are prefixed with it, and quoted values unquoted.
+#[cfg(feature = "config")]
+fn parse_config(text: &str) -> Result<Vec<(String, String, usize)>, String> {
+    let mut values = Vec::new();
+    let mut section = String::new();
+    for (i, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            section = line[1..line.len() - 1].trim().to_owned();
+            continue;
         }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return Err(format!("Expected 'key = value' on line {}", i + 1)),
+        };
+        let value = match value.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => config_string(&value[1..], quote, i + 1)?,
+            // An unquoted value ends at a comment
+            _ => value[..config_comment(value).unwrap_or(value.len())].trim_end().to_owned(),
+        };
+        let key = if section.is_empty() { key.to_owned() } else { format!("{}.{}", section, key) };
+        values.push((key, value, i + 1));
+    }
+    Ok(values)
+}
 
-        let mut and: bool = false;
-        let mut output = if self.invalid.len() == 1 {
-                "Invalid parameter"
-            } else {
-                and = true;
-                "Invalid parameters"
-            }
-            .to_owned();
+/// Find where the `#` or `;` comment ending a config line starts, at its start or after a space
+#[cfg(feature = "config")]
+fn config_comment(text: &str) -> Option<usize> {
+    let mut previous = ' ';
+    text.char_indices()
+        .find(|&(_, ch)| {
+            let comment = (ch == '#' || ch == ';') && previous.is_whitespace();
+            previous = ch;
+            comment
+        })
+        .map(|(i, _)| i)
+}
 
-        let mut iter = self.invalid.iter().peekable();
-        while let Some(param) = iter.next() {
-            match param {
-                &Param::Short(ch) => {
-                    output += " '-";
-                    output.push(ch);
-                    output.push('\'');
+/// Read a quoted value, `text` following its opening `quote`, up to the closing one. Only a
+/// comment may come after it. Double quotes allow `\"` and `\\` escapes, single quotes keep
+/// their content literally.
+#[cfg(feature = "config")]
+fn config_string(text: &str, quote: char, line: usize) -> Result<String, String> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '\\' if quote == '"' => match chars.next() {
+                Some((_, escaped)) if escaped == '"' || escaped == '\\' => value.push(escaped),
+                Some((_, other)) => {
+                    value.push('\\');
+                    value.push(other);
                 }
-                &Param::Long(ref s) => {
-                    output += " '--";
-                    output += s;
-                    output.push('\'');
+                None => value.push('\\'),
+            },
+            ch if ch == quote => {
+                let rest = text[i + ch.len_utf8()..].trim_start();
+                if !rest.is_empty() && config_comment(rest) != Some(0) {
+                    return Err(format!("Unexpected '{}' after the value on line {}", rest, line));
                 }
+                return Ok(value);
             }
-            if and && iter.peek().is_some() {
-                output += " and";
-            }
+            ch => value.push(ch),
         }
-        output.push('\n');
-        Err(output)
     }
+    Err(format!("Unterminated string on line {}", line))
 }
 
-pub fn format_system_time(time: SystemTime) -> String {
-    let tz_offset = 0; //TODO Apply timezone offset
-    match time.duration_since(UNIX_EPOCH) {
-        Ok(duration) => format_time(duration.as_secs() as i64, tz_offset), 
-        Err(_) => "duration since epoch err".to_string(),
+/// Name a parameter by its short name if it's a single character, or else its long one, with
+/// or without dashes
+fn param_named(name: &str) -> Option<Param> {
+    let name = name.trim_start_matches('-');
+    match name.chars().next() {
+        Some(short) if name.chars().count() == 1 => Some(Param::Short(short)),
+        Some(_) => Some(Param::Long(name.to_owned())),
+        None => None,
     }
 }
 
-// Sweet algorithm from http://ptspts.blogspot.com/2009/11/how-to-convert-unix-timestamp-to-civil.html
-// TODO: Apply timezone offset
-pub fn get_time_tuple(mut ts: i64, tz_offset: i64) -> (i64, i64, i64, i64, i64, i64) {
-    ts += tz_offset * 3600;
-    let s = ts % 86400;
-    ts /= 86400;
-    let h = s / 3600;
-    let m = s / 60 % 60;
-    let s = s % 60;
-    let x = (ts * 4 + 102032) / 146097 + 15;
-    let b = ts + 2442113 + x - (x / 4);
-    let mut c = (b * 20 - 2442) / 7305;
-    let d = b - 365 * c - c / 4;
-    let mut e = d * 1000 / 30601;
-    let f = d - e * 30 - e * 601 / 1000;
-    if e < 14 {
-        c -= 4716;
-        e -= 1;
+/// Pick the name an opt is referred to by, its long name if it has one
+fn primary_param(short: &str, long: &str) -> Option<Param> {
+    if long.is_empty() {
+        short.chars().next().map(Param::Short)
     } else {
-        c -= 4715;
-        e -= 13;
+        Some(Param::Long(long.to_owned()))
     }
-    (c, e, f, h, m, s)
 }
 
-pub fn format_time(ts: i64, tz_offset: i64) -> String {
-    let (c, e, f, h, m, s) = get_time_tuple(ts, tz_offset);
-    format!("{:>04}-{:>02}-{:>02} {:>02}:{:>02}:{:>02}", c, e, f, h, m, s)
+/// Separates a vendor prefix from the parameter name, as in `--plugin:verbose`
+const VENDOR_SEPARATOR: char = ':';
+
+/// Build the long name under which a vendor's parameter is registered
+fn vendor_name(vendor: &str, name: &str) -> String {
+    format!("{}{}{}", vendor, VENDOR_SEPARATOR, name)
 }
 
-pub fn to_human_readable_string(size: u64) -> String {
-    if size < 1024 {
-        return format!("{}", size);
+/// The `key=value` pairs collected by a keyval opt
+type KeyVals = Vec<(String, String)>;
+
+/// A check over the parsed parameters as a whole, see `ArgParser::add_post_validator`
+pub type Validator = fn(&ArgParser) -> Result<(), String>;
+
+/// A check over the value of a single opt, see `ArgParser::add_opt_validated`
+type CheckFn = dyn Fn(&str) -> Result<(), String>;
+
+/// A shared `CheckFn`, so that clones of a parser run the same checks
+#[derive(Clone)]
+struct ValueCheck(Rc<CheckFn>);
+
+impl std::fmt::Debug for ValueCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("ValueCheck")
     }
+}
+
+/// A callback given the count of a flag, see `ArgParser::on_flag`
+type FlagCallback = dyn FnMut(usize);
 
-    static UNITS: [&'static str; 7] = ["", "K", "M", "G", "T", "P", "E"];
+/// A callback given a value of an opt, see `ArgParser::on_opt`
+type OptCallback = dyn FnMut(&str);
 
-    let sizef = size as f64;
-    let digit_groups = (sizef.log10() / 1024f64.log10()) as i32;
-    format!("{:.1}{}",
-            sizef / 1024f64.powf(digit_groups as f64),
-            UNITS[digit_groups as usize])
+/// A callback run while parsing each time a parameter is given. Clones of a parser share it.
+#[derive(Clone)]
+enum Callback {
+    Flag(Rc<RefCell<FlagCallback>>),
+    Opt(Rc<RefCell<OptCallback>>),
 }
 
-#[cfg(test)]
-mod tests {
-    use super::ArgParser;
+impl std::fmt::Debug for Callback {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Callback")
+    }
+}
 
-    #[test]
-    fn stop_parsing() {
-        let args = vec![String::from("binname"), String::from("-a"), String::from("--"), String::from("-v")];
-        let mut parser = ArgParser::new(2);
-        parser = parser.add_flag(&["a"]).add_flag(&["v"]);
-        parser.parse(args.into_iter());
-        assert!(parser.found(&'a'));
-        assert!(!parser.found(&'v'));
-        assert!(parser.args[0] == "-v");
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// The Value for each parameter
+enum Value {
+    Flag(Rhs<Rc<RefCell<bool>>>),
+    /// The RHS String value is shared between both short and long parameters
+    Opt {
+        rhs: Rhs<Rc<RefCell<String>>>,
+        found: Rc<RefCell<bool>>,
+        /// The value taken when the opt ends the args without a value of its own
+        implicit: Option<String>,
+        /// Whether the value goes on over the following args, up to the next option
+        greedy: bool,
+        /// The value the opt was registered with
+        default: Option<String>,
+        /// Whether a leading `~/` in the value stands for the home directory
+        expand_home: bool,
+        /// Whether the opt also counts on its own, taking a value only when attached, as in `-d3`
+        hybrid: bool,
+    },
+    Setting {
+        rhs: Rhs<Rc<RefCell<String>>>,
+        found: Rc<RefCell<bool>>,
+        /// The value the setting was registered with
+        default: Option<String>,
+        /// Every value given, in order, for a setting that accumulates them
+        values: Option<Rc<RefCell<Vec<String>>>>,
+    },
+    /// Every occurrence holds a `key=value` pair, collected in order
+    KeyVal {
+        rhs: Rhs<Rc<RefCell<KeyVals>>>,
+        found: Rc<RefCell<bool>>
+    },
+    /// Every occurrence adds a value, collected in order
+    MultiOpt {
+        rhs: Rhs<Rc<RefCell<Vec<String>>>>,
+        /// Whether each occurrence is split on commas, adding every segment
+        csv: bool,
+    },
+    /// The `--no-<long>` form of a negatable opt, sharing the opt's value and found state
+    NegatedOpt {
+        value: Rc<RefCell<String>>,
+        found: Rc<RefCell<bool>>
+    },
+    /// The name under which the args after the `--` terminator can be retrieved
+    Trailing,
+}
+
+impl Value {
+    fn new_opt(value: Rc<RefCell<String>>, found: Rc<RefCell<bool>>) -> Self {
+        Value::Opt {
+            rhs: Rhs::new(value),
+            found,
+            implicit: None,
+            greedy: false,
+            default: None,
+            expand_home: false,
+            hybrid: false,
+        }
     }
 
-    #[test]
-    fn short_opts() {
-        let args = vec![String::from("binname"), String::from("-asdf"), String::from("-f"), String::from("foo")];
-        let mut parser = ArgParser::new(4);
-        parser = parser.add_flag(&["a"])
-            .add_flag(&["d"])
-            .add_opt("s", "")
-            .add_opt("f", "");
-        parser.parse(args.into_iter());
-        assert!(parser.found(&'a'));
-        assert!(!parser.found(&'d'));
-        assert!(parser.get_opt(&'s') == Some(String::from("df")));
-        assert!(parser.get_opt(&'f') == Some(String::from("foo")));
+    fn new_opt_implicit(value: Rc<RefCell<String>>, found: Rc<RefCell<bool>>, implicit: &str) -> Self {
+        Value::Opt {
+            rhs: Rhs::new(value),
+            found,
+            implicit: Some(implicit.to_owned()),
+            greedy: false,
+            default: None,
+            expand_home: false,
+            hybrid: false,
+        }
     }
 
-    #[test]
-    fn long_opts() {
+    fn new_opt_greedy(value: Rc<RefCell<String>>, found: Rc<RefCell<bool>>) -> Self {
+        Value::Opt {
+            rhs: Rhs::new(value),
+            found,
+            implicit: None,
+            greedy: true,
+            default: None,
+            expand_home: false,
+            hybrid: false,
+        }
+    }
+
+    fn new_setting(value: Rc<RefCell<String>>, found: Rc<RefCell<bool>>) -> Self {
+        Value::Setting {
+            rhs: Rhs::new(value),
+            found,
+            default: None,
+            values: None,
+        }
+    }
+
+    fn new_setting_multi(value: Rc<RefCell<String>>, found: Rc<RefCell<bool>>) -> Self {
+        Value::Setting {
+            rhs: Rhs::new(value),
+            found,
+            default: None,
+            values: Some(Rc::new(RefCell::new(Vec::new()))),
+        }
+    }
+
+    /// Record the value an opt or setting was registered with
+    fn with_default(mut self, value: &str) -> Self {
+        if let Value::Opt { ref mut default, .. } | Value::Setting { ref mut default, .. } = self {
+            *default = Some(value.to_owned());
+        }
+        self
+    }
+
+    /// Make an opt count when given on its own, only taking a value attached to it
+    fn with_hybrid(mut self) -> Self {
+        if let Value::Opt { ref mut hybrid, .. } = self {
+            *hybrid = true;
+        }
+        self
+    }
+
+    /// Make an opt's leading `~/` stand for the home directory
+    fn with_expand_home(mut self) -> Self {
+        if let Value::Opt { ref mut expand_home, .. } = self {
+            *expand_home = true;
+        }
+        self
+    }
+
+    /// Make a copy of this parameter with new storage holding the same contents
+    fn deep_copy(&self) -> Self {
+        fn cell<T: Clone>(cell: &Rc<RefCell<T>>) -> Rc<RefCell<T>> {
+            Rc::new(RefCell::new((**cell).borrow().clone()))
+        }
+        fn rhs<T: Clone>(rhs: &Rhs<Rc<RefCell<T>>>) -> Rhs<Rc<RefCell<T>>> {
+            Rhs { value: cell(&rhs.value), occurrences: rhs.occurrences }
+        }
+        match self {
+            Value::Flag(flag) => Value::Flag(rhs(flag)),
+            Value::Opt { rhs: opt, found, implicit, greedy, default, expand_home, hybrid } => Value::Opt {
+                rhs: rhs(opt),
+                found: cell(found),
+                implicit: implicit.clone(),
+                greedy: *greedy,
+                default: default.clone(),
+                expand_home: *expand_home,
+                hybrid: *hybrid,
+            },
+            Value::Setting { rhs: setting, found, default, values } => Value::Setting {
+                rhs: rhs(setting),
+                found: cell(found),
+                default: default.clone(),
+                values: values.as_ref().map(cell),
+            },
+            Value::KeyVal { rhs: pairs, found } => Value::KeyVal { rhs: rhs(pairs), found: cell(found) },
+            Value::MultiOpt { rhs: values, csv } => Value::MultiOpt {
+                rhs: rhs(values),
+                csv: *csv,
+            },
+            Value::NegatedOpt { .. } | Value::Trailing => self.clone(),
+        }
+    }
+
+    /// Take the storage of `copy`, an alias' copy, keeping this parameter's own occurrences
+    fn rebind(&self, copy: &Value) -> Self {
+        let mut value = copy.clone();
+        match (&mut value, self) {
+            (Value::Flag(new), Value::Flag(old)) => new.occurrences = old.occurrences,
+            (Value::Opt { rhs: new, .. }, Value::Opt { rhs: old, .. })
+            | (Value::Setting { rhs: new, .. }, Value::Setting { rhs: old, .. }) => new.occurrences = old.occurrences,
+            (Value::KeyVal { rhs: new, .. }, Value::KeyVal { rhs: old, .. }) => new.occurrences = old.occurrences,
+            (Value::MultiOpt { rhs: new, .. }, Value::MultiOpt { rhs: old, .. }) => new.occurrences = old.occurrences,
+            _ => (),
+        }
+        value
+    }
+
+    /// Make another name for this parameter, sharing its storage but with occurrences of its own
+    fn alias(&self) -> Self {
+        let mut value = self.clone();
+        match &mut value {
+            Value::Flag(rhs) => rhs.occurrences = 0,
+            Value::Opt { rhs, .. } | Value::Setting { rhs, .. } => rhs.occurrences = 0,
+            Value::KeyVal { rhs, .. } => rhs.occurrences = 0,
+            Value::MultiOpt { rhs, .. } => rhs.occurrences = 0,
+            Value::NegatedOpt { .. } | Value::Trailing => (),
+        }
+        value
+    }
+
+    /// Bring this parameter's storage back to its default, clearing its own occurrences
+    fn clear(&mut self) {
+        match self {
+            Value::Flag(rhs) => {
+                *(*rhs.value).borrow_mut() = false;
+                rhs.occurrences = 0;
+            }
+            Value::Opt { rhs, found, default, .. } => {
+                let mut value = (*rhs.value).borrow_mut();
+                value.clear();
+                value.push_str(default.as_deref().unwrap_or_default());
+                drop(value);
+                *(**found).borrow_mut() = false;
+                rhs.occurrences = 0;
+            }
+            Value::Setting { rhs, found, default, values } => {
+                let mut value = (*rhs.value).borrow_mut();
+                value.clear();
+                value.push_str(default.as_deref().unwrap_or_default());
+                drop(value);
+                *(**found).borrow_mut() = false;
+                if let Some(values) = values {
+                    (**values).borrow_mut().clear();
+                }
+                rhs.occurrences = 0;
+            }
+            Value::KeyVal { rhs, found } => {
+                (*rhs.value).borrow_mut().clear();
+                *(**found).borrow_mut() = false;
+                rhs.occurrences = 0;
+            }
+            Value::MultiOpt { rhs, .. } => {
+                (*rhs.value).borrow_mut().clear();
+                rhs.occurrences = 0;
+            }
+            Value::NegatedOpt { .. } | Value::Trailing => (),
+        }
+    }
+
+    /// Make a copy of this parameter's registration, with new storage holding no parsed state
+    fn respec(&self) -> Self {
+        match self {
+            Value::Flag(_) => Value::Flag(Rhs::new(Rc::new(RefCell::new(false)))),
+            Value::Opt { implicit, greedy, default, expand_home, hybrid, .. } => Value::Opt {
+                rhs: Rhs::new(Rc::new(RefCell::new(default.clone().unwrap_or_default()))),
+                found: Rc::new(RefCell::new(false)),
+                implicit: implicit.clone(),
+                greedy: *greedy,
+                default: default.clone(),
+                expand_home: *expand_home,
+                hybrid: *hybrid,
+            },
+            Value::Setting { default, values, .. } => Value::Setting {
+                rhs: Rhs::new(Rc::new(RefCell::new(default.clone().unwrap_or_default()))),
+                found: Rc::new(RefCell::new(false)),
+                default: default.clone(),
+                values: values.as_ref().map(|_| Rc::new(RefCell::new(Vec::new()))),
+            },
+            Value::KeyVal { .. } => Value::new_keyval(Rc::new(RefCell::new(Vec::new())), Rc::new(RefCell::new(false))),
+            Value::MultiOpt { csv, .. } => {
+                Value::new_multi(Rc::new(RefCell::new(Vec::new())), *csv)
+            }
+            Value::NegatedOpt { .. } | Value::Trailing => self.clone(),
+        }
+    }
+
+    /// Identify the storage shared by all the aliases of a parameter, if it has any of its own
+    fn storage(&self) -> Option<*const ()> {
+        match self {
+            Value::Flag(rhs) => Some(Rc::as_ptr(&rhs.value) as *const ()),
+            Value::Opt { rhs, .. } | Value::Setting { rhs, .. } => Some(Rc::as_ptr(&rhs.value) as *const ()),
+            Value::KeyVal { rhs, .. } => Some(Rc::as_ptr(&rhs.value) as *const ()),
+            Value::MultiOpt { rhs, .. } => Some(Rc::as_ptr(&rhs.value) as *const ()),
+            Value::NegatedOpt { .. } | Value::Trailing => None,
+        }
+    }
+
+    fn new_multi(values: Rc<RefCell<Vec<String>>>, csv: bool) -> Self {
+        Value::MultiOpt {
+            rhs: Rhs::new(values),
+            csv,
+        }
+    }
+
+    fn new_keyval(pairs: Rc<RefCell<KeyVals>>, found: Rc<RefCell<bool>>) -> Self {
+        Value::KeyVal {
+            rhs: Rhs::new(pairs),
+            found,
+        }
+    }
+}
+
+/// Split a `key=value` pair at its first `=`, returning `None` when there is no `=`
+fn split_keyval(pair: &str) -> Option<(String, String)> {
+    pair.split_once('=').map(|(key, value)| (key.to_owned(), value.to_owned()))
+}
+
+/// Cloning gives the new parser its own storage, so parsing with one leaves the other untouched.
+impl Clone for ArgParser {
+    fn clone(&self) -> Self {
+        ArgParser {
+            params: self.copy_params(Value::deep_copy, true),
+            order: self.order.clone(),
+            groups: self.groups.clone(),
+            required: self.required.clone(),
+            invalid: self.invalid.clone(),
+            invalid_operands: self.invalid_operands.clone(),
+            missing_values: self.missing_values.clone(),
+            garbage: self.garbage.clone(),
+            #[cfg(feature = "config")]
+            config_values: self.config_values.clone(),
+            #[cfg(feature = "config")]
+            from_config: self.from_config.clone(),
+            trailing: self.trailing.clone(),
+            skipped: self.skipped.clone(),
+            unknown: self.unknown.clone(),
+            warnings: self.warnings.clone(),
+            diagnostics: self.diagnostics.clone(),
+            events: self.events.clone(),
+            positions: self.positions.clone(),
+            total_input: self.total_input,
+            original: self.original.clone(),
+            os_args: self.os_args.clone(),
+            os_values: self.os_values.clone(),
+            commands: self.commands.clone(),
+            descriptions: self.descriptions.clone(),
+            delimiters: self.delimiters.clone(),
+            messages: self.messages.clone(),
+            env: self.env.clone(),
+            from_env: self.from_env.clone(),
+            config: self.config.clone(),
+            validators: self.validators.clone(),
+            exclusive: self.exclusive.clone(),
+            choices: self.choices.clone(),
+            help: self.help.clone(),
+            version: self.version.clone(),
+            metadata: self.metadata.clone(),
+            value_checks: self.value_checks.clone(),
+            arg_checks: self.arg_checks.clone(),
+            callbacks: self.callbacks.clone(),
+            validation_failures: self.validation_failures.clone(),
+            positionals: self.positionals.clone(),
+            negatable: self.negatable.clone(),
+            counted: self.counted.clone(),
+            deprecated: self.deprecated.clone(),
+            hidden: self.hidden.clone(),
+            unstable: self.unstable.clone(),
+            unstable_opt_in: self.unstable_opt_in.clone(),
+            requirements: self.requirements.clone(),
+            one_of: self.one_of.clone(),
+            subcommands: self.subcommands.clone(),
+            globals: self.globals.clone(),
+            #[cfg(feature = "prompt")]
+            prompts: self.prompts.clone(),
+            subcommand: self.subcommand,
+            args: self.args.clone(),
+        }
+    }
+}
+
+impl Default for ArgParser {
+    fn default() -> Self {
+        ArgParser::new(0)
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+/// What was parsed for the aliases of a parameter, in `ParsedArgs`
+struct Slot {
+    found: bool,
+    opt: Option<String>,
+    setting: Option<String>,
+    values: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+/// The results of a parse, detached from the parser by `to_parsed`
+///
+/// Unlike `ArgParser`, which shares the storage of aliases through `Rc<RefCell<..>>`, this is
+/// `Send` and `Sync`: every name points at the index of its parameter's slot, so it can be
+/// handed to another thread or kept in a static.
+pub struct ParsedArgs {
+    /// Every name of every parameter, with its index in `slots` and its own count
+    names: HashMap<Param, (usize, usize)>,
+    slots: Vec<Slot>,
+    /// The parameters found, as yielded by `ArgParser::iter_set`
+    set: Vec<(Param, ParsedValue)>,
+    pub args: Vec<String>,
+}
+
+impl ParsedArgs {
+    /// Look the slot of a parameter up by any of its names
+    fn slot<P: ParamName>(&self, name: P) -> Option<&Slot> {
+        self.names.named(name).map(|&(index, _)| &self.slots[index])
+    }
+
+    /// Check if a flag, opt or setting was found, like `ArgParser::found`
+    pub fn found<P: ParamName>(&self, name: P) -> bool {
+        self.slot(name).is_some_and(|slot| slot.found)
+    }
+
+    /// Get the number of times a parameter was given under this name, like `ArgParser::count`
+    pub fn count<P: ParamName>(&self, name: P) -> usize {
+        self.names.named(name).map_or(0, |&(_, count)| count)
+    }
+
+    /// Get the value of an opt, like `ArgParser::get_opt`
+    pub fn get_opt<P: ParamName>(&self, name: P) -> Option<&str> {
+        self.slot(name).and_then(|slot| slot.opt.as_deref())
+    }
+
+    /// Get the value of a setting, like `ArgParser::get_setting`
+    pub fn get_setting<P: ParamName>(&self, name: P) -> Option<&str> {
+        self.slot(name).and_then(|slot| slot.setting.as_deref())
+    }
+
+    /// Get every value of an opt, like `ArgParser::get_opt_all`. It is empty if none were found.
+    pub fn get_opt_all<P: ParamName>(&self, name: P) -> &[String] {
+        self.slot(name).map_or(&[], |slot| &slot.values)
+    }
+
+    /// Iterate over the parameters that were found, like `ArgParser::iter_set`: flags as
+    /// `ParsedValue::Flag`, opts and settings with their values, in the order they were
+    /// registered in. Together with `args` this is all there is to the results.
+    pub fn iter(&self) -> impl Iterator<Item = (&Param, &ParsedValue)> {
+        self.set.iter().map(|(param, value)| (param, value))
+    }
+}
+
+#[cfg(feature = "serde")]
+/// A flag as a bool, an opt or a setting as its value and a repeated opt as the list of its values
+impl serde::Serialize for ParsedValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ParsedValue::Flag(found) => serializer.serialize_bool(*found),
+            ParsedValue::Opt(value) | ParsedValue::Setting(value) => serializer.serialize_str(value),
+            ParsedValue::Multi(values) => values.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+/// A map of the parameters found, as given by `iter`, from their name to their value, followed by
+/// the positionals under `args`, like `{"verbose": true, "output": "out", "args": ["file"]}`
+impl serde::Serialize for ParsedArgs {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.set.len() + 1))?;
+        for (param, value) in self.set.iter() {
+            match param {
+                Param::Short(short) => map.serialize_entry(short, value)?,
+                Param::Long(long) => map.serialize_entry(long, value)?,
+            }
+        }
+        map.serialize_entry("args", &self.args)?;
+        map.end()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// What a command line asked for, as told by `parse_with_outcome`
+pub enum ParseOutcome {
+    /// To run the program, the args having been parsed
+    Parsed,
+    /// The help, with the flag registered with `set_help_flag`
+    Help,
+    /// The version, with the flag registered with `set_version_flag` or `version`
+    Version,
+}
+
+#[derive(Clone, Debug, Default)]
+/// The description of the program given to `name`, `version`, `author` and `about`
+struct Metadata {
+    name: Option<String>,
+    version: Option<String>,
+    author: Option<String>,
+    about: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+/// A command selected by the first positional, with a parser of its own for the args after it
+pub struct Subcommand {
+    name: String,
+    parser: ArgParser,
+}
+
+impl Subcommand {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the parser of the subcommand, holding what was parsed after its name
+    pub fn parser(&self) -> &ArgParser {
+        &self.parser
+    }
+}
+
+/// Our homebrewed Arg Parser
+///
+/// The aliases of a parameter share its storage through `Rc<RefCell<..>>`, so the parser is
+/// neither `Send` nor `Sync`. `to_parsed` copies the results into a `ParsedArgs`, which is.
+#[derive(Debug)]
+pub struct ArgParser {
+    params: HashMap<Param, Value>,
+    /// Every name of `params`, in registration order
+    order: Vec<Param>,
+    /// The sections registered with `group`, each with its parameters in registration order
+    groups: Vec<(String, Vec<Param>)>,
+    /// The opts and settings which must be given, in registration order
+    required: Vec<Param>,
+    invalid: Vec<Param>,
+    /// The names of the `name=value` args that aren't registered settings
+    invalid_operands: Vec<String>,
+    /// The opts that ended the args without being given a value
+    missing_values: Vec<Param>,
+    garbage: (RefCell<bool>, RefCell<String>),
+    /// The values read by `load_config`
+    #[cfg(feature = "config")]
+    config_values: Vec<(Param, String)>,
+    /// The parameters which got their value from `config_values`
+    #[cfg(feature = "config")]
+    from_config: Vec<Param>,
+    /// The args found after the `--` terminator, also appended to `args`
+    trailing: Vec<String>,
+    /// The args left unprocessed because parsing stopped early
+    skipped: Vec<String>,
+    /// The unknown parameters collected by `collect_unknown_matching`
+    unknown: Vec<String>,
+    warnings: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+    /// The parameters and positionals given, in command line order, with their positions
+    events: Vec<(usize, ParsedItem)>,
+    /// The position of each of `args` in the args given to `parse`
+    positions: Vec<usize>,
+    /// The number of args given to `parse`, without the program name
+    total_input: usize,
+    /// The args given to the last `parse`, kept if `retain_input` is enabled
+    original: Option<Vec<String>>,
+    /// The args given to `parse_os` which aren't valid UTF-8, by their position in the args
+    os_args: HashMap<usize, OsString>,
+    /// The original bytes of the opt and setting values taken from `os_args`
+    os_values: Vec<(Param, OsString)>,
+    /// The commands the first positional can select
+    commands: Vec<String>,
+    /// The descriptions shown by `usage`, under every alias of a parameter
+    descriptions: HashMap<Param, String>,
+    /// The delimiters set with `delimiter`, splitting the values given by `get_opt_list`
+    delimiters: HashMap<Param, char>,
+    /// The messages diagnostics, errors, usage and help are built from
+    messages: MessagesRef,
+    /// The parameters falling back on an environment variable, by one of their names
+    env: Vec<(Param, String)>,
+    /// The parameters whose value was taken from the environment
+    from_env: Vec<Param>,
+    /// The toggles set by the builder methods
+    config: ParserConfig,
+    /// The cross-parameter checks run by `run_validators`
+    validators: Vec<Validator>,
+    /// The groups of parameters checked by `check_exclusive`
+    exclusive: Vec<Vec<Param>>,
+    /// The values accepted by the opts registered with `add_opt_choices`
+    choices: Vec<(Param, Vec<String>)>,
+    /// The flag registered with `set_help_flag`
+    help: Option<Param>,
+    /// The flag registered with `set_version_flag` or `version`
+    version: Option<Param>,
+    /// The description of the program, shown by `help_text` and `version_text`
+    metadata: Metadata,
+    /// The checks of the opts registered with `add_opt_validated`
+    value_checks: Vec<(Param, ValueCheck)>,
+    /// The checks of the opts registered with `add_opt_existing_path` and its variants
+    arg_checks: Vec<(Param, ValueCheck)>,
+    /// The callbacks registered with `on_flag` and `on_opt`, in registration order
+    callbacks: Vec<(Param, Callback)>,
+    /// The opts whose value failed their check, with the error
+    validation_failures: Vec<(Param, String)>,
+    /// The positionals declared with `add_positional` and alike, in order
+    positionals: Vec<(String, Arity)>,
+    /// The long flags registered with `add_flag_negatable`, shown as `--[no-]<flag>` by `usage`
+    negatable: Vec<String>,
+    /// The names of the flags registered with `add_counted_flag`, shown as `-v...` by `usage`
+    counted: Vec<String>,
+    /// The names registered with `add_deprecated`, along with the name to use instead
+    deprecated: Vec<(Param, Param)>,
+    /// The parameters left out of `usage` and `definitions`, see `hidden`
+    hidden: Vec<Param>,
+    /// The parameters `validate` refuses unless they are opted in to, see `unstable`
+    unstable: Vec<Param>,
+    /// The flag and environment variable opting in to the unstable parameters
+    unstable_opt_in: Option<(String, String)>,
+    /// The parameters registered with `requires`, each with the one it needs
+    requirements: Vec<(Param, Param)>,
+    /// The groups registered with `one_of`
+    one_of: Vec<Vec<Param>>,
+    /// The subcommands registered with `add_subcommand`
+    subcommands: Vec<Subcommand>,
+    /// The names registered with `add_global_flag` and `add_global_opt`
+    globals: Vec<Param>,
+    /// The prompts registered with `prompt_if_missing`, and whether they hide the input
+    #[cfg(feature = "prompt")]
+    prompts: Vec<(Param, String, bool)>,
+    /// The index in `subcommands` of the one given on the command line
+    subcommand: Option<usize>,
+    pub args: Vec<String>,
+}
+
+impl ArgParser {
+    /// Create a new ArgParser object
+    ///
+    /// The capacity specifies the initial capacity of the number parameters.
+    /// Always good to set it at the number of flags and opts total.
+    pub fn new(capacity: usize) -> Self {
+        ArgParser {
+            params: HashMap::with_capacity(capacity),
+            order: Vec::with_capacity(capacity),
+            groups: Vec::new(),
+            required: Vec::new(),
+            invalid: Vec::new(),
+            invalid_operands: Vec::new(),
+            missing_values: Vec::new(),
+            garbage: (RefCell::new(false), RefCell::new(String::with_capacity(0))),
+            #[cfg(feature = "config")]
+            config_values: Vec::new(),
+            #[cfg(feature = "config")]
+            from_config: Vec::new(),
+            trailing: Vec::new(),
+            skipped: Vec::new(),
+            unknown: Vec::new(),
+            warnings: Vec::new(),
+            diagnostics: Vec::new(),
+            events: Vec::new(),
+            positions: Vec::new(),
+            total_input: 0,
+            original: None,
+            os_args: HashMap::new(),
+            os_values: Vec::new(),
+            commands: Vec::new(),
+            descriptions: HashMap::new(),
+            delimiters: HashMap::new(),
+            messages: MessagesRef(Rc::new(English)),
+            env: Vec::new(),
+            from_env: Vec::new(),
+            config: ParserConfig::default(),
+            validators: Vec::new(),
+            exclusive: Vec::new(),
+            choices: Vec::new(),
+            help: None,
+            version: None,
+            metadata: Metadata::default(),
+            value_checks: Vec::new(),
+            arg_checks: Vec::new(),
+            callbacks: Vec::new(),
+            validation_failures: Vec::new(),
+            positionals: Vec::new(),
+            negatable: Vec::new(),
+            counted: Vec::new(),
+            deprecated: Vec::new(),
+            hidden: Vec::new(),
+            unstable: Vec::new(),
+            unstable_opt_in: None,
+            requirements: Vec::new(),
+            one_of: Vec::new(),
+            subcommands: Vec::new(),
+            globals: Vec::new(),
+            #[cfg(feature = "prompt")]
+            prompts: Vec::new(),
+            subcommand: None,
+            args: Vec::new(),
+        }
+    }
+
+    /// Builder method for setting the characters that separate a long opt from its value
+    ///
+    /// By default only `=` is, as in `--opt=val`. With `value_separators(&['=', ':'])`,
+    /// `--opt:val` works too. The first separator that follows a registered name splits the
+    /// arg, so `--opt=a:b` gives `opt` the value `a:b`.
+    pub fn value_separators(mut self, separators: &[char]) -> Self {
+        self.config.value_separators = separators.to_vec();
+        self
+    }
+
+    /// Split a long arg (without its dashes) into a name and a value at a value separator
+    ///
+    /// An arg that is a registered name as a whole isn't split, and the first separator that
+    /// follows a registered name is preferred. Returns None when there is no separator.
+    fn split_long<'a>(&self, arg: &'a str) -> Option<(&'a str, &'a str)> {
+        if self.params.has_named(arg) {
+            return None;
+        }
+        let mut first = None;
+        for (i, ch) in arg.char_indices().filter(|&(_, ch)| self.config.value_separators.contains(&ch)) {
+            let (lhs, rhs) = (&arg[..i], &arg[i + ch.len_utf8()..]);
+            if self.params.has_named(lhs) {
+                return Some((lhs, rhs));
+            }
+            first = first.or(Some((lhs, rhs)));
+        }
+        first
+    }
+
+    /// Spell the name of a long arg, or of a setting, like the registered long name it matches
+    /// once normalized, as in `--COLOR=Always` for `--color=Always` when `case_insensitive_long`
+    /// is enabled, or `--log_level` for `--log-level` with `underscores_as_dashes`. There's
+    /// nothing to respell if the name is registered as it is, and when several registered names
+    /// match, the first one registered is used.
+    fn respell_long(&self, arg: &str) -> Option<String> {
+        if !self.config.case_insensitive_long && !self.config.underscores_as_dashes
+            || self.params.has_named(arg)
+            || self.split_long(arg).is_some_and(|(lhs, _)| self.params.has_named(lhs)) {
+            return None;
+        }
+        let ends = arg.char_indices().filter(|&(_, ch)| self.config.value_separators.contains(&ch)).map(|(i, _)| i);
+        for end in Some(arg.len()).into_iter().chain(ends) {
+            let (name, rest) = arg.split_at(end);
+            let name = self.normalize_long(name);
+            let long = self.order.iter().find_map(|param| match param {
+                Param::Long(long) if self.normalize_long(long) == name => Some(long),
+                _ => None,
+            });
+            if let Some(long) = long {
+                return Some(format!("{}{}", long, rest));
+            }
+        }
+        None
+    }
+
+    /// Normalize a long name as configured, for `respell_long`
+    fn normalize_long(&self, name: &str) -> String {
+        let name = if self.config.case_insensitive_long { name.to_lowercase() } else { name.to_owned() };
+        if self.config.underscores_as_dashes {
+            name.replace('_', "-")
+        } else {
+            name
+        }
+    }
+
+    /// Expand a long arg whose name is an abbreviation, like `--verb` for `--verbose`, into its
+    /// full name. There's nothing to expand if the name is registered, or if it is the prefix
+    /// of no parameter or of several different ones, making it ambiguous.
+    fn expand_abbreviation(&self, arg: &str) -> Option<String> {
+        if !self.config.abbreviations
+            || self.params.has_named(arg)
+            || self.split_long(arg).is_some_and(|(lhs, _)| self.params.has_named(lhs)) {
+            return None;
+        }
+        let (name, rest) = match arg.char_indices().find(|&(_, ch)| self.config.value_separators.contains(&ch)) {
+            Some((i, _)) => arg.split_at(i),
+            None => (arg, ""),
+        };
+        if name.is_empty() {
+            return None;
+        }
+        match self.abbreviated(name).as_slice() {
+            [long] => Some(format!("{}{}", long, rest)),
+            _ => None,
+        }
+    }
+
+    /// Get the long names `name` is a prefix of, one for each parameter, ordered. Aliases of the
+    /// same parameter don't make an abbreviation ambiguous.
+    fn abbreviated(&self, name: &str) -> Vec<&str> {
+        let mut candidates: Vec<(&str, Option<*const ()>)> = self.params.iter()
+            .filter_map(|(param, value)| match (param, value) {
+                (_, Value::Setting { .. }) => None,
+                (Param::Long(long), _) if long.starts_with(name) => Some((long.as_str(), value.storage())),
+                _ => None,
+            })
+            .collect();
+        candidates.sort();
+        let mut longs: Vec<&str> = Vec::new();
+        let mut storages = Vec::new();
+        for (long, storage) in candidates {
+            if storage.is_none() || !storages.contains(&storage) {
+                storages.push(storage);
+                longs.push(long);
+            }
+        }
+        longs
+    }
+
+    /// Builder method for adding both short and long flags
+    ///
+    /// Flags are just parameters that have no assigned values. They are used
+    /// for when certain features or options have been enabled for the application
+    ///
+    /// For example
+    ///
+    /// ```text
+    /// > ls -l --human-readable
+    ///   ^  ^  ^
+    ///   |  |  |
+    ///   |  |  `-- A long flag to enable human readable numbers.
+    ///   |  `-- A short flag to enable the long format.
+    ///   `-- The command to list files.
+    /// ```
+    ///
+    /// See `add_flag_negatable` for flags which can be turned back off with `--no-<long>`.
+    pub fn add_flag(mut self, flags: &[&str]) -> Self {
+        let value = Rc::new(RefCell::new(bool::default()));
+        for flag in flags.iter().map(|flag| flag.trim_start_matches('-')) {
+            if flag.chars().count() == 1 {
+                if let Some(short) = flag.chars().next() {
+                    self.register(Param::Short(short), Value::Flag(Rhs::new(value.clone())));
+                }
+            } else if !flag.is_empty() {
+                self.register(Param::Long(flag.to_owned()), Value::Flag(Rhs::new(value.clone())));
+            }
+        }
+        self
+    }
+
+    /// Builder method for adding flags which default to being found from an environment variable
+    ///
+    /// When none of the flags is given on the command line and `var` holds a true value (see
+    /// `get_setting_bool`), the flag is found anyway, as in `VERBOSE=1 prog`. Its `count` stays 0.
+    pub fn add_flag_env(mut self, flags: &[&str], var: &str) -> Self {
+        let first = flags.iter().map(|flag| flag.trim_start_matches('-')).find(|flag| !flag.is_empty());
+        if let Some(flag) = first {
+            let param = if flag.chars().count() == 1 {
+                Param::Short(flag.chars().next().unwrap_or_default())
+            } else {
+                Param::Long(flag.to_owned())
+            };
+            self.env.push((param, var.to_owned()));
+        }
+        self.add_flag(flags)
+    }
+
+    /// Builder method for adding flags along with a description shown by `usage`
+    pub fn add_flag_desc(mut self, flags: &[&str], help: &str) -> Self {
+        for flag in flags.iter().map(|flag| flag.trim_start_matches('-')).filter(|flag| !flag.is_empty()) {
+            let param = if flag.chars().count() == 1 {
+                Param::Short(flag.chars().next().unwrap_or_default())
+            } else {
+                Param::Long(flag.to_owned())
+            };
+            self.descriptions.insert(param, help.to_owned());
+        }
+        self.add_flag(flags)
+    }
+
+    /// Builder method for adding flags which can be turned back off with `--no-<flag>`, as in
+    /// `--color --no-color`, the last one winning
+    ///
+    /// Both forms count as an occurrence, and the flag is shown as `--[no-]color` by `usage`. Only
+    /// the long flags registered this way accept `--no-<flag>`.
+    pub fn add_flag_negatable(mut self, flags: &[&str]) -> Self {
+        let longs = flags.iter().map(|flag| flag.trim_start_matches('-')).filter(|flag| flag.chars().count() > 1);
+        self.negatable.extend(longs.map(String::from));
+        self.add_flag(flags)
+    }
+
+    /// Builder method for adding flags given several times to raise a level, as in `-vvv` or
+    /// `-v --verbose`, read back with `get_count`
+    ///
+    /// The flags are shown as `-v, --verbose...` by `usage`.
+    pub fn add_counted_flag(mut self, flags: &[&str]) -> Self {
+        let names = flags.iter().map(|flag| flag.trim_start_matches('-')).filter(|flag| !flag.is_empty());
+        self.counted.extend(names.map(String::from));
+        self.add_flag(flags)
+    }
+
+    /// Builder method for adding a long flag, even if its name is a single character
+    ///
+    /// `add_flag` registers one-character names as short flags; `add_long_flag("a")` instead
+    /// matches `--a`, independently of a short `-a`.
+    pub fn add_long_flag(mut self, flag: &str) -> Self {
+        let flag = flag.trim_start_matches('-');
+        if !flag.is_empty() {
+            let value = Rc::new(RefCell::new(bool::default()));
+            self.register(Param::Long(flag.to_owned()), Value::Flag(Rhs::new(value)));
+        }
+        self
+    }
+
+    /// Builder method for adding flags namespaced under a vendor prefix
+    ///
+    /// Every name is registered as a long flag within the vendor's group, so
+    /// `add_vendor_flag("plugin", &["verbose"])` matches `--plugin:verbose` but not `--verbose`,
+    /// nor the `verbose` flag of another vendor.
+    pub fn add_vendor_flag(mut self, vendor: &str, flags: &[&str]) -> Self {
+        let value = Rc::new(RefCell::new(bool::default()));
+        for flag in flags.iter().map(|flag| flag.trim_start_matches('-')).filter(|flag| !flag.is_empty()) {
+            self.register(Param::Long(vendor_name(vendor, flag)), Value::Flag(Rhs::new(value.clone())));
+        }
+        self
+    }
+
+    /// Builder method for adding another name to a registered parameter, e.g. `--loud` for
+    /// `add_flag(&["v", "verbose"])`, sharing its value with the other names
+    ///
+    /// Like in `add_flag`, one-character names are short and longer ones are long. Nothing is
+    /// added if `existing` isn't registered.
+    pub fn alias(mut self, existing: &str, new_alias: &str) -> Self {
+        let param = |name: &str| {
+            let name = name.trim_start_matches('-');
+            match name.chars().next() {
+                Some(short) if name.chars().count() == 1 => Some(Param::Short(short)),
+                Some(_) => Some(Param::Long(name.to_owned())),
+                None => None,
+            }
+        };
+        let value = param(existing).and_then(|existing| self.params.get(&existing)).map(Value::alias);
+        if let (Some(value), Some(new_alias)) = (value, param(new_alias)) {
+            self.register(new_alias, value);
+        }
+        self
+    }
+
+    /// Builder method for keeping an old name of a registered parameter working, e.g.
+    /// `add_deprecated("old-name", "new-name")`
+    ///
+    /// The old name is an `alias` of the new one, left out of `usage`, and each parse using it
+    /// records a warning pointing at the new name in `warnings`. Nothing is added if `new` isn't registered.
+    pub fn add_deprecated(self, old: &str, new: &str) -> Self {
+        let mut parser = self.alias(new, old);
+        if let (Some(old), Some(new)) = (param_named(old), param_named(new)) {
+            if parser.params.contains_key(&new) {
+                parser.deprecated.push((old, new));
+            }
+        }
+        parser
+    }
+
+    /// Builder method for leaving a registered parameter out of `usage`, `help_text`,
+    /// `generate_man`, `definitions` and `suggest`, e.g. for debugging flags. It is still parsed
+    /// as usual. Any of its aliases can be used.
+    pub fn hidden<P: ParamName>(mut self, name: P) -> Self {
+        let param = match name.param_ref() {
+            ParamRef::Short(ch) => Param::Short(ch),
+            ParamRef::Long(long) => Param::Long(long.to_owned()),
+        };
+        self.hidden.push(param);
+        self
+    }
+
+    /// Builder method for making a registered parameter `hidden` and refused by `validate`
+    /// unless the user opts in to unstable parameters, see `unstable_opt_in`
+    pub fn unstable<P: ParamName>(mut self, name: P) -> Self {
+        let param = match name.param_ref() {
+            ParamRef::Short(ch) => Param::Short(ch),
+            ParamRef::Long(long) => Param::Long(long.to_owned()),
+        };
+        self.unstable.push(param.clone());
+        self.hidden.push(param);
+        self
+    }
+
+    /// Builder method for letting the `unstable` parameters be used when the long flag `flag` is
+    /// given, as in `--unstable`, or when `var` holds a true value (see `get_setting_bool`). An
+    /// empty `flag` or `var` is left out. The flag is added if it isn't registered yet.
+    pub fn unstable_opt_in(mut self, flag: &str, var: &str) -> Self {
+        let flag = flag.trim_start_matches('-');
+        self.unstable_opt_in = Some((flag.to_owned(), var.to_owned()));
+        if !flag.is_empty() && !self.params.has_named(flag) {
+            self = self.add_long_flag(flag);
+        }
+        self
+    }
+
+    /// Check whether `value` belongs to a `hidden` parameter, under any of its names
+    fn is_hidden(&self, value: &Value) -> bool {
+        self.hidden.iter().any(|param| self.params.get(param).and_then(Value::storage) == value.storage())
+    }
+
+    /// Builder method for giving a registered parameter the description shown by `usage`, e.g.
+    /// `describe('v', "Print more details")`. Any of its aliases can be used.
+    pub fn describe<P: ParamName>(mut self, name: P, help: &str) -> Self {
+        let param = match name.param_ref() {
+            ParamRef::Short(ch) => Param::Short(ch),
+            ParamRef::Long(long) => Param::Long(long.to_owned()),
+        };
+        self.descriptions.insert(param, help.to_owned());
+        self
+    }
+
+    /// Builder method for splitting the values of a registered opt on `delimiter` in
+    /// `get_opt_list`, instead of on commas, e.g. `delimiter("path", ':')`. Any of its aliases can
+    /// be used.
+    pub fn delimiter<P: ParamName>(mut self, name: P, delimiter: char) -> Self {
+        let param = match name.param_ref() {
+            ParamRef::Short(ch) => Param::Short(ch),
+            ParamRef::Long(long) => Param::Long(long.to_owned()),
+        };
+        self.delimiters.insert(param, delimiter);
+        self
+    }
+
+    /// Builder method for calling `callback` while parsing, each time an arg gives the flag `name`,
+    /// with the number of times it has been given so far under any of its names
+    ///
+    /// Callbacks run in command line order, across parameters, so order-sensitive options can be
+    /// followed as they come. Clones of the parser share the callback.
+    pub fn on_flag<P: ParamName, F: FnMut(usize) + 'static>(mut self, name: P, callback: F) -> Self {
+        let param = match name.param_ref() {
+            ParamRef::Short(ch) => Param::Short(ch),
+            ParamRef::Long(long) => Param::Long(long.to_owned()),
+        };
+        self.callbacks.push((param, Callback::Flag(Rc::new(RefCell::new(callback)))));
+        self
+    }
+
+    /// Builder method for calling `callback` while parsing with each value given to the opt or
+    /// setting `name`, as in `on_opt("include", |dir| ...)`. See `on_flag`.
+    ///
+    /// A multi-valued opt calls it once per value, after splitting `add_opt_multi_csv` values.
+    pub fn on_opt<P: ParamName, F: FnMut(&str) + 'static>(mut self, name: P, callback: F) -> Self {
+        let param = match name.param_ref() {
+            ParamRef::Short(ch) => Param::Short(ch),
+            ParamRef::Long(long) => Param::Long(long.to_owned()),
+        };
+        self.callbacks.push((param, Callback::Opt(Rc::new(RefCell::new(callback)))));
+        self
+    }
+
+    /// Builder method for building the diagnostics, `found_invalid` errors, `usage` and
+    /// `help_text` from other `Messages` than the English ones, e.g. a translation
+    pub fn messages<M: Messages + 'static>(mut self, messages: M) -> Self {
+        self.messages = MessagesRef(Rc::new(messages));
+        self
+    }
+
+    /// Builder method for adding the flag asking for help, like `-h` and `--help`. Once parsed,
+    /// `help_requested` tells whether it was given, and `help_text` renders the help.
+    pub fn set_help_flag(mut self, short: &str, long: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        self.help = primary_param(short, long);
+        let flags: Vec<&str> = [short, long].iter().cloned().filter(|flag| !flag.is_empty()).collect();
+        self.add_flag_desc(&flags, "Print this help")
+    }
+
+    /// Builder method for adding the flag asking for the version, like `-V` and `--version`.
+    /// `parse_or_exit` prints `version`, e.g. `prog 1.2.0`, when it is given, after the `name`
+    /// if there is one.
+    pub fn set_version_flag(mut self, short: &str, long: &str, version: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        self.version = primary_param(short, long);
+        self.metadata.version = Some(version.to_owned());
+        let flags: Vec<&str> = [short, long].iter().cloned().filter(|flag| !flag.is_empty()).collect();
+        self.add_flag_desc(&flags, "Print the version")
+    }
+
+    /// Builder method for naming the program, as shown by `help`, `help_text` and `version_text`,
+    /// and by `parse_or_exit` instead of the name it was run as
+    pub fn name(mut self, name: &str) -> Self {
+        self.metadata.name = Some(name.to_owned());
+        self
+    }
+
+    /// Builder method for the version of the program, e.g. `version(env!("CARGO_PKG_VERSION"))`
+    ///
+    /// Unless `set_version_flag` registered another one, this adds the `-V` and `--version`
+    /// flags asking for it, so `parse_or_exit` prints `version_text`.
+    pub fn version(mut self, version: &str) -> Self {
+        if self.version.is_some() {
+            self.metadata.version = Some(version.to_owned());
+            self
+        } else {
+            self.set_version_flag("V", "version", version)
+        }
+    }
+
+    /// Builder method for the author of the program, shown by `help_text`
+    pub fn author(mut self, author: &str) -> Self {
+        self.metadata.author = Some(author.to_owned());
+        self
+    }
+
+    /// Builder method for what the program does in a sentence, shown by `help_text`
+    pub fn about(mut self, about: &str) -> Self {
+        self.metadata.about = Some(about.to_owned());
+        self
+    }
+
+    /// Builder method for the status `parse_or_exit` exits with when the command line is wrong,
+    /// 2 by default as for most Unix tools
+    pub fn usage_exit_code(mut self, code: i32) -> Self {
+        self.config.usage_exit_code = code;
+        self
+    }
+
+    /// Builder method for registering the commands the first positional can select, as in
+    /// `prog build|run|test ...`. See `command_name` and `unknown_command`.
+    pub fn commands(mut self, commands: &[&str]) -> Self {
+        self.commands.extend(commands.iter().map(|command| (*command).to_owned()));
+        self
+    }
+
+    /// Builder method for declaring the next positional, which must be given, so it can be
+    /// retrieved with `get_positional`. Positionals take the args left in declaration order.
+    pub fn add_positional(mut self, name: &str) -> Self {
+        self.positionals.push((name.to_owned(), Arity::One));
+        self
+    }
+
+    /// Builder method for declaring the next positional, which can be left out
+    pub fn add_positional_optional(mut self, name: &str) -> Self {
+        self.positionals.push((name.to_owned(), Arity::Optional));
+        self
+    }
+
+    /// Builder method for declaring a last positional taking all the args left, like the files
+    /// of `cat`. See `get_positional_all`.
+    pub fn add_positional_multi(mut self, name: &str) -> Self {
+        self.positionals.push((name.to_owned(), Arity::Variadic));
+        self
+    }
+
+    /// Builder method for adding a git-style subcommand, as in `prog fetch --all`
+    ///
+    /// When the first positional is `name`, the args after it are parsed by `parser` instead,
+    /// the parent keeping the parameters given before it. See `which_subcommand` and
+    /// `subcommand_matches`.
+    pub fn add_subcommand(mut self, name: &str, parser: ArgParser) -> Self {
+        self.subcommands.push(Subcommand { name: name.to_owned(), parser });
+        self
+    }
+
+    /// Builder method for adding flags accepted before as well as after the name of any
+    /// subcommand, like `add_flag`
+    ///
+    /// Wherever they are given, they are found on this parser, as in `prog -v fetch -v` where
+    /// `count('v')` is 2, and on the subcommand's parser as well. Subcommands of
+    /// subcommands accept them too.
+    pub fn add_global_flag(mut self, flags: &[&str]) -> Self {
+        let start = self.order.len();
+        self = self.add_flag(flags);
+        let names = self.order[start..].to_vec();
+        self.globals.extend(names);
+        self
+    }
+
+    /// Builder method for adding opts accepted before as well as after the name of any
+    /// subcommand, like `add_opt`. See `add_global_flag`.
+    pub fn add_global_opt(mut self, short: &str, long: &str) -> Self {
+        let start = self.order.len();
+        self = self.add_opt(short, long);
+        let names = self.order[start..].to_vec();
+        self.globals.extend(names);
+        self
+    }
+
+    /// Builder method for adding both short and long opts
+    ///
+    /// Opts are parameters that hold assigned values. They are used
+    /// for when certain features or options have been enabled for the application
+    ///
+    /// For example
+    ///
+    /// ```text
+    /// > ls -T 4 --color=always
+    ///   ^  ^    ^
+    ///   |  |    |
+    ///   |  |    `-- A long opt to enable the use of color with value `always`.
+    ///   |  `-- A short opt to set tab size to the value `4`.
+    ///   `-- The command to list files.
+    /// ```
+    pub fn add_opt(mut self, short: &str, long: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        let value = Rc::new(RefCell::new("".to_owned()));
+        let found = Rc::new(RefCell::new(false));
+        if let Some(short) = short.chars().next() {
+            self.register(Param::Short(short), Value::new_opt(value.clone(), found.clone()));
+        }
+        if !long.is_empty() {
+            self.register(Param::Long(long.to_owned()), Value::new_opt(value, found));
+        }
+        self
+    }
+
+    pub fn add_opt_default(mut self, short: &str, long: &str, default: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        let value = Rc::new(RefCell::new(default.to_owned()));
+        let found = Rc::new(RefCell::new(false));
+        if let Some(short) = short.chars().next() {
+            self.register(Param::Short(short), Value::new_opt(value.clone(), found.clone()).with_default(default));
+        }
+        if !long.is_empty() {
+            self.register(Param::Long(long.to_owned()), Value::new_opt(value, found).with_default(default));
+        }
+        self
+    }
+
+    /// Builder method for adding both short and long opts whose value is optional
+    ///
+    /// When the opt is the last arg and has no value of its own, as in `prog --color` or
+    /// `prog -c`, it takes the `implicit` value instead of being reported by `missing_values`.
+    /// The long opt only takes a value through `=`, so `prog --color file` leaves `file` as a
+    /// positional.
+    pub fn add_opt_implicit(mut self, short: &str, long: &str, implicit: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        let value = Rc::new(RefCell::new("".to_owned()));
+        let found = Rc::new(RefCell::new(false));
+        if let Some(short) = short.chars().next() {
+            self.register(Param::Short(short), Value::new_opt_implicit(value.clone(), found.clone(), implicit));
+        }
+        if !long.is_empty() {
+            self.register(Param::Long(long.to_owned()), Value::new_opt_implicit(value, found, implicit));
+        }
+        self
+    }
+
+    /// Builder method for adding both short and long opts whose value spans several args
+    ///
+    /// The value is made of every following arg up to the next one that looks like an option
+    /// (or `--`), joined by single spaces: `--message a b c --verbose` sets `message` to `a b c`
+    /// and still parses `--verbose`.
+    pub fn add_opt_greedy_words(mut self, short: &str, long: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        let value = Rc::new(RefCell::new("".to_owned()));
+        let found = Rc::new(RefCell::new(false));
+        if let Some(short) = short.chars().next() {
+            self.register(Param::Short(short), Value::new_opt_greedy(value.clone(), found.clone()));
+        }
+        if !long.is_empty() {
+            self.register(Param::Long(long.to_owned()), Value::new_opt_greedy(value, found));
+        }
+        self
+    }
+
+    /// Builder method for adding both short and long opts along with a description shown by
+    /// `usage`
+    pub fn add_opt_desc(mut self, short: &str, long: &str, help: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        if let Some(short) = short.chars().next() {
+            self.descriptions.insert(Param::Short(short), help.to_owned());
+        }
+        if !long.is_empty() {
+            self.descriptions.insert(Param::Long(long.to_owned()), help.to_owned());
+        }
+        self.add_opt(short, long)
+    }
+
+    /// Builder method for adding both short and long opts which default to the value of an
+    /// environment variable
+    ///
+    /// When the opt isn't given on the command line and `var` is set, the opt is found with the
+    /// variable's value, as in `OUTPUT=out.txt prog`. Its `count` stays 0.
+    pub fn add_opt_env(mut self, short: &str, long: &str, var: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        if let Some(param) = primary_param(short, long) {
+            self.env.push((param, var.to_owned()));
+        }
+        self.add_opt(short, long)
+    }
+
+    /// Builder method for adding both short and long opts which only accept some values, like
+    /// `--color` taking `always`, `never` or `auto`
+    ///
+    /// Any other value is still stored, and the opt found, but it's reported by `invalid_values`.
+    pub fn add_opt_choices(mut self, short: &str, long: &str, choices: &[&str]) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        if let Some(param) = primary_param(short, long) {
+            self.choices.push((param, choices.iter().map(|choice| (*choice).to_owned()).collect()));
+        }
+        self.add_opt(short, long)
+    }
+
+    /// Builder method for adding both short and long opts which also count on their own, like
+    /// a debug level raised by `-dd` or set by `-d3`
+    ///
+    /// Given bare, as in `-d` or `--debug`, the opt takes no value, and isn't followed by its
+    /// value: `count` tells how many times it was given. A value is taken only when attached, as
+    /// in `-d3`, `-d=3` or `--debug=3`; `get_opt` returns the last one.
+    pub fn add_hybrid(mut self, short: &str, long: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        let value = Rc::new(RefCell::new("".to_owned()));
+        let found = Rc::new(RefCell::new(false));
+        if let Some(short) = short.chars().next() {
+            self.register(Param::Short(short), Value::new_opt(value.clone(), found.clone()).with_hybrid());
+        }
+        if !long.is_empty() {
+            self.register(Param::Long(long.to_owned()), Value::new_opt(value, found).with_hybrid());
+        }
+        self
+    }
+
+    /// Builder method for adding both short and long opts whose value must pass a check, like a
+    /// port being between 1 and 65535
+    ///
+    /// The check runs at the end of every parse over the opt's value, if it was found; the
+    /// errors are kept in `validation_failures`. Clones of the parser share the same check.
+    pub fn add_opt_validated<F>(mut self, short: &str, long: &str, check: F) -> Self
+        where F: Fn(&str) -> Result<(), String> + 'static
+    {
+        let (short, long) = strip_dashes(short, long);
+        if let Some(param) = primary_param(short, long) {
+            self.value_checks.push((param, ValueCheck(Rc::new(check))));
+        }
+        self.add_opt(short, long)
+    }
+
+    /// Builder method for adding both short and long opts whose value must satisfy `accept`, e.g.
+    /// `add_opt_with("m", "mode", |mode| mode.len() <= 8)`. See `add_opt_validated`, which can
+    /// tell why a value is refused.
+    pub fn add_opt_with<F: Fn(&str) -> bool + 'static>(self, short: &str, long: &str, accept: F) -> Self {
+        self.add_opt_validated(short, long, move |value| {
+            if accept(value) { Ok(()) } else { Err(format!("'{}' isn't accepted", value)) }
+        })
+    }
+
+    /// Builder method for adding both short and long opts whose every value must pass `check`
+    /// as it's given, a failure making the value invalid like one an opt can't hold
+    fn add_opt_checked<F>(mut self, short: &str, long: &str, check: F) -> Self
+        where F: Fn(&str) -> Result<(), String> + 'static
+    {
+        let (short, long) = strip_dashes(short, long);
+        if let Some(param) = primary_param(short, long) {
+            self.arg_checks.push((param, ValueCheck(Rc::new(check))));
+        }
+        self.add_opt(short, long)
+    }
+
+    /// Builder method for adding both short and long opts naming a path that must exist, be it a
+    /// file or a directory
+    ///
+    /// The path is checked as it's given; one that doesn't exist is an invalid value, reported
+    /// by `found_invalid` and in `diagnostics`.
+    pub fn add_opt_existing_path(self, short: &str, long: &str) -> Self {
+        self.add_opt_checked(short, long, |value| match fs::metadata(value) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(format!("'{}' does not exist", value)),
+        })
+    }
+
+    /// Builder method for adding both short and long opts naming an existing file. See
+    /// `add_opt_existing_path`.
+    pub fn add_opt_existing_file(self, short: &str, long: &str) -> Self {
+        self.add_opt_checked(short, long, |value| match fs::metadata(value) {
+            Ok(ref metadata) if metadata.is_file() => Ok(()),
+            Ok(_) => Err(format!("'{}' is not a file", value)),
+            Err(_) => Err(format!("'{}' does not exist", value)),
+        })
+    }
+
+    /// Builder method for adding both short and long opts naming an existing directory. See
+    /// `add_opt_existing_path`.
+    pub fn add_opt_existing_dir(self, short: &str, long: &str) -> Self {
+        self.add_opt_checked(short, long, |value| match fs::metadata(value) {
+            Ok(ref metadata) if metadata.is_dir() => Ok(()),
+            Ok(_) => Err(format!("'{}' is not a directory", value)),
+            Err(_) => Err(format!("'{}' does not exist", value)),
+        })
+    }
+
+    /// Builder method for adding both short and long opts holding a path, where a leading `~/`
+    /// is expanded to the home directory from `$HOME`, or the one set with `home_dir`
+    ///
+    /// The value is left unchanged if there's no home directory.
+    pub fn add_opt_expanduser(mut self, short: &str, long: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        let value = Rc::new(RefCell::new("".to_owned()));
+        let found = Rc::new(RefCell::new(false));
+        if let Some(short) = short.chars().next() {
+            self.register(Param::Short(short), Value::new_opt(value.clone(), found.clone()).with_expand_home());
+        }
+        if !long.is_empty() {
+            self.register(Param::Long(long.to_owned()), Value::new_opt(value, found).with_expand_home());
+        }
+        self
+    }
+
+    /// Builder method for the home directory the opts of `add_opt_expanduser` expand `~/` to,
+    /// rather than `$HOME`
+    pub fn home_dir(mut self, dir: &str) -> Self {
+        self.config.home_dir = Some(String::from(dir));
+        self
+    }
+
+    /// Builder method for adding both short and long opts which must be given
+    ///
+    /// See `missing_required` to find out which required opts and settings are still missing.
+    pub fn add_opt_required(mut self, short: &str, long: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        if !long.is_empty() {
+            self.required.push(Param::Long(long.to_owned()));
+        } else if let Some(short) = short.chars().next() {
+            self.required.push(Param::Short(short));
+        }
+        self.add_opt(short, long)
+    }
+
+    /// Builder method for making an already registered opt or setting required, e.g.
+    /// `.add_opt("u", "user").required("user")`. See `missing_required` and `validate`.
+    pub fn required<P: ParamName>(mut self, name: P) -> Self {
+        let param = match name.param_ref() {
+            ParamRef::Short(ch) => Param::Short(ch),
+            ParamRef::Long(long) => Param::Long(long.to_owned()),
+        };
+        if !self.required.contains(&param) {
+            self.required.push(param);
+        }
+        self
+    }
+
+    /// Builder method for making an already registered flag, opt or setting fall back on the
+    /// environment variable `var` when it isn't given, like `add_opt_env` does. The variable
+    /// wins over the default, e.g. with `.add_opt_default("p", "port", "80").env("port", "PORT")`.
+    pub fn env<P: ParamName>(mut self, name: P, var: &str) -> Self {
+        let param = match name.param_ref() {
+            ParamRef::Short(ch) => Param::Short(ch),
+            ParamRef::Long(long) => Param::Long(long.to_owned()),
+        };
+        self.env.push((param, var.to_owned()));
+        self
+    }
+
+    /// Builder method for allowing at most `n` positionals, `validate` reporting the ones after
+    pub fn max_positionals(mut self, n: usize) -> Self {
+        self.config.max_positionals = Some(n);
+        self
+    }
+
+    /// Builder method for adding both short and long opts which can be negated
+    ///
+    /// Besides the usual forms, `--no-<long>` clears the opt's value and marks it as not found,
+    /// so a later `--no-output` cancels an earlier `--output=a`.
+    pub fn add_opt_negatable(mut self, short: &str, long: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        let value = Rc::new(RefCell::new("".to_owned()));
+        let found = Rc::new(RefCell::new(false));
+        if let Some(short) = short.chars().next() {
+            self.register(Param::Short(short), Value::new_opt(value.clone(), found.clone()));
+        }
+        if !long.is_empty() {
+            self.register(Param::Long(long.to_owned()), Value::new_opt(value.clone(), found.clone()));
+            self.register(Param::Long(format!("no-{}", long)), Value::NegatedOpt { value, found });
+        }
+        self
+    }
+
+    /// Builder method for adding both short and long opts holding `key=value` pairs
+    ///
+    /// The opt can be repeated, each occurrence adding one pair, e.g.
+    /// `--label a=1 --label b=2` or `-l a=1`. A value without `=` is recorded as invalid.
+    pub fn add_opt_keyval(mut self, short: &str, long: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        let pairs = Rc::new(RefCell::new(Vec::new()));
+        let found = Rc::new(RefCell::new(false));
+        if let Some(short) = short.chars().next() {
+            self.register(Param::Short(short), Value::new_keyval(pairs.clone(), found.clone()));
+        }
+        if !long.is_empty() {
+            self.register(Param::Long(long.to_owned()), Value::new_keyval(pairs, found));
+        }
+        self
+    }
+
+    /// Builder method for adding both short and long opts which can be given several times
+    ///
+    /// Every occurrence adds a value instead of replacing the previous one, whether given as
+    /// `-Ifoo`, `-I foo`, `--include=foo` or `--include foo`. See `get_opt_all`.
+    pub fn add_opt_multi(mut self, short: &str, long: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        let values = Rc::new(RefCell::new(Vec::new()));
+        if let Some(short) = short.chars().next() {
+            self.register(Param::Short(short), Value::new_multi(values.clone(), false));
+        }
+        if !long.is_empty() {
+            self.register(Param::Long(long.to_owned()), Value::new_multi(values, false));
+        }
+        self
+    }
+
+    /// Builder method for adding both short and long opts collecting comma-separated values
+    ///
+    /// Like `add_opt_multi`, but each occurrence is split on commas, so `--tag a,b --tag c`
+    /// collects `a`, `b` and `c`. Empty segments are left out.
+    pub fn add_opt_multi_csv(mut self, short: &str, long: &str) -> Self {
+        let (short, long) = strip_dashes(short, long);
+        let values = Rc::new(RefCell::new(Vec::new()));
+        if let Some(short) = short.chars().next() {
+            self.register(Param::Short(short), Value::new_multi(values.clone(), true));
+        }
+        if !long.is_empty() {
+            self.register(Param::Long(long.to_owned()), Value::new_multi(values, true));
+        }
+        self
+    }
+
+    /// Builder method for adding settings
+    ///
+    /// Settings are parameters that hold assigned values. They are used
+    /// in some applications such as dd
+    ///
+    /// For example
+    ///
+    /// ```text
+    /// > dd if=/path/file
+    ///   ^  ^
+    ///   |  |
+    ///   |  |
+    ///   |  `-- The setting set to /path/file
+    ///   `-- The command to list files.
+    /// ```
+    pub fn add_setting(mut self, setting: &str) -> Self {
+        let value = Rc::new(RefCell::new("".to_owned()));
+        let found = Rc::new(RefCell::new(false));
+        if !setting.is_empty() {
+            self.register(Param::Long(setting.to_owned()), Value::new_setting(value, found));
+        }
+        self
+    }
+
+    /// Builder method for adding settings whose value must be of a `SettingType`, like `count=3`
+    /// for `dd`. Any other value is still stored, but reported in `validation_failures`.
+    pub fn add_setting_typed(mut self, setting: &str, kind: SettingType) -> Self {
+        if !setting.is_empty() {
+            let check = move |value: &str| {
+                let (valid, expected) = match kind {
+                    SettingType::U64 => (value.parse::<u64>().is_ok(), "an unsigned integer"),
+                    SettingType::I64 => (value.parse::<i64>().is_ok(), "an integer"),
+                    SettingType::Bool => (parse_bool(value).is_some(), "a boolean"),
+                    SettingType::Bytes => (parse_human_readable(value).is_some(), "a size"),
+                };
+                if valid { Ok(()) } else { Err(format!("'{}' isn't {}", value, expected)) }
+            };
+            self.value_checks.push((Param::Long(setting.to_owned()), ValueCheck(Rc::new(check))));
+        }
+        self.add_setting(setting)
+    }
+
+    /// Builder method for adding settings which only accept some values, like `conv=notrunc`.
+    /// Any other value is still stored, but reported by `invalid_values`.
+    pub fn add_setting_choices(mut self, setting: &str, choices: &[&str]) -> Self {
+        if !setting.is_empty() {
+            let choices = choices.iter().map(|choice| (*choice).to_owned()).collect();
+            self.choices.push((Param::Long(setting.to_owned()), choices));
+        }
+        self.add_setting(setting)
+    }
+
+    /// Builder method for adding settings which default to the value of an environment variable,
+    /// like `add_opt_env`
+    pub fn add_setting_env(mut self, setting: &str, var: &str) -> Self {
+        if !setting.is_empty() {
+            self.env.push((Param::Long(setting.to_owned()), var.to_owned()));
+        }
+        self.add_setting(setting)
+    }
+
+    /// Builder method for adding settings which must be given
+    pub fn add_setting_required(mut self, setting: &str) -> Self {
+        if !setting.is_empty() {
+            self.required.push(Param::Long(setting.to_owned()));
+        }
+        self.add_setting(setting)
+    }
+
+    /// Builder method for adding settings along with a description shown by `usage`
+    pub fn add_setting_desc(mut self, setting: &str, help: &str) -> Self {
+        if !setting.is_empty() {
+            self.descriptions.insert(Param::Long(setting.to_owned()), help.to_owned());
+        }
+        self.add_setting(setting)
+    }
+
+    /// Register a setting that may be given several times, e.g. `env=A=1 env=B=2`, collecting
+    /// every value. Only the first `=` splits the name from the value.
+    pub fn add_setting_multi(mut self, setting: &str) -> Self {
+        let value = Rc::new(RefCell::new(String::new()));
+        let found = Rc::new(RefCell::new(false));
+        if !setting.is_empty() {
+            self.register(Param::Long(setting.to_owned()), Value::new_setting_multi(value, found));
+        }
+        self
+    }
+
+    pub fn add_setting_default(mut self, setting: &str, default: &str) -> Self {
+        let value = Rc::new(RefCell::new(default.to_owned()));
+        let found = Rc::new(RefCell::new(false));
+        if !setting.is_empty() {
+            self.register(Param::Long(setting.to_owned()), Value::new_setting(value, found).with_default(default));
+        }
+        self
+    }
+
+    /// Builder method for registering a check run by `run_validators` once parsing is done.
+    ///
+    /// This covers rules spanning several parameters, e.g. requiring `--key` when
+    /// `--mode=advanced` is given.
+    pub fn add_post_validator(mut self, validator: Validator) -> Self {
+        self.validators.push(validator);
+        self
+    }
+
+    /// Builder method for registering parameters that can't be given together, like `--json`
+    /// and `--yaml`. Flags and opts can be named by their short or long name; see
+    /// `check_exclusive`.
+    pub fn add_exclusive_group(mut self, members: &[&str]) -> Self {
+        let group = members.iter().filter_map(|member| param_named(member)).collect();
+        self.exclusive.push(group);
+        self
+    }
+
+    /// Builder method for registering two parameters that can't be given together, like
+    /// `conflicts("quiet", "verbose")`. See `add_exclusive_group`.
+    pub fn conflicts(self, first: &str, second: &str) -> Self {
+        self.add_exclusive_group(&[first, second])
+    }
+
+    /// Builder method for registering a parameter which can only be given along with another,
+    /// like `requires("output", "format")`. Checked by `validate`.
+    pub fn requires(mut self, param: &str, required: &str) -> Self {
+        if let (Some(param), Some(required)) = (param_named(param), param_named(required)) {
+            self.requirements.push((param, required));
+        }
+        self
+    }
+
+    /// Builder method for registering parameters of which exactly one must be given, like
+    /// `one_of(&["json", "yaml", "toml"])`. Checked by `validate`.
+    pub fn one_of(mut self, members: &[&str]) -> Self {
+        self.one_of.push(members.iter().filter_map(|member| param_named(member)).collect());
+        self.add_exclusive_group(members)
+    }
+
+    /// Describe how the parameters found break the constraints of `add_exclusive_group`,
+    /// `conflicts`, `requires` and `one_of`, one line per violation
+    fn constraint_violations(&self) -> Vec<String> {
+        let mut violations: Vec<String> = self.check_exclusive().err().into_iter()
+            .flat_map(|errors| errors.lines().map(String::from).collect::<Vec<String>>())
+            .collect();
+        let opted_in = match self.unstable_opt_in {
+            Some((ref flag, ref var)) => {
+                !flag.is_empty() && self.found(flag.as_str())
+                    || env::var(var).ok().and_then(|value| parse_bool(&value)).unwrap_or(false)
+            }
+            None => false,
+        };
+        let (flag, var) = match self.unstable_opt_in {
+            Some((ref flag, ref var)) => (Some(flag.as_str()), Some(var.as_str())),
+            None => (None, None),
+        };
+        let (flag, var) = (flag.filter(|flag| !flag.is_empty()), var.filter(|var| !var.is_empty()));
+        let messages = &self.messages.0;
+        violations.extend(self.unstable.iter()
+            .filter(|param| !opted_in && self.get_count(*param) > 0)
+            .map(|param| messages.unstable(&self.display_name(param), flag, var)));
+        violations.extend(self.requirements.iter()
+            .filter(|(param, required)| self.found(param) && !self.found(required))
+            .map(|(param, required)| messages.requires(&self.display_name(param), &self.display_name(required))));
+        for group in self.one_of.iter().filter(|group| !group.iter().any(|param| self.found(param))) {
+            let names: Vec<String> = group.iter().map(|param| self.display_name(param)).collect();
+            violations.push(messages.one_of_required(&names));
+        }
+        violations
+    }
+
+    /// Copy every parameter onto new storage, made with `copy` once per group of aliases so
+    /// the aliases keep sharing it. `rebind` carries each alias' own state over to the copy.
+    fn copy_params(&self, copy: fn(&Value) -> Value, rebind: bool) -> HashMap<Param, Value> {
+        let mut fresh: Vec<(*const (), Value)> = Vec::new();
+        let mut params = HashMap::with_capacity(self.params.len());
+        for (param, value) in self.params.iter() {
+            if let Some(storage) = value.storage() {
+                let new = match fresh.iter().find(|(known, _)| *known == storage) {
+                    Some((_, new)) => new.clone(),
+                    None => {
+                        let new = copy(value);
+                        fresh.push((storage, new.clone()));
+                        new
+                    }
+                };
+                let new = if rebind { value.rebind(&new) } else { new };
+                params.insert(param.clone(), new);
+            }
+        }
+        // The `--no-<long>` forms point at the new storage of their opt
+        for (param, value) in self.params.iter() {
+            match value {
+                Value::NegatedOpt { value, .. } => {
+                    let storage = Rc::as_ptr(value) as *const ();
+                    let opt = fresh.iter().find(|(known, _)| *known == storage);
+                    if let Some((_, Value::Opt { rhs, found, .. })) = opt {
+                        let negated = Value::NegatedOpt { value: rhs.value.clone(), found: found.clone() };
+                        params.insert(param.clone(), negated);
+                    }
+                }
+                Value::Trailing => {
+                    params.insert(param.clone(), Value::Trailing);
+                }
+                _ => (),
+            }
+        }
+        params
+    }
+
+    /// Register `value` under `param`, keeping the name at its first place in `order` if it is
+    /// registered again
+    fn register(&mut self, param: Param, value: Value) {
+        if self.params.insert(param.clone(), value).is_none() {
+            self.order.push(param);
+        }
+    }
+
+    /// Builder method for registering parameters under a section of their own, titled `title`,
+    /// e.g. `parser.group("Output options", |g| g.add_flag(&["q", "quiet"]).add_opt("o", "output"))`.
+    /// `usage`, `generate_man` and `definitions` show these in the order they were registered
+    /// in, after the flags, opts and settings registered out of any group. Registering under the
+    /// same title again adds to that section.
+    pub fn group<F: FnOnce(ArgParser) -> ArgParser>(self, title: &str, f: F) -> Self {
+        let start = self.order.len();
+        let mut parser = f(self);
+        let params = parser.order[start..].to_vec();
+        match parser.groups.iter_mut().find(|(known, _)| known == title) {
+            Some((_, known)) => known.extend(params),
+            None => parser.groups.push((title.to_owned(), params)),
+        }
+        parser
+    }
+
+    /// Get the index in `groups` of the section holding a parameter, and its place within it
+    fn group_of(&self, entry: &Entry) -> Option<(usize, usize)> {
+        let names: Vec<Param> = entry.shorts.iter().map(|&short| Param::Short(short))
+            .chain(entry.longs.iter().map(|long| Param::Long((*long).to_owned())))
+            .collect();
+        self.groups.iter().enumerate().find_map(|(index, (_, params))| {
+            params.iter().position(|param| names.contains(param)).map(|place| (index, place))
+        })
+    }
+
+    /// Make an independent parser with the same registrations and configuration, but none of
+    /// the parsed state: every parameter gets new storage holding its default value, or the
+    /// value read by `load_config`.
+    pub fn clone_spec(&self) -> ArgParser {
+        let mut parser = ArgParser {
+            params: self.copy_params(Value::respec, false),
+            order: self.order.clone(),
+            groups: self.groups.clone(),
+            required: self.required.clone(),
+            commands: self.commands.clone(),
+            descriptions: self.descriptions.clone(),
+            delimiters: self.delimiters.clone(),
+            messages: self.messages.clone(),
+            env: self.env.clone(),
+            config: self.config.clone(),
+            validators: self.validators.clone(),
+            exclusive: self.exclusive.clone(),
+            choices: self.choices.clone(),
+            help: self.help.clone(),
+            version: self.version.clone(),
+            metadata: self.metadata.clone(),
+            value_checks: self.value_checks.clone(),
+            arg_checks: self.arg_checks.clone(),
+            callbacks: self.callbacks.clone(),
+            positionals: self.positionals.clone(),
+            negatable: self.negatable.clone(),
+            counted: self.counted.clone(),
+            deprecated: self.deprecated.clone(),
+            hidden: self.hidden.clone(),
+            unstable: self.unstable.clone(),
+            unstable_opt_in: self.unstable_opt_in.clone(),
+            requirements: self.requirements.clone(),
+            one_of: self.one_of.clone(),
+            subcommands: self.subcommands.iter()
+                .map(|subcommand| Subcommand { name: subcommand.name.clone(), parser: subcommand.parser.clone_spec() })
+                .collect(),
+            globals: self.globals.clone(),
+            #[cfg(feature = "prompt")]
+            prompts: self.prompts.clone(),
+            #[cfg(feature = "config")]
+            config_values: self.config_values.clone(),
+            ..ArgParser::new(0)
+        };
+        parser.apply_config();
+        parser
+    }
+
+    /// Clear everything parsed so far, keeping the registrations and configuration, so that
+    /// another command line can be parsed as if by a new parser. Every parameter goes back to
+    /// its default value, or to empty, with no occurrences.
+    pub fn reset(&mut self) {
+        *self = self.clone_spec();
+    }
+
+    /// Bring a single parameter, under all its aliases, back to its default value, or to empty,
+    /// with no occurrences, as `reset` does for all of them. Returns whether it is registered.
+    pub fn reset_param<P: ParamName>(&mut self, name: P) -> bool {
+        let storage = match self.get_param(name) {
+            Some(value) => value.storage(),
+            None => return false,
+        };
+        if storage.is_none() {
+            return true;
+        }
+        for value in self.params.values_mut().filter(|value| value.storage() == storage) {
+            value.clear();
+        }
+        let params = &self.params;
+        self.from_env.retain(|param| params.get(param).and_then(Value::storage) != storage);
+        true
+    }
+
+    /// Start parsing user inputted args for which flags and opts are used at
+    /// runtime. The rest of the args that are not associated to opts get added
+    /// to `ArgParser.args`.
+    ///
+    /// Parsing goes on past problems; they are all returned, in the order they were met. Unknown
+    /// parameters are left out unless `unknown_severity` is `Severity::Error`, the default.
+    pub fn parse<A: Iterator<Item = String>>(&mut self, args: A) -> Result<(), Vec<ArgParseError>> {
+        self.parse_args(args.map(Cow::Owned))
+    }
+
+    /// The parsing of `parse`, over owned or borrowed args
+    fn parse_args<'a, A: Iterator<Item = Cow<'a, str>>>(&mut self, args: A) -> Result<(), Vec<ArgParseError>> {
+        let start = self.diagnostics.len();
+        self.scan(args, self.config.strict);
+        let severity = if self.config.strict { Severity::Error } else { self.config.unknown_severity };
+        let errors: Vec<ArgParseError> = self.diagnostics[start..].iter()
+            .filter(|diagnostic| diagnostic.kind != DiagnosticKind::Unknown || severity == Severity::Error)
+            .map(|diagnostic| {
+                let name = self.display_name(&diagnostic.param);
+                match (diagnostic.kind, &diagnostic.param) {
+                    (DiagnosticKind::Unknown, Param::Short(_)) => ArgParseError::UnknownFlag(name),
+                    (DiagnosticKind::Unknown, Param::Long(name)) if !diagnostic.token.starts_with('-') => {
+                        ArgParseError::InvalidSetting(name.clone())
+                    }
+                    (DiagnosticKind::Unknown, Param::Long(_)) => ArgParseError::UnknownOpt(name),
+                    (DiagnosticKind::MissingValue, _) => ArgParseError::MissingValue(name),
+                    (DiagnosticKind::InvalidValue, _) => ArgParseError::InvalidValue(name),
+                    (DiagnosticKind::Unexpected, _) => ArgParseError::UnexpectedArg(diagnostic.token.clone()),
+                    (DiagnosticKind::Ambiguous, param) => {
+                        ArgParseError::AmbiguousOpt(name, self.ambiguous_candidates(param))
+                    }
+                }
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Parse args like `parse`, taking them as given by `std::env::args_os`
+    ///
+    /// Args which aren't valid UTF-8 are parsed in their lossy conversion, but `get_opt_os`
+    /// and `args_os` give back their original bytes, so paths are kept intact.
+    pub fn parse_os<A: Iterator<Item = OsString>>(&mut self, args: A) -> Result<(), Vec<ArgParseError>> {
+        let start = self.events.len();
+        let mut lossless = Vec::new();
+        self.os_args.clear();
+        for (index, arg) in args.enumerate() {
+            match arg.into_string() {
+                Ok(arg) => lossless.push(arg),
+                Err(arg) => {
+                    lossless.push(arg.to_string_lossy().into_owned());
+                    self.os_args.insert(index, arg);
+                }
+            }
+        }
+        let result = self.parse(lossless.into_iter());
+        self.remember_os_values(start);
+        result
+    }
+
+    /// Keep the original bytes of the values given by the events from `start` on, when they
+    /// were taken from an arg which isn't valid UTF-8, either whole or after its parameter
+    fn remember_os_values(&mut self, start: usize) {
+        for (index, event) in self.events[start..].iter() {
+            let (param, value) = match event {
+                ParsedItem::Opt(param, value) => (param.clone(), value),
+                ParsedItem::Setting(key, value) => (Param::Long(key.clone()), value),
+                _ => continue,
+            };
+            // A value is attached to its parameter or is the arg after it
+            let raw = [*index, index + 1].iter()
+                .filter_map(|index| self.os_args.get(index))
+                .find_map(|arg| raw_value(arg, value));
+            if let Some(raw) = raw {
+                let storage = self.params.get(&param).and_then(Value::storage);
+                let params = &self.params;
+                self.os_values.retain(|(param, _)| params.get(param).and_then(Value::storage) != storage);
+                self.os_values.push((param, raw));
+            }
+        }
+    }
+
+    /// Get the value of an Opt like `get_opt`, as it was given to `parse_os` even if it isn't
+    /// valid UTF-8.
+    pub fn get_opt_os<P: ParamName>(&self, opt: P) -> Option<OsString> {
+        let param = opt.param_ref();
+        let value = self.get_opt(param)?;
+        let storage = self.get_param(param).and_then(Value::storage);
+        let raw = self.os_values.iter()
+            .find(|(param, _)| self.params.get::<Param>(param).and_then(Value::storage) == storage)
+            .map(|(_, raw)| raw)
+            .filter(|raw| raw.to_string_lossy() == value);
+        Some(raw.cloned().unwrap_or_else(|| OsString::from(value)))
+    }
+
+    /// Get the positional args like `args`, as they were given to `parse_os` even if they
+    /// aren't valid UTF-8.
+    pub fn args_os(&self) -> Vec<OsString> {
+        self.args.iter().enumerate()
+            .map(|(i, arg)| {
+                let raw = self.positions.get(i).and_then(|index| self.os_args.get(index));
+                match raw.filter(|raw| raw.to_string_lossy() == arg.as_str()) {
+                    Some(raw) => raw.clone(),
+                    None => OsString::from(arg),
+                }
+            })
+            .collect()
+    }
+
+    /// Parse args like `parse`, whether or not they start with the program name
+    ///
+    /// The first arg is taken for the program name and skipped unless it looks like a parameter:
+    /// it starts with `-`, it sets a registered setting (`name=value`), or it is one of the
+    /// registered `commands`. So `["prog", "-v"]` and `["-v"]` parse the same, but a leading
+    /// positional like `["file.txt", "-v"]` is skipped; use `parse` when it can happen.
+    pub fn parse_auto<A: Iterator<Item = String>>(&mut self, args: A) -> Result<(), Vec<ArgParseError>> {
+        let mut args = args.peekable();
+        let has_program = match args.peek() {
+            Some(first) => !self.looks_like_param(first),
+            None => true,
+        };
+        if has_program {
+            self.parse(args)
+        } else {
+            self.parse(Some(String::new()).into_iter().chain(args))
+        }
+    }
+
+    /// Check whether `arg` looks like a parameter rather than a program name, for `parse_auto`
+    fn looks_like_param(&self, arg: &str) -> bool {
+        let setting = arg.split_once('=')
+            .is_some_and(|(name, _)| matches!(self.params.named(name), Some(Value::Setting { .. })));
+        arg.starts_with('-') || setting || self.commands.iter().any(|command| command == arg)
+    }
+
+    /// Parse args like `parse`, but stop at the first unknown parameter or opt missing its
+    /// value and return it as an error. The args left are kept in `skipped_args`.
+    pub fn parse_strict<A: Iterator<Item = String>>(&mut self, args: A) -> Result<(), ParseError> {
+        let start = self.diagnostics.len();
+        if !self.scan(args.map(Cow::Owned), true) {
+            return Ok(());
+        }
+        let severity = self.config.unknown_severity;
+        let diagnostic = self.diagnostics[start..].iter()
+            .find(|diagnostic| diagnostic.kind != DiagnosticKind::Unknown || severity == Severity::Error);
+        match diagnostic {
+            Some(Diagnostic { param, kind, .. }) => {
+                let name = self.display_name(param);
+                Err(match kind {
+                    DiagnosticKind::Unknown => ParseError::Unknown(name),
+                    DiagnosticKind::MissingValue => ParseError::MissingValue(name),
+                    DiagnosticKind::InvalidValue => ParseError::InvalidValue(name),
+                    DiagnosticKind::Unexpected => ParseError::Unexpected(name),
+                    DiagnosticKind::Ambiguous => ParseError::Ambiguous(name, self.ambiguous_candidates(param)),
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// The scanning shared by `parse` and `parse_strict`. Returns whether it stopped at an
+    /// error, which it only does with `stop_at_error`.
+    fn scan<'a, A: Iterator<Item = Cow<'a, str>>>(&mut self, args: A, stop_at_error: bool) -> bool {
+        let (invalid, missing) = (self.invalid.len() + self.invalid_operands.len(), self.missing_values.len());
+        let deprecated_counts: Vec<usize> = self.deprecated.iter().map(|(old, _)| self.count(old)).collect();
+        let mut stopped = false;
+        let args: Vec<Cow<str>> = args.collect();
+        if self.config.retain_input {
+            self.original = Some(args.iter().map(|arg| arg.to_string()).collect());
+        }
+        // Every arg along with its position in the args given, the program name being 0
+        let args: Vec<(usize, Cow<str>)> = (1..).zip(args.into_iter().skip(1)).collect();
+        let args = if self.config.response_files { self.expand_response_files(args, 0) } else { args };
+        let total = args.len();
+        self.total_input += total;
+        let (args, origins): (Vec<Cow<str>>, Vec<usize>) = args.into_iter().map(|(origin, arg)| (arg, origin)).unzip();
+        let mut args = args.into_iter().peekable();
+        let mut positionals = 0;
+        // The names counted for each callback, looked up once rather than for every arg
+        let callback_names: Vec<Vec<Param>> = self.callbacks.iter().map(|(param, _)| self.count_names(param)).collect();
+        while let Some(arg) = args.next() {
+            // The position of `arg` among the args once expanded, and in the args given, the args
+            // read from an `@file` being at the position of the `@file`
+            let position = total - args.len();
+            let index = origins[position - 1];
+            let counts: Vec<usize> = callback_names.iter().map(|names| self.total_count(names)).collect();
+            let args_before = self.args.len();
+            let (events_before, token) = (self.events.len(), (!self.arg_checks.is_empty()).then(|| arg.to_string()));
+            if self.config.verbatim_after == Some(positionals) {
+                self.collect_trailing(Some(arg).into_iter().chain(args), &origins[position - 1..]);
+                break;
+            }
+            if self.config.end_marker.as_deref() == Some(&*arg) {
+                self.collect_trailing(args, &origins[position..]);
+                break;
+            }
+            let (arg, dashed) = match self.translate_slash(&arg) {
+                Some(translated) => (Cow::Owned(translated), true),
+                None => (arg, self.config.style != ParserStyle::Windows),
+            };
+            if let Some(arg) = arg.strip_prefix("--").filter(|_| dashed) {
+                // Remove both dashes
+                if arg.is_empty() {
+                    //Arg `--` means we are done parsing args, collect the rest
+                    self.collect_trailing(args, &origins[position..]);
+                    break;
+                }
+                let respelled = self.respell_long(arg);
+                let arg = respelled.as_deref().unwrap_or(arg);
+                let expanded = self.expand_abbreviation(arg);
+                let arg = expanded.as_deref().unwrap_or(arg);
+                if let Some((lhs, rhs)) = self.split_long(arg) {
+                    match self.params.named_mut(lhs) {
+                        Some(&mut Value::Opt { rhs: ref mut opt_rhs, ref mut found, greedy, .. }) => {
+                            let previous = (self.config.warn_on_override && *(**found).borrow())
+                                .then(|| (*opt_rhs.value).borrow().clone());
+                            opt_rhs.occurrences += 1;
+                            (*opt_rhs.value).borrow_mut().clear();
+                            (*opt_rhs.value).borrow_mut().push_str(rhs);
+                            if greedy {
+                                if let Some(words) = take_words(&mut args) {
+                                    (*opt_rhs.value).borrow_mut().push(' ');
+                                    (*opt_rhs.value).borrow_mut().push_str(&words);
+                                }
+                            }
+                            *(*found).borrow_mut() = true;
+                            self.events.push(opt_event(index, Param::Long(lhs.to_owned()), &opt_rhs.value));
+                            if let Some(previous) = previous {
+                                let value = opt_rhs.value.clone();
+                                self.warn_override(&value, previous);
+                            }
+                        }
+                        Some(&mut Value::KeyVal { rhs: ref mut kv_rhs, ref mut found }) => {
+                            match split_keyval(rhs) {
+                                Some(pair) => {
+                                    (*kv_rhs.value).borrow_mut().push(pair);
+                                    kv_rhs.occurrences += 1;
+                                    *(*found).borrow_mut() = true;
+                                    let event = ParsedItem::Opt(Param::Long(lhs.to_owned()), rhs.to_owned());
+                                    self.events.push((index, event));
+                                }
+                                None => self.reject_value(Param::Long(lhs.to_owned()), index, format!("--{}", arg)),
+                            }
+                        }
+                        Some(&mut Value::MultiOpt { rhs: ref mut values, csv }) => {
+                            let param = Param::Long(lhs.to_owned());
+                            push_multi(values, rhs.to_owned(), csv, index, param, &mut self.events)
+                        }
+                        _ => self.reject(Param::Long(lhs.to_owned()), index, format!("--{}", arg)),
+                    }
+                } else {
+                    let param = Param::Long(arg.to_owned());
+                    match self.params.named_mut(arg) {
+                        Some(&mut Value::Flag(ref mut rhs)) => {
+                            *(*rhs.value).borrow_mut() = true;
+                            rhs.occurrences += 1;
+                            self.events.push((index, ParsedItem::Flag(param)));
+                        }
+                        Some(&mut Value::Opt { ref mut rhs, ref mut found, hybrid: true, .. }) => {
+                            rhs.occurrences += 1;
+                            *(*found).borrow_mut() = true;
+                            self.events.push(opt_event(index, param, &rhs.value));
+                        }
+                        Some(&mut Value::Opt { ref mut rhs, ref mut found, greedy: true, .. })
+                            if args.peek().is_some_and(|next| !(next.starts_with('-') && next.len() > 1)) => {
+                            *(*rhs.value).borrow_mut() = take_words(&mut args).unwrap_or_default();
+                            rhs.occurrences += 1;
+                            *(*found).borrow_mut() = true;
+                            self.events.push(opt_event(index, param, &rhs.value));
+                        }
+                        Some(&mut Value::Opt { ref mut rhs, ref mut found, ref implicit, .. }) => {
+                            match (args.peek(), implicit) {
+                                (None, Some(implicit)) => {
+                                    *(*rhs.value).borrow_mut() = implicit.clone();
+                                    rhs.occurrences += 1;
+                                    *(*found).borrow_mut() = true;
+                                    self.events.push(opt_event(index, param, &rhs.value));
+                                }
+                                (None, None) => self.missing(param, index, format!("--{}", arg)),
+                                // The long form of an implicit opt only takes a value through `=`
+                                (Some(next), None) if is_negative_number(next)
+                                    || !self.config.strict_long_values
+                                        && (!next.starts_with('-') || next.len() == 1) => {
+                                    *(*rhs.value).borrow_mut() = args.next().map(Cow::into_owned).unwrap_or_default();
+                                    rhs.occurrences += 1;
+                                    *(*found).borrow_mut() = true;
+                                    self.events.push(opt_event(index, param, &rhs.value));
+                                }
+                                (Some(_), _) => {
+                                    rhs.occurrences += 1;
+                                    *(*found).borrow_mut() = true;
+                                    self.events.push(opt_event(index, param, &rhs.value));
+                                }
+                            }
+                        }
+                        Some(&mut Value::KeyVal { ref mut rhs, ref mut found }) => {
+                            // A pair never starts with a dash, so `--label --verbose` misses its pair
+                            let pair = args.next_if(|next| !(next.starts_with('-') && next.len() > 1));
+                            match pair.as_ref().and_then(|pair| split_keyval(pair)) {
+                                Some((key, value)) => {
+                                    self.events.push((index, ParsedItem::Opt(param, format!("{}={}", key, value))));
+                                    (*rhs.value).borrow_mut().push((key, value));
+                                    rhs.occurrences += 1;
+                                    *(*found).borrow_mut() = true;
+                                }
+                                None => self.reject_value(param, index, format!("--{}", arg)),
+                            }
+                        }
+                        Some(&mut Value::MultiOpt { rhs: ref mut values, csv }) => {
+                            match args.next() {
+                                Some(value) => {
+                                    let events = &mut self.events;
+                                    push_multi(values, value.into_owned(), csv, index, param, events)
+                                }
+                                None => self.missing(param, index, format!("--{}", arg)),
+                            }
+                        }
+                        Some(&mut Value::NegatedOpt { ref mut value, ref mut found }) => {
+                            (*value).borrow_mut().clear();
+                            *(*found).borrow_mut() = false;
+                        }
+                        // `--no-<flag>` turns a negatable long flag back off
+                        _ => match arg.strip_prefix("no-")
+                            .filter(|flag| self.negatable.iter().any(|negatable| negatable == flag))
+                            .and_then(|flag| self.params.named_mut(flag)) {
+                            Some(Value::Flag(rhs)) => {
+                                *(*rhs.value).borrow_mut() = false;
+                                rhs.occurrences += 1;
+                                self.events.push((index, ParsedItem::Flag(Param::Long(arg["no-".len()..].to_owned()))));
+                            }
+                            _ => self.reject(param, index, format!("--{}", arg)),
+                        },
+                    }
+                }
+            } else if dashed && arg.starts_with("-") && arg != "-" && !self.is_negative_number(&arg) {
+                let mut chars = arg[1..].chars();
+                while let Some(ch) = chars.next() {
+                    // Whether the rest of the cluster goes on with parameters, as in `-dd` or `-dv`
+                    let cluster_goes_on = chars.clone().next().map_or(true, |next| self.params.has_named(next));
+                    match self.params.named_mut(ch) {
+                        Some(&mut Value::Flag(ref mut rhs)) => {
+                            *(*rhs.value).borrow_mut() = true;
+                            rhs.occurrences += 1;
+                            self.events.push((index, ParsedItem::Flag(Param::Short(ch))));
+                        }
+                        Some(&mut Value::Opt { ref mut rhs, ref mut found, hybrid: true, .. }) => {
+                            rhs.occurrences += 1;
+                            *(*found).borrow_mut() = true;
+                            if !cluster_goes_on {
+                                *(*rhs.value).borrow_mut() = attached_value(chars).unwrap_or_default();
+                                self.events.push(opt_event(index, Param::Short(ch), &rhs.value));
+                                break;
+                            }
+                            self.events.push(opt_event(index, Param::Short(ch), &rhs.value));
+                        }
+                        Some(&mut Value::Opt { ref mut rhs, ref mut found, ref implicit, greedy, .. }) => {
+                            let previous = (self.config.warn_on_override && *(**found).borrow())
+                                .then(|| (*rhs.value).borrow().clone());
+                            let mut rest = attached_value(chars);
+                            if greedy {
+                                if let Some(words) = take_words(&mut args) {
+                                    match rest {
+                                        Some(ref mut rest) if !rest.is_empty() => {
+                                            rest.push(' ');
+                                            rest.push_str(&words);
+                                        }
+                                        _ => rest = Some(words),
+                                    }
+                                }
+                            }
+                            let value = rhs.value.clone();
+                            match rest.or_else(|| args.next().map(Cow::into_owned)).or_else(|| implicit.clone()) {
+                                Some(given) => {
+                                    *(*value).borrow_mut() = given;
+                                    rhs.occurrences += 1;
+                                    *(*found).borrow_mut() = true;
+                                    self.events.push(opt_event(index, Param::Short(ch), &value));
+                                }
+                                None => {
+                                    (*value).borrow_mut().clear();
+                                    self.missing(Param::Short(ch), index, arg.to_string());
+                                }
+                            }
+                            if let Some(previous) = previous {
+                                self.warn_override(&value, previous);
+                            }
+                            break;
+                        }
+                        Some(&mut Value::KeyVal { ref mut rhs, ref mut found }) => {
+                            let pair = attached_value(chars).or_else(|| {
+                                args.next_if(|next| !(next.starts_with('-') && next.len() > 1)).map(Cow::into_owned)
+                            });
+                            match pair.as_ref().and_then(|pair| split_keyval(pair)) {
+                                Some((key, value)) => {
+                                    let event = ParsedItem::Opt(Param::Short(ch), format!("{}={}", key, value));
+                                    self.events.push((index, event));
+                                    (*rhs.value).borrow_mut().push((key, value));
+                                    rhs.occurrences += 1;
+                                    *(*found).borrow_mut() = true;
+                                }
+                                None => self.reject_value(Param::Short(ch), index, arg.to_string()),
+                            }
+                            break;
+                        }
+                        Some(&mut Value::MultiOpt { rhs: ref mut values, csv }) => {
+                            match attached_value(chars).or_else(|| args.next().map(Cow::into_owned)) {
+                                Some(value) => {
+                                    push_multi(values, value, csv, index, Param::Short(ch), &mut self.events)
+                                }
+                                None => self.missing(Param::Short(ch), index, arg.to_string()),
+                            }
+                            break;
+                        }
+                        Some(&mut Value::Setting { .. })
+                        | Some(&mut Value::NegatedOpt { .. })
+                        | Some(&mut Value::Trailing) => {
+                            self.reject(Param::Short(ch), index, arg.to_string())
+                        }
+                        // The rest of the cluster is kept with it, as it may be its value
+                        None if self.config.collect_unknown => {
+                            self.unknown.push(format!("-{}{}", ch, chars.as_str()));
+                            break;
+                        }
+                        None => self.reject(Param::Short(ch), index, arg.to_string()),
+                    }
+                    // The rest of a cluster like `-xyz` is dropped once `max_invalid` is reached
+                    if self.reached_max_invalid() {
+                        break;
+                    }
+                }
+            } else if arg.contains("=") {
+                if arg.is_empty() {
+                    //Arg `--` means we are done parsing args, collect the rest
+                    self.args.extend(args.map(Cow::into_owned));
+                    break;
+                }
+                let arg = self.respell_long(&arg).map(Cow::Owned).unwrap_or(arg);
+                if let Some(i) = arg.find('=') {
+                    let (lhs, rhs) = arg.split_at(i);
+                    let rhs = &rhs[1..]; // slice off the `=` char
+                    match self.params.named_mut(lhs) {
+                        Some(&mut Value::Setting { rhs: ref mut opt_rhs, ref mut found, ref values, .. }) => {
+                            opt_rhs.occurrences += 1;
+                            (*opt_rhs.value).borrow_mut().clear();
+                            (*opt_rhs.value).borrow_mut().push_str(rhs);
+                            if let Some(values) = values {
+                                (**values).borrow_mut().push(rhs.to_owned());
+                            }
+                            *(*found).borrow_mut() = true;
+                            self.events.push((index, ParsedItem::Setting(lhs.to_owned(), rhs.to_owned())));
+                        }
+                        Some(_) => self.reject(Param::Long(lhs.to_owned()), index, arg.to_string()),
+                        None => self.reject_operand(lhs.to_owned(), index, arg.to_string()),
+                    }
+                }
+            } else if let Some(i) = self.subcommands.iter()
+                .position(|subcommand| positionals == 0 && subcommand.name == arg) {
+                self.subcommand = Some(i);
+                // The global parameters are lent to the subcommand, sharing their storage, and
+                // given back with the occurrences it found
+                let globals: Vec<(Param, Value)> = self.globals.iter()
+                    .filter_map(|param| self.params.get(param).map(|value| (param.clone(), value.clone())))
+                    .collect();
+                let subcommand = &mut self.subcommands[i].parser;
+                let mut lent = Vec::new();
+                for (param, value) in globals {
+                    if !subcommand.params.contains_key(&param) || subcommand.globals.contains(&param) {
+                        subcommand.register(param.clone(), value);
+                        if !subcommand.globals.contains(&param) {
+                            subcommand.globals.push(param.clone());
+                        }
+                        lent.push(param);
+                    }
+                }
+                let start = subcommand.diagnostics.len();
+                let _ = subcommand.parse_args(Some(arg).into_iter().chain(args));
+                let returned: Vec<(Param, Value)> = lent.into_iter()
+                    .filter_map(|param| subcommand.params.get(&param).map(|value| (param.clone(), value.clone())))
+                    .collect();
+                // Report the subcommand's problems as well, at their place in the whole args
+                let diagnostics = subcommand.diagnostics[start..].iter()
+                    .map(|diagnostic| {
+                        let index = origins.get(position - 1 + diagnostic.index).copied().unwrap_or(index);
+                        Diagnostic { index, ..diagnostic.clone() }
+                    });
+                self.diagnostics.extend(diagnostics);
+                self.params.extend(returned);
+                break;
+            } else if self.config.stop_at_first_positional {
+                self.collect_trailing(Some(arg).into_iter().chain(args), &origins[position - 1..]);
+                break;
+            } else if self.config.strict && self.config.max_positionals.is_some_and(|max| positionals >= max) {
+                let message = self.messages.0.unexpected_argument(&arg);
+                let (token, param) = (arg.to_string(), Param::Long(arg.into_owned()));
+                self.diagnostics.push(Diagnostic { index, token, param, message, kind: DiagnosticKind::Unexpected });
+                self.skipped.extend(args.map(Cow::into_owned));
+                stopped = true;
+                break;
+            } else {
+                self.args.push(arg.into_owned());
+                self.positions.push(index);
+                positionals += 1;
+            }
+            if let Some(token) = token {
+                self.check_args(index, events_before, token);
+            }
+            self.run_callbacks(&callback_names, &counts);
+            self.record_positionals(index, args_before);
+            if self.reached_max_invalid() {
+                self.skipped.extend(args.map(Cow::into_owned));
+                break;
+            }
+            if stop_at_error
+                && (self.invalid.len() + self.invalid_operands.len() > invalid || self.missing_values.len() > missing) {
+                self.skipped.extend(args.map(Cow::into_owned));
+                stopped = true;
+                break;
+            }
+        }
+        for ((old, new), before) in self.deprecated.clone().iter().zip(deprecated_counts) {
+            if self.count(old) > before {
+                let warning = self.messages.0.deprecated(&self.display_name(old), &self.display_name(new));
+                self.warnings.push(warning);
+            }
+        }
+        self.apply_env();
+        self.apply_config();
+        self.expand_homes();
+        if self.config.expand_references {
+            self.expand_values();
+        }
+        self.check_values();
+        stopped
+    }
+
+    /// Run the checks of `add_opt_checked` over the values given by the arg `token`, found from
+    /// `events_before` on in `events`
+    fn check_args(&mut self, index: usize, events_before: usize, token: String) {
+        let failures: Vec<(Param, String)> = self.events[events_before..].iter()
+            .filter_map(|(_, item)| match item {
+                ParsedItem::Opt(param, value) => Some((param, value)),
+                _ => None,
+            })
+            .filter_map(|(param, value)| {
+                let storage = self.params.get(param).and_then(Value::storage);
+                let (_, check) = self.arg_checks.iter()
+                    .find(|(checked, _)| self.params.get(checked).and_then(Value::storage) == storage)?;
+                (check.0)(value).err().map(|error| (param.clone(), error))
+            })
+            .collect();
+        for (param, error) in failures {
+            if self.reached_max_invalid() {
+                break;
+            }
+            let message = self.messages.0.failed_check(&self.display_name(&param), &error);
+            self.reject_value_as(param, index, token.clone(), message);
+        }
+    }
+
+    /// Check whether there are as many invalid parameters as `max_invalid` allows
+    fn reached_max_invalid(&self) -> bool {
+        self.config.max_invalid.is_some_and(|max| self.invalid.len() + self.invalid_operands.len() >= max)
+    }
+
+    /// Run the checks of the opts registered with `add_opt_validated` over their current value
+    fn check_values(&mut self) {
+        let failures = self.value_checks.iter()
+            .filter_map(|(param, check)| {
+                let result = self.map_opt(param, |value| (check.0)(value))
+                    .or_else(|| self.get_setting(param).map(|value| (check.0)(&value)));
+                result.map(|result| (param, result))
+            })
+            .filter_map(|(param, result)| result.err().map(|error| (param.clone(), error)))
+            .collect();
+        self.validation_failures = failures;
+    }
+
+    /// Fall back on the environment for the parameters registered with one, like
+    /// `add_flag_env` or `add_opt_env`, which weren't found on the command line. This is done
+    /// at the end of `parse`; the environment never counts as an occurrence.
+    pub fn apply_env(&mut self) {
+        for (param, var) in self.env.clone() {
+            let value = match env::var(&var) {
+                Ok(value) if !self.found(&param)
+                    || self.params.get(&param).is_some_and(|value| self.is_from_config(value)) => value,
+                _ => continue,
+            };
+            match self.params.get(&param) {
+                Some(Value::Flag(rhs)) if parse_bool(&value).unwrap_or(false) => {
+                    *(*rhs.value).borrow_mut() = true;
+                }
+                Some(Value::Opt { rhs, found, .. }) | Some(Value::Setting { rhs, found, .. }) => {
+                    *(*rhs.value).borrow_mut() = value;
+                    *(**found).borrow_mut() = true;
+                }
+                _ => continue,
+            }
+            self.from_env.push(param);
+        }
+    }
+
+    /// Expand the leading `~/` of the opts registered with `add_opt_expanduser`
+    fn expand_homes(&mut self) {
+        let home = match self.config.home_dir.clone().map_or_else(|| env::var("HOME"), Ok) {
+            Ok(home) => home,
+            Err(_) => return,
+        };
+        for value in self.params.values() {
+            if let Value::Opt { rhs, found, expand_home: true, .. } = value {
+                if !*(**found).borrow() {
+                    continue;
+                }
+                let expanded = (*rhs.value).borrow().strip_prefix("~/")
+                    .map(|path| format!("{}/{}", home.trim_end_matches('/'), path));
+                if let Some(expanded) = expanded {
+                    *(*rhs.value).borrow_mut() = expanded;
+                }
+            }
+        }
+    }
+
+    /// Check whether the storage of `value` was filled from the environment
+    fn is_from_env(&self, value: &Value) -> bool {
+        self.from_env.iter().any(|param| self.params.get(param).and_then(Value::storage) == value.storage())
+    }
+
+    /// Check whether the storage of `value` was filled from the file given to `load_config`
+    #[cfg(feature = "config")]
+    fn is_from_config(&self, value: &Value) -> bool {
+        self.from_config.iter().any(|param| self.params.get(param).and_then(Value::storage) == value.storage())
+    }
+
+    #[cfg(not(feature = "config"))]
+    fn is_from_config(&self, _value: &Value) -> bool {
+        false
+    }
+
+    /// Read the `key = value` lines of a TOML or INI file into the opts, settings and flags named
+    /// by the keys, as in `output = "out.txt"` or `verbose = true`
+    ///
+    /// Keys under a `[section]` are prefixed with it, as in `section.key`. Values given on the
+    /// command line or by the environment win over the file's, which win over the defaults;
+    /// `value_source` tells them apart. The file can be loaded before or after `parse`.
+    #[cfg(feature = "config")]
+    pub fn load_config<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|err| format!("Can't read '{}': {}", path.display(), err))?;
+        let mut values = Vec::new();
+        for (key, value, line) in parse_config(&text)? {
+            match param_named(&key) {
+                Some(param) if self.params.contains_key(&param) => values.push((param, value)),
+                _ => return Err(format!("Unknown parameter '{}' on line {} of '{}'", key, line, path.display())),
+            }
+        }
+        self.config_values = values;
+        self.apply_config();
+        Ok(())
+    }
+
+    /// Fill the parameters neither given on the command line nor by the environment from the
+    /// values read by `load_config`
+    #[cfg(feature = "config")]
+    fn apply_config(&mut self) {
+        self.from_config.clear();
+        for (param, value) in self.config_values.clone() {
+            let storage = self.params.get(&param).and_then(Value::storage);
+            let given = self.params.iter().any(|(alias, value)| value.storage() == storage && self.count(alias) > 0);
+            if given || self.params.get(&param).is_some_and(|value| self.is_from_env(value)) {
+                continue;
+            }
+            match self.params.get(&param) {
+                Some(Value::Flag(rhs)) => match parse_bool(&value) {
+                    Some(enabled) => *(*rhs.value).borrow_mut() = enabled,
+                    None => continue,
+                },
+                Some(Value::Opt { rhs, found, .. }) | Some(Value::Setting { rhs, found, .. }) => {
+                    *(*rhs.value).borrow_mut() = value;
+                    *(**found).borrow_mut() = true;
+                }
+                _ => continue,
+            }
+            self.from_config.push(param);
+        }
+    }
+
+    #[cfg(not(feature = "config"))]
+    fn apply_config(&mut self) {}
+
+    /// Ask for the value of the opt or setting `name` with `prompt` in `resolve_prompts` when it
+    /// wasn't given on the command line, by the environment or by a config file
+    ///
+    /// Only opts and settings registered before can be prompted for; other names are ignored.
+    #[cfg(feature = "prompt")]
+    pub fn prompt_if_missing<P: ParamName>(self, name: P, prompt: &str) -> Self {
+        self.add_prompt(name, prompt, false)
+    }
+
+    /// Like `prompt_if_missing`, but the answer isn't echoed back, as fits a password
+    ///
+    /// Hiding the input relies on `stty`, so it's only done on unix.
+    #[cfg(feature = "prompt")]
+    pub fn prompt_secret_if_missing<P: ParamName>(self, name: P, prompt: &str) -> Self {
+        self.add_prompt(name, prompt, true)
+    }
+
+    /// Register the prompt of `prompt_if_missing` or `prompt_secret_if_missing`
+    #[cfg(feature = "prompt")]
+    fn add_prompt<P: ParamName>(mut self, name: P, prompt: &str, secret: bool) -> Self {
+        let param = match name.param_ref() {
+            ParamRef::Short(ch) => Param::Short(ch),
+            ParamRef::Long(long) => Param::Long(long.to_owned()),
+        };
+        if let Some(Value::Opt { .. }) | Some(Value::Setting { .. }) = self.params.get(&param) {
+            self.prompts.push((param, String::from(prompt), secret));
+        }
+        self
+    }
+
+    /// Ask on stderr for the values registered with `prompt_if_missing` which are still
+    /// missing after `parse`, reading each answer from a line of stdin
+    ///
+    /// The answers fill the opts and settings as if given on the command line, but their
+    /// count stays 0. Stdin ending before an answer is an `UnexpectedEof` error.
+    #[cfg(feature = "prompt")]
+    pub fn resolve_prompts(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+        self.resolve_prompts_from(&mut input, &mut io::stderr(), true)
+    }
+
+    /// Ask for the missing values on `output` and read them from `input`, hiding the secret
+    /// ones only when `terminal` is set
+    #[cfg(feature = "prompt")]
+    fn resolve_prompts_from(
+        &mut self,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+        terminal: bool,
+    ) -> io::Result<()> {
+        for (param, prompt, secret) in self.prompts.clone() {
+            if self.found(&param) {
+                continue;
+            }
+            write!(output, "{}", prompt)?;
+            output.flush()?;
+            let hidden = secret && terminal && set_echo(false);
+            let mut line = String::new();
+            let read = input.read_line(&mut line);
+            if hidden {
+                set_echo(true);
+                writeln!(output)?;
+            }
+            if read? == 0 {
+                let message = format!("No value given for '{}'", self.display_name(&param));
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, message));
+            }
+            let value = line.trim_end_matches(['\n', '\r']);
+            let stored = self.params.get(&param);
+            if let Some(Value::Opt { rhs, found, .. }) | Some(Value::Setting { rhs, found, .. }) = stored {
+                *(*rhs.value).borrow_mut() = String::from(value);
+                *(**found).borrow_mut() = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the args held by the environment variable `var`, split the way a shell would.
+    ///
+    /// Call this before `parse` so that the args given on the command line override the ones
+    /// from the environment. Nothing is parsed if the variable isn't set, or if it can't be split.
+    pub fn parse_env_args(&mut self, var: &str) -> Result<(), String> {
+        match env::var(var) {
+            Ok(line) => self.parse_str(&line),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Parse the args of a single command line, split the way a shell would.
+    ///
+    /// Unlike `parse`, there is no program name to skip in `line`. Single quotes keep their
+    /// content literally, double quotes allow `\"` and `\\` escapes, and a backslash outside of
+    /// quotes escapes the next character. An unterminated quote is an error and nothing is parsed.
+    pub fn parse_str(&mut self, line: &str) -> Result<(), String> {
+        let words = split_shell(line)?;
+        let _ = self.parse(Some(String::new()).into_iter().chain(words));
+        Ok(())
+    }
+
+    /// Parse a line read interactively, e.g. by a REPL or a debugger, as a fresh command line.
+    ///
+    /// Everything parsed before is cleared as by `reset`, then `line` is split and parsed like
+    /// `parse_str` does, and the results are returned as a `ParsedArgs`. The parser holds them
+    /// too, so `found_invalid` or `diagnostics` can tell what went wrong. An unterminated quote
+    /// is an error and leaves the parser reset.
+    pub fn parse_line(&mut self, line: &str) -> Result<ParsedArgs, String> {
+        self.reset();
+        self.parse_str(line)?;
+        Ok(self.to_parsed())
+    }
+
+    /// Check whether an arg is a negative number, like `-5` or `-0.5`, rather than short
+    /// parameters. A registered short parameter named by its first digit takes precedence.
+    fn is_negative_number(&self, arg: &str) -> bool {
+        is_negative_number(arg) && !arg[1..].chars().next().is_some_and(|ch| self.params.has_named(ch))
+    }
+
+    /// Record an invalid parameter, unknown unless it is registered and misused
+    fn reject(&mut self, param: Param, index: usize, token: String) {
+        if !self.params.contains_key(&param) {
+            let name = match param {
+                Param::Short(ch) => format!("-{}", ch),
+                Param::Long(_) => token.clone(),
+            };
+            if self.config.collect_unknown
+                || self.config.unknown_patterns.iter().any(|pattern| glob_match(pattern, &name)) {
+                self.unknown.push(name);
+                return;
+            }
+        }
+        let candidates = self.ambiguous_candidates(&param);
+        let (kind, message) = match self.params.get(&param) {
+            Some(_) => (DiagnosticKind::InvalidValue, self.messages.0.invalid_value(&self.display_name(&param))),
+            None if !candidates.is_empty() => {
+                let message = self.messages.0.ambiguous_parameter(&self.display_name(&param), &candidates);
+                (DiagnosticKind::Ambiguous, message)
+            }
+            None => (DiagnosticKind::Unknown, self.messages.0.unknown_parameter(&self.display_name(&param))),
+        };
+        if kind == DiagnosticKind::Unknown && self.config.unknown_severity == Severity::Warn && !self.config.strict {
+            self.warnings.push(message.clone());
+        } else {
+            self.invalid.push(param.clone());
+        }
+        self.diagnostics.push(Diagnostic { index, token, param, message, kind });
+    }
+
+    /// Get the `--long` names an unregistered long parameter is an ambiguous abbreviation of,
+    /// if abbreviations are allowed
+    fn ambiguous_candidates(&self, param: &Param) -> Vec<String> {
+        let candidates = match param {
+            Param::Long(long) if self.config.abbreviations && !long.is_empty() && !self.params.contains_key(param) => {
+                self.abbreviated(long)
+            }
+            _ => return Vec::new(),
+        };
+        if candidates.len() < 2 {
+            return Vec::new();
+        }
+        candidates.into_iter().map(|long| format!("--{}", long)).collect()
+    }
+
+    /// Record a `name=value` arg whose name isn't a registered setting, like `reject` does for
+    /// unknown parameters
+    fn reject_operand(&mut self, name: String, index: usize, token: String) {
+        if self.config.collect_unknown
+            || self.config.unknown_patterns.iter().any(|pattern| glob_match(pattern, &token)) {
+            self.unknown.push(token);
+            return;
+        }
+        let message = self.messages.0.unknown_operand(&name);
+        if self.config.unknown_severity == Severity::Warn && !self.config.strict {
+            self.warnings.push(message.clone());
+        } else {
+            self.invalid_operands.push(name.clone());
+        }
+        let param = Param::Long(name);
+        self.diagnostics.push(Diagnostic { index, token, param, message, kind: DiagnosticKind::Unknown });
+    }
+
+    /// Record a registered parameter given a value it can't hold
+    fn reject_value(&mut self, param: Param, index: usize, token: String) {
+        let message = self.messages.0.invalid_value(&self.display_name(&param));
+        self.reject_value_as(param, index, token, message);
+    }
+
+    /// Record a value refused by a parameter, described by `message`
+    fn reject_value_as(&mut self, param: Param, index: usize, token: String, message: String) {
+        self.invalid.push(param.clone());
+        self.diagnostics.push(Diagnostic { index, token, param, message, kind: DiagnosticKind::InvalidValue });
+    }
+
+    /// Record an opt that ended the args without a value
+    fn missing(&mut self, param: Param, index: usize, token: String) {
+        let message = self.messages.0.missing_value(&self.display_name(&param));
+        self.missing_values.push(param.clone());
+        self.diagnostics.push(Diagnostic { index, token, param, message, kind: DiagnosticKind::MissingValue });
+    }
+
+    /// Get the problems found while parsing, in the order they were met, each pointing at the
+    /// offending arg so it can be shown within the original command line.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Get the parameters and positionals given, in the order they appeared on the command line,
+    /// each with the position of its arg, the program name being 0. The parameters of a cluster
+    /// like `-vx` share a position.
+    ///
+    /// Unlike the values kept per parameter, this tells `-a x -b y` from `-b y -a x`, as tools like
+    /// `find` need.
+    pub fn events(&self) -> &[(usize, ParsedItem)] {
+        &self.events
+    }
+
+    /// Get the args given to the last `parse`, program name included. Returns None unless
+    /// `retain_input` is enabled.
+    pub fn original_args(&self) -> Option<&[String]> {
+        self.original.as_deref()
+    }
+
+    /// Show the command line given to the last `parse` with the arg of `diagnostic` underlined
+    /// on the line below. Returns None unless `retain_input` is enabled.
+    pub fn highlight(&self, diagnostic: &Diagnostic) -> Option<String> {
+        let args = self.original.as_ref()?;
+        let offset: usize = args.iter().take(diagnostic.index).map(|arg| arg.chars().count() + 1).sum();
+        let width = args.get(diagnostic.index).map_or(1, |arg| arg.chars().count().max(1));
+        Some(format!("{}\n{}{}", args.join(" "), " ".repeat(offset), "^".repeat(width)))
+    }
+
+    /// Check args the way `parse` would, without touching the state of this parser. Returns
+    /// every problem found: invalid parameters, opts missing their value, values an opt doesn't
+    /// accept and required parameters that weren't given.
+    pub fn validate_args<A: Iterator<Item = String>>(&self, args: A) -> Result<(), Vec<String>> {
+        let mut parser = self.clone_spec();
+        let _ = parser.parse(args);
+        let problems = parser.problems();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Describe the problems reported by `validate_args`, one line each
+    fn problems(&self) -> Vec<String> {
+        let messages = &self.messages.0;
+        let mut problems: Vec<String> = self.invalid.iter()
+            .map(|param| messages.invalid_parameter(&self.display_name(param)))
+            .collect();
+        problems.extend(self.missing_values.iter()
+            .map(|param| messages.missing_value(&self.display_name(param))));
+        problems.extend(self.invalid_values().iter()
+            .map(|(param, value)| messages.rejected_value(value, &self.display_name(param))));
+        problems.extend(self.validation_failures.iter()
+            .map(|(param, error)| messages.failed_check(&self.display_name(param), error)));
+        problems.extend(self.missing_required().iter()
+            .map(|param| messages.missing_required(&self.display_name(param))));
+        problems
+    }
+
+    /// Check, after parsing, that every required opt and setting was given, as well as the
+    /// positionals declared with `add_positional`, that there aren't more positionals than
+    /// declared or than `max_positionals` allows, that opts with choices or checks hold a
+    /// value they accept, and that the constraints between parameters, like `conflicts`, hold.
+    /// The error lists all of them.
+    #[allow(clippy::result_large_err)]
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let missing = self.missing_required();
+        let missing_positionals: Vec<String> = self.positionals.iter().zip(self.positional_ranges())
+            .filter(|((_, arity), (_, range))| *arity == Arity::One && range.is_empty())
+            .map(|(_, (name, _))| name.to_owned())
+            .collect();
+        let mut max = self.config.max_positionals.unwrap_or(usize::MAX);
+        if !self.positionals.is_empty() && self.positionals.iter().all(|(_, arity)| *arity != Arity::Variadic) {
+            max = max.min(self.positionals.len());
+        }
+        let unexpected: Vec<String> = self.args.iter().skip(max).cloned().collect();
+        let messages = &self.messages.0;
+        let mut invalid: Vec<String> = self.invalid_values().iter().map(|(param, value)| {
+            let choices = self.choices.iter().find(|(choice, _)| choice == param).map(|(_, choices)| choices);
+            messages.invalid_choice(value, &self.display_name(param), choices.map_or(&[], Vec::as_slice))
+        }).collect();
+        invalid.extend(self.validation_failures.iter()
+            .map(|(param, error)| messages.failed_check(&self.display_name(param), error)));
+        let constraints = self.constraint_violations();
+        if missing.is_empty() && missing_positionals.is_empty() && unexpected.is_empty() && invalid.is_empty()
+            && constraints.is_empty() {
+            return Ok(());
+        }
+        let lines = missing.iter().map(|param| messages.missing_required(&self.display_name(param)))
+            .chain(missing_positionals.iter().map(|name| messages.missing_argument(name)))
+            .chain(unexpected.iter().map(|arg| messages.unexpected_argument(arg)))
+            .chain(invalid.iter().chain(constraints.iter()).cloned());
+        let message = lines.map(|line| line + "\n").collect();
+        Err(ValidationError { missing, missing_positionals, unexpected, invalid, constraints, message })
+    }
+
+    /// Describe everything wrong with the parsed args, one line per problem, to be printed
+    /// before exiting. Covers what `validate_args` reports, then the conflicts of
+    /// `check_exclusive` and the failures of `run_validators`. Returns None if all is well.
+    pub fn error_report(&self) -> Option<String> {
+        let mut problems = self.problems();
+        problems.extend(self.check_exclusive().err());
+        problems.extend(self.run_validators().err().unwrap_or_default());
+        if problems.is_empty() {
+            None
+        } else {
+            Some(problems.join("\n"))
+        }
+    }
+
+    /// Name a parameter the way it's written on the command line
+    fn display_name(&self, param: &Param) -> String {
+        match param {
+            Param::Short(ch) => format!("-{}", ch),
+            Param::Long(name) => match self.params.named(name.as_str()) {
+                Some(Value::Setting { .. }) => name.clone(),
+                _ => format!("--{}", name),
+            },
+        }
+    }
+
+    /// Record the events of the positionals given by the arg at `index`, those of the
+    /// parameters being recorded as they are given
+    fn record_positionals(&mut self, index: usize, args_before: usize) {
+        let positionals = self.args[args_before..].iter().map(|arg| (index, ParsedItem::Positional(arg.clone())));
+        self.events.extend(positionals);
+    }
+
+    /// Run the callbacks of the parameters given by an arg, `names` being the names counted for
+    /// each, as given by `count_names`, and `counts` how many times each had been given before it
+    fn run_callbacks(&self, names: &[Vec<Param>], counts: &[usize]) {
+        for (((param, callback), names), &before) in self.callbacks.iter().zip(names).zip(counts) {
+            let count = self.total_count(names);
+            if count == before {
+                continue;
+            }
+            match (callback, self.params.get(param)) {
+                (Callback::Flag(callback), _) => (*callback.borrow_mut())(count),
+                (Callback::Opt(callback), Some(Value::MultiOpt { rhs, .. })) => {
+                    let values = (*rhs.value).borrow().clone();
+                    for value in values.iter().skip(before) {
+                        (*callback.borrow_mut())(value);
+                    }
+                }
+                (Callback::Opt(callback), _) => {
+                    if let Some(value) = self.get_opt(param).or_else(|| self.get_setting(param)) {
+                        (*callback.borrow_mut())(&value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace the `@file` args by the args read from `file`, themselves expanded up to
+    /// `MAX_RESPONSE_FILE_DEPTH` files deep. Each arg comes with its position in the args given,
+    /// which the args read from a file take from their `@file`.
+    fn expand_response_files<'a>(&mut self, args: Vec<(usize, Cow<'a, str>)>,
+                                 depth: usize) -> Vec<(usize, Cow<'a, str>)> {
+        let mut expanded = Vec::with_capacity(args.len());
+        for (origin, arg) in args {
+            let path = match arg.strip_prefix('@') {
+                Some(path) if !path.is_empty() => path,
+                _ => {
+                    expanded.push((origin, arg));
+                    continue;
+                }
+            };
+            if depth == MAX_RESPONSE_FILE_DEPTH {
+                self.warnings.push(self.messages.0.response_file_too_deep(path));
+                expanded.push((origin, arg));
+                continue;
+            }
+            match fs::read_to_string(path).map_err(|err| err.to_string()).and_then(|text| split_shell(&text)) {
+                Ok(words) => {
+                    let words = words.into_iter().map(|word| (origin, Cow::Owned(word))).collect();
+                    expanded.extend(self.expand_response_files(words, depth + 1))
+                }
+                Err(err) => {
+                    self.warnings.push(self.messages.0.unreadable_response_file(path, &err));
+                    expanded.push((origin, arg));
+                }
+            }
+        }
+        expanded
+    }
+
+    /// Rewrite a Windows style `/v` or `/out:file` arg as `-v` or `--out=file`, unless the style
+    /// is `Gnu` or the name of a `Mixed` one isn't registered
+    fn translate_slash(&self, arg: &str) -> Option<String> {
+        if self.config.style == ParserStyle::Gnu {
+            return None;
+        }
+        let body = arg.strip_prefix('/')?;
+        let (name, value) = match body.split_once([':', '=']) {
+            Some((name, value)) => (name, Some(value)),
+            None => (body, None),
+        };
+        let param = param_named(name)?;
+        if self.config.style == ParserStyle::Mixed && !self.params.contains_key(&param) {
+            return None;
+        }
+        let separator = self.config.value_separators.first().copied().unwrap_or('=');
+        Some(match (param, value) {
+            (Param::Short(short), value) => format!("-{}{}", short, value.unwrap_or_default()),
+            (Param::Long(long), Some(value)) => format!("--{}{}{}", long, separator, value),
+            (Param::Long(long), None) => format!("--{}", long),
+        })
+    }
+
+    /// Collect the args that are taken verbatim, without looking for options in them
+    fn collect_trailing<'a, A: Iterator<Item = Cow<'a, str>>>(&mut self, args: A, indices: &[usize]) {
+        let start = self.trailing.len();
+        self.trailing.extend(args.map(Cow::into_owned));
+        self.args.extend(self.trailing[start..].iter().cloned());
+        self.positions.extend(indices.iter().take(self.trailing.len() - start));
+        let positionals = self.trailing[start..].iter().map(|arg| ParsedItem::Positional(arg.clone()));
+        self.events.extend(indices.iter().copied().zip(positionals));
+    }
+
+    /// Get the args taken by each declared positional, as a range of `args`
+    fn positional_ranges(&self) -> Vec<(&str, std::ops::Range<usize>)> {
+        let mut start = 0;
+        self.positionals.iter().map(|(name, arity)| {
+            let end = match arity {
+                Arity::One | Arity::Optional => self.args.len().min(start + 1),
+                Arity::Variadic => self.args.len(),
+            };
+            let range = start.min(end)..end;
+            start = end.max(start + 1);
+            (name.as_str(), range)
+        }).collect()
+    }
+
+    /// Get the arg taken by the positional declared as `name`. Returns None if it wasn't given.
+    pub fn get_positional(&self, name: &str) -> Option<&str> {
+        self.get_positional_all(name).first().map(String::as_str)
+    }
+
+    /// Get every arg taken by the positional declared as `name`, several for one declared with
+    /// `add_positional_multi`.
+    pub fn get_positional_all(&self, name: &str) -> &[String] {
+        match self.positional_ranges().into_iter().find(|(positional, _)| *positional == name) {
+            Some((_, range)) => &self.args[range],
+            None => &[],
+        }
+    }
+
+    /// Get the subcommands registered with `add_subcommand`, in registration order.
+    pub fn subcommands(&self) -> &[Subcommand] {
+        &self.subcommands
+    }
+
+    /// Get the name of the subcommand given on the command line, if any.
+    pub fn which_subcommand(&self) -> Option<&str> {
+        self.subcommand.map(|i| self.subcommands[i].name())
+    }
+
+    /// Get the parser of the subcommand `name` if it was the one given on the command line,
+    /// holding what was parsed after it.
+    pub fn subcommand_matches(&self, name: &str) -> Option<&ArgParser> {
+        self.subcommand.map(|i| &self.subcommands[i])
+            .filter(|subcommand| subcommand.name == name)
+            .map(Subcommand::parser)
+    }
+
+    /// Get the first positional if it is one of the registered `commands`.
+    pub fn command_name(&self) -> Option<&str> {
+        self.args.first().map(String::as_str).filter(|first| self.commands.iter().any(|command| command == first))
+    }
+
+    /// Get the first positional if commands are registered but it isn't one of them.
+    pub fn unknown_command(&self) -> Option<&str> {
+        match self.args.first() {
+            Some(first) if !self.commands.is_empty() && !self.commands.contains(first) => Some(first),
+            _ => None,
+        }
+    }
+
+    /// Get the number of args given to `parse`, without the program name, whether they were
+    /// parameters, their values or positionals. Zero means the program was run without args.
+    ///
+    /// Like `args`, it adds up over several calls to `parse` on the same parser; `reset` and
+    /// `parse_slice` start it over.
+    pub fn total_input_args(&self) -> usize {
+        self.total_input
+    }
+
+    /// Get the positional args along with their position in the args given to `parse`, the
+    /// program name being 0. This includes the args taken verbatim, e.g. after `--`.
+    pub fn positionals_with_index(&self) -> Vec<(usize, String)> {
+        self.positions.iter().cloned().zip(self.args.iter().cloned()).collect()
+    }
+
+    /// Builder method for naming the args after the `--` terminator
+    ///
+    /// The trailing args can then be retrieved with `get_opt_all(name)`, like the values of a
+    /// repeated opt. The name itself isn't accepted as a parameter on the command line.
+    pub fn trailing_name(mut self, name: &str) -> Self {
+        if !name.is_empty() {
+            self.register(Param::Long(name.to_owned()), Value::Trailing);
+        }
+        self
+    }
+
+    /// Builder method for taking every arg after the `n`th positional verbatim
+    ///
+    /// Once `n` positionals have been seen, the rest of the args are not parsed for options but
+    /// collected as they are, the same way as the args after `--`. This suits tools like
+    /// `sudo user cmd -x` where `-x` belongs to `cmd`.
+    pub fn verbatim_after_positional(mut self, n: usize) -> Self {
+        self.config.verbatim_after = Some(n);
+        self
+    }
+
+    /// Builder method for taking the first positional and every arg after it verbatim, as the
+    /// command of wrappers like `env`, `sudo` or `time`
+    ///
+    /// In `time -v ls -la`, `-v` is a parameter while `ls -la` is kept in `trailing_args` and
+    /// split by `command`.
+    pub fn stop_at_first_positional(mut self, enabled: bool) -> Self {
+        self.config.stop_at_first_positional = enabled;
+        self
+    }
+
+    /// Builder method for ending the options at `marker`, like `--` does
+    ///
+    /// `--` still works as well. With `end_marker("END")`, the args after `END` are collected as
+    /// they are, the marker itself being dropped.
+    pub fn end_marker(mut self, marker: &str) -> Self {
+        self.config.end_marker = Some(marker.to_owned());
+        self
+    }
+
+    /// Builder method for only taking the values of long opts as `--opt=value`
+    ///
+    /// By default `--output result.txt` also sets `output`, like getopt_long does; with strict
+    /// long values `result.txt` is left as a positional and `output` is found without a value.
+    pub fn strict_long_values(mut self, enabled: bool) -> Self {
+        self.config.strict_long_values = enabled;
+        self
+    }
+
+    /// Builder method for making `parse` stop at the first unknown parameter or other problem,
+    /// as `parse_strict` does, whatever the `unknown_severity`. The args left are kept in
+    /// `skipped_args`.
+    ///
+    /// Positionals are then refused past `max_positionals`, so `strict(true).max_positionals(0)`
+    /// accepts none.
+    pub fn strict(mut self, enabled: bool) -> Self {
+        self.config.strict = enabled;
+        self
+    }
+
+    /// Builder method for accepting any unambiguous prefix of a long name in its place, as
+    /// getopt_long does, e.g. `--verb` for `--verbose`. This is disabled by default.
+    ///
+    /// A prefix of several parameters is an error naming them, like "Ambiguous parameter
+    /// '--ver', could be '--verbose' or '--version'". Disabled, only full names are recognized.
+    pub fn allow_abbreviations(mut self, enabled: bool) -> Self {
+        self.config.abbreviations = enabled;
+        self
+    }
+
+    /// Builder method for replacing each `@file` arg by the args written in `file`, as compilers
+    /// and linkers do to get around command line length limits
+    ///
+    /// The file is split like `parse_str` splits a line, newlines separating args like spaces.
+    /// Files can name other files, up to `MAX_RESPONSE_FILE_DEPTH` deep. An `@file` that can't be
+    /// read is kept as an arg and recorded in `warnings`. Positions, as in `diagnostics`, count
+    /// the args once expanded.
+    pub fn response_files(mut self, enabled: bool) -> Self {
+        self.config.response_files = enabled;
+        self
+    }
+
+    /// Builder method for recognizing the Windows `/v` and `/out:file` forms of parameters,
+    /// instead of or as well as the `-v` and `--out=file` ones, e.g. `style(ParserStyle::Mixed)`
+    ///
+    /// A one-character name is short and a longer one long, as in `add_flag`; the value can follow
+    /// a `:` or a `=`.
+    pub fn style(mut self, style: ParserStyle) -> Self {
+        self.config.style = style;
+        self
+    }
+
+    /// Builder method for keeping the args given to `parse`, so `original_args` and `highlight`
+    /// can show the whole command line
+    pub fn retain_input(mut self, enabled: bool) -> Self {
+        self.config.retain_input = enabled;
+        self
+    }
+
+    /// Builder method for matching long names whatever their case, so `--COLOR=Always` sets the
+    /// `color` opt to `Always`, and `LEVEL=1` the `level` setting. Values and short names keep
+    /// their case: `-v` and `-V` differ.
+    pub fn case_insensitive_long(mut self, enabled: bool) -> Self {
+        self.config.case_insensitive_long = enabled;
+        self
+    }
+
+    /// Builder method for taking `_` and `-` as the same within long names and settings, so
+    /// `--log_level=debug` sets the `log-level` opt, and `log-level=debug` the `log_level`
+    /// setting. Combined with `case_insensitive_long`, `--Log_Level` does too. A name matching
+    /// several registered ones, like `--Dry_Run` with both `dry-run` and `dry_run`, goes to the
+    /// first one registered.
+    pub fn underscores_as_dashes(mut self, enabled: bool) -> Self {
+        self.config.underscores_as_dashes = enabled;
+        self
+    }
+
+    /// Builder method for collecting the unknown parameters matching a glob, like `--x-*`, in
+    /// `unknown_args` instead of reporting them as invalid
+    ///
+    /// In the glob, `*` stands for any run of characters and `?` for any single one. It's matched
+    /// against a long parameter or setting as it was given, value included, as in `--x-foo=1`,
+    /// and against each unknown short parameter of a cluster on its own, as in `-x`.
+    pub fn collect_unknown_matching(mut self, pattern: &str) -> Self {
+        self.config.unknown_patterns.push(pattern.to_owned());
+        self
+    }
+
+    /// Get the unknown parameters collected by `collect_unknown_matching`, in order.
+    pub fn unknown_args(&self) -> &[String] {
+        &self.unknown
+    }
+
+    /// Builder method for keeping every unknown parameter as it was given in `unparsed`,
+    /// instead of reporting it as invalid, so that a wrapper can handle its own parameters and
+    /// pass the others on to the program it runs
+    ///
+    /// `--inner=1` and `key=value` are kept whole. In a cluster like `-vZfoo`, the first unknown
+    /// short and the rest of the cluster are kept together, as `-Zfoo`, since the rest may be its
+    /// value. A value given in the next arg, as in `--inner 1`, can't be told from a positional
+    /// and stays one.
+    pub fn collect_unknown(mut self, enabled: bool) -> Self {
+        self.config.collect_unknown = enabled;
+        self
+    }
+
+    /// Get the unknown parameters kept by `collect_unknown`, or `collect_unknown_matching`, as
+    /// they were given and in the order they were, like `unknown_args`.
+    pub fn unparsed(&self) -> &[String] {
+        &self.unknown
+    }
+
+    /// Builder method for stopping the parse once `max` invalid parameters have been found
+    ///
+    /// The args after the one that reached the limit are not processed; they can be retrieved
+    /// with `skipped_args`.
+    pub fn max_invalid(mut self, max: usize) -> Self {
+        self.config.max_invalid = Some(max);
+        self
+    }
+
+    /// Builder method for recording a warning each time an opt's value is overridden
+    ///
+    /// The last value given for an opt always wins; with this enabled, repeating an opt also
+    /// pushes a warning like `--output overridden: 'a' replaced by 'b'` to `warnings`.
+    pub fn warn_on_override(mut self, enabled: bool) -> Self {
+        self.config.warn_on_override = enabled;
+        self
+    }
+
+    /// Builder method for choosing how unknown parameters are reported
+    ///
+    /// With `Severity::Warn`, they go to `warnings` instead of making `found_invalid` fail.
+    /// Misused parameters, like a flag given a value, are still invalid.
+    pub fn unknown_severity(mut self, severity: Severity) -> Self {
+        self.config.unknown_severity = severity;
+        self
+    }
+
+    /// Get the toggles set by the builder methods, like `max_invalid` or `value_separators`
+    pub fn config(&self) -> &ParserConfig {
+        &self.config
+    }
+
+    /// Record that the opt holding `value` had its `previous` value overridden
+    fn warn_override(&mut self, value: &Rc<RefCell<String>>, previous: String) {
+        let mut name = None;
+        for (param, param_value) in self.params.iter() {
+            if let Value::Opt { rhs, .. } = param_value {
+                if Rc::ptr_eq(&rhs.value, value) {
+                    match param {
+                        Param::Long(long) => name = Some(format!("--{}", long)),
+                        Param::Short(short) if name.is_none() => name = Some(format!("-{}", short)),
+                        Param::Short(_) => (),
+                    }
+                }
+            }
+        }
+        let warning = self.messages.0.overridden(&name.unwrap_or_default(), &previous, &(**value).borrow());
+        self.warnings.push(warning);
+    }
+
+    /// Builder method for expanding `${name}` references in opt and setting values
+    ///
+    /// After parsing, a reference to another found opt or setting (by long or short name) is
+    /// replaced by its value, so `--output=foo --log=${output}.log` sets `log` to `foo.log`.
+    /// Unknown and cyclic references are left as they are.
+    pub fn expand_references(mut self, enabled: bool) -> Self {
+        self.config.expand_references = enabled;
+        self
+    }
+
+    /// Get the value cell a `${name}` reference points to, if it names a found opt or setting
+    fn reference(&self, name: &str) -> Option<Rc<RefCell<String>>> {
+        let mut chars = name.chars();
+        let value = match (chars.next(), chars.next()) {
+            (Some(ch), None) => self.params.named(ch).or_else(|| self.params.named(name)),
+            _ => self.params.named(name),
+        };
+        match value {
+            Some(Value::Opt { rhs, found, .. }) if *(**found).borrow() => Some(rhs.value.clone()),
+            Some(Value::Setting { rhs, found, .. })
+                if *(**found).borrow() || !(*rhs.value).borrow().is_empty() => Some(rhs.value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Expand the references in `value`, skipping the ones to cells already being expanded
+    fn expand_value(&self, value: &str, stack: &mut Vec<Rc<RefCell<String>>>) -> String {
+        let mut output = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find("${") {
+            let reference = &rest[start..];
+            let end = match reference.find('}') {
+                Some(end) => end,
+                None => break,
+            };
+            output.push_str(&rest[..start]);
+            match self.reference(&reference[2..end]) {
+                Some(cell) if !stack.iter().any(|seen| Rc::ptr_eq(seen, &cell)) => {
+                    let raw = (*cell).borrow().clone();
+                    stack.push(cell);
+                    output += &self.expand_value(&raw, stack);
+                    stack.pop();
+                }
+                _ => output.push_str(&reference[..=end]),
+            }
+            rest = &reference[end + 1..];
+        }
+        output.push_str(rest);
+        output
+    }
+
+    /// Expand the references in every opt and setting value
+    fn expand_values(&mut self) {
+        let mut cells: Vec<Rc<RefCell<String>>> = Vec::new();
+        for value in self.params.values() {
+            if let Value::Opt { rhs, .. } | Value::Setting { rhs, .. } = value {
+                if !cells.iter().any(|cell| Rc::ptr_eq(cell, &rhs.value)) {
+                    cells.push(rhs.value.clone());
+                }
+            }
+        }
+        // Expand everything from the parsed values before writing any result back
+        let expanded: Vec<Option<String>> = cells.iter()
+            .map(|cell| {
+                let raw = (**cell).borrow();
+                if raw.contains("${") {
+                    Some(self.expand_value(&raw, &mut vec![cell.clone()]))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for (cell, value) in cells.iter().zip(expanded) {
+            if let Some(value) = value {
+                *(**cell).borrow_mut() = value;
+            }
+        }
+    }
+
+    /// Look a parameter up by any of the ways of naming it
+    fn get_param<P: ParamName>(&self, name: P) -> Option<&Value> {
+        self.params.named(name)
+    }
+
+    /// Get the number of times a flag, opt or setting has been found after parsing.
+    ///
+    /// The name can be given owned or borrowed, e.g. `count('v')`, `count(&'v')`, `count("verbose")`
+    /// or `count(String::from("verbose"))`.
+    pub fn count<P: ParamName>(&self, name: P) -> usize {
+        match self.get_param(name) {
+            Some(Value::Flag(rhs)) => rhs.occurrences,
+            Some(Value::Opt { rhs, .. }) => rhs.occurrences,
+            Some(Value::Setting { rhs, .. }) => rhs.occurrences,
+            Some(Value::KeyVal { rhs, .. }) => rhs.occurrences,
+            Some(Value::MultiOpt { rhs, .. }) => (*rhs.value).borrow().len(),
+            _ => 0,
+        }
+    }
+
+    /// Get the number of times a parameter has been found under any of its names, e.g. 4 for
+    /// `-vvv --verbose`, where `count('v')` is 3 and `count("verbose")` is 1.
+    pub fn get_count<P: ParamName>(&self, name: P) -> usize {
+        self.total_count(&self.count_names(name))
+    }
+
+    /// Get the names whose counts add up to the total count of `name`: all of its aliases, or only
+    /// `name` for a multi-valued opt, whose count is already its number of values whatever the names
+    fn count_names<P: ParamName>(&self, name: P) -> Vec<Param> {
+        let storage = match self.params.named_key_value(name) {
+            Some((param, Value::MultiOpt { .. })) => return vec![param.clone()],
+            Some((_, value)) => value.storage(),
+            None => return Vec::new(),
+        };
+        self.params.iter().filter(|(_, value)| value.storage() == storage).map(|(alias, _)| alias.clone()).collect()
+    }
+
+    /// Add up the counts of `names`, as given by `count_names`
+    fn total_count(&self, names: &[Param]) -> usize {
+        names.iter().map(|name| self.count(name)).sum()
+    }
+
+    /// Get the number of distinct parameters given on the command line, each parameter counting
+    /// once however many times, and under however many aliases, it was given.
+    pub fn distinct_found(&self) -> usize {
+        let mut storages = Vec::new();
+        for (param, value) in self.params.iter() {
+            if self.count(param) > 0 && !storages.contains(&value.storage()) {
+                storages.push(value.storage());
+            }
+        }
+        storages.len()
+    }
+
+    /// Check if a flag or opt has been found after initialization. Like `count`, the name can
+    /// be given owned or borrowed.
+    pub fn found<P: ParamName>(&self, name: P) -> bool {
+        match self.get_param(name) {
+            Some(Value::Flag(rhs)) => *(*rhs.value).borrow(),
+            Some(Value::Opt { found, .. }) => *(**found).borrow(),
+            Some(Value::Setting { found, .. }) => *(**found).borrow(),
+            Some(Value::KeyVal { found, .. }) => *(**found).borrow(),
+            Some(Value::MultiOpt { rhs, .. }) => !(*rhs.value).borrow().is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Check if a flag or opt has been found by its long name, e.g. `found_long("verbose")`.
+    pub fn found_long(&self, name: &str) -> bool {
+        self.found(name)
+    }
+
+    /// Check if a flag or opt has been found by its short name, e.g. `found_short('v')`.
+    pub fn found_short(&self, ch: char) -> bool {
+        self.found(ch)
+    }
+
+    /// Check if a flag registered within a vendor's group has been found after initialization.
+    pub fn found_vendor(&self, vendor: &str, flag: &str) -> bool {
+        self.found(&*vendor_name(vendor, flag))
+    }
+
+    /// Modify the state of a flag. Use `true` if the flag is to be enabled. Use `false` to
+    /// disable its use.
+    pub fn flag<P: ParamName>(&mut self, flag: P) -> RefMut<'_, bool> {
+        if let Some(Value::Flag(rhs)) = self.get_param(flag) {
+            return (*rhs.value).borrow_mut();
+        }
+        self.garbage.0.borrow_mut()
+    }
+
+    /// Modify the state value of an opt. Use `Some(String)` to set if the opt is to be enabled and
+    /// has been assigned a value from `String`. Use `None` to disable the opt's use.
+    pub fn opt<P: ParamName>(&mut self, opt: P) -> RefMut<'_, String> {
+        if let Some(Value::Opt { rhs, .. }) = self.get_param(opt) {
+            return (*rhs.value).borrow_mut();
+        }
+        self.garbage.1.borrow_mut()
+    }
+
+    /// Get the value of an Opt. If it has been set or defaulted, it will return a `Some(String)`
+    /// value otherwise it will return None.
+    ///
+    /// The value is cloned out of the parser; use `map_opt` to only borrow it. An opt registered
+    /// with `add_opt_default` gives its default until it is found; `value_source` tells the two
+    /// apart.
+    pub fn get_opt<P: ParamName>(&self, opt: P) -> Option<String> {
+        self.map_opt(opt, str::to_owned)
+    }
+
+    /// Call `f` with the value of an Opt, without cloning it, and return its result. Returns
+    /// None like `get_opt` does. The value stays borrowed until `f` returns.
+    pub fn map_opt<P: ParamName, R, F: FnOnce(&str) -> R>(&self, opt: P, f: F) -> Option<R> {
+        if let Some(Value::Opt { rhs, found, hybrid, default, .. }) = self.get_param(opt) {
+            // A hybrid opt given on its own has no value
+            if *(**found).borrow() && !(*hybrid && (*rhs.value).borrow().is_empty()) {
+                return Some(f(&(*rhs.value).borrow()));
+            }
+            if !*(**found).borrow() {
+                return default.as_deref().map(f);
+            }
+        }
+        None
+    }
+
+    /// Get the value of an Opt by its long name. See `get_opt`.
+    pub fn get_opt_long(&self, name: &str) -> Option<String> {
+        self.get_opt(name)
+    }
+
+    /// Get the value of the first Opt found among `names`, by long name, e.g. the new and old
+    /// names of a renamed opt. Returns None if none of them was found.
+    pub fn get_opt_first_of(&self, names: &[&str]) -> Option<String> {
+        names.iter().find_map(|name| self.get_opt(*name))
+    }
+
+    /// Get the value of an Opt by its short name. See `get_opt`.
+    pub fn get_opt_short(&self, ch: char) -> Option<String> {
+        self.get_opt(ch)
+    }
+
+    /// Get every value of an Opt, as collected by a multi-valued opt. A keyval opt gives its pairs
+    /// as `key=value` strings, and the name set with `trailing_name` gives the args after `--`.
+    /// Returns None if nothing was found. See `get_opt_all_positioned` for where each was given.
+    pub fn get_opt_all<P: ParamName>(&self, opt: P) -> Option<Vec<String>> {
+        match self.get_param(opt) {
+            Some(Value::Opt { rhs, found, .. }) if *(**found).borrow() => {
+                Some(vec![(*rhs.value).borrow().clone()])
+            }
+            Some(Value::KeyVal { rhs, found }) if *(**found).borrow() => {
+                Some((*rhs.value).borrow().iter().map(|(key, value)| format!("{}={}", key, value)).collect())
+            }
+            Some(Value::MultiOpt { rhs, .. }) if !(*rhs.value).borrow().is_empty() => {
+                Some((*rhs.value).borrow().clone())
+            }
+            Some(Value::Trailing) if !self.trailing.is_empty() => Some(self.trailing.clone()),
+            _ => None,
+        }
+    }
+
+    /// Get the values of an Opt split into a list, as in `--features=a,b,c`, every occurrence of
+    /// a multi-valued opt being split in turn. It is empty if the opt wasn't found.
+    ///
+    /// Values are split on commas unless another delimiter was set with `delimiter`. A backslash
+    /// keeps the delimiter, or another backslash, in the item, and empty items are left out, so
+    /// `a,,b\,c` gives `a` and `b,c`.
+    pub fn get_opt_list<P: ParamName>(&self, name: P) -> Vec<String> {
+        let (param, storage) = match name.param_ref() {
+            ParamRef::Short(ch) => (Param::Short(ch), self.params.named(ch).and_then(Value::storage)),
+            ParamRef::Long(long) => (Param::Long(long.to_owned()), self.params.named(long).and_then(Value::storage)),
+        };
+        let delimiter = self.delimiters.iter()
+            .find(|(alias, _)| self.params.get(*alias).and_then(Value::storage) == storage)
+            .map_or(',', |(_, &delimiter)| delimiter);
+        self.get_opt_all(&param).unwrap_or_default().iter()
+            .flat_map(|value| split_delimited(value, delimiter))
+            .collect()
+    }
+
+    /// Get every value given to an opt or setting on the command line, under any of its names,
+    /// each paired with the position of the arg naming it, the program name being 0. Unlike
+    /// `get_opt`, which keeps the last one, this knows which value applied to which args, as in
+    /// `-o a.txt file1 -o b.txt file2`. Returns None if none were given or it isn't found anymore.
+    pub fn get_opt_all_positioned<P: ParamName>(&self, name: P) -> Option<Vec<(usize, String)>> {
+        let name = name.param_ref();
+        let storage = self.get_param(name).and_then(Value::storage).filter(|_| self.found(name))?;
+        let alias = |param: &Param| self.params.get(param).and_then(Value::storage) == Some(storage);
+        let positioned: Vec<(usize, String)> = self.events.iter()
+            .filter_map(|(index, event)| match event {
+                ParsedItem::Opt(param, value) if alias(param) => Some((*index, value.clone())),
+                ParsedItem::Setting(key, value) if alias(&Param::Long(key.clone())) => Some((*index, value.clone())),
+                _ => None,
+            })
+            .collect();
+        Some(positioned).filter(|positioned| !positioned.is_empty())
+    }
+
+    /// Get every value of a Setting in the order they were given. A setting registered with
+    /// `add_setting_multi` gives each occurrence, others just their value. Returns None if it
+    /// wasn't found.
+    pub fn get_setting_all<P: ParamName>(&self, setting: P) -> Option<Vec<String>> {
+        match self.get_param(setting) {
+            Some(Value::Setting { values: Some(values), found, .. }) if *(**found).borrow() => {
+                Some((**values).borrow().clone())
+            }
+            Some(Value::Setting { rhs, found, .. }) if *(**found).borrow() => {
+                Some(vec![(*rhs.value).borrow().clone()])
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the value, the number of occurrences and whether it was found of an Opt, all in a
+    /// single lookup. Returns None if no such opt is registered.
+    pub fn opt_info<P: ParamName>(&self, opt: P) -> Option<(String, usize, bool)> {
+        if let Some(Value::Opt { rhs, found, .. }) = self.get_param(opt) {
+            return Some(((*rhs.value).borrow().clone(), rhs.occurrences, *(**found).borrow()));
+        }
+        None
+    }
+
+    /// Get the default an opt or setting was registered with, whether or not it was given.
+    pub fn get_default<P: ParamName>(&self, name: P) -> Option<&str> {
+        match self.get_param(name) {
+            Some(Value::Opt { default, .. }) | Some(Value::Setting { default, .. }) => default.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Check if the current value of an opt or setting is the default it was registered with,
+    /// i.e. it was given neither on the command line nor through the environment or a config
+    /// file.
+    pub fn is_default<P: ParamName>(&self, name: P) -> bool {
+        self.value_source(name) == ValueSource::Default
+    }
+
+    /// Tell where the current value of a parameter comes from.
+    pub fn value_source<P: ParamName>(&self, opt: P) -> ValueSource {
+        match self.get_param(opt) {
+            Some(value @ Value::Flag(Rhs { value: found, .. }))
+            | Some(value @ Value::Opt { found, .. })
+            | Some(value @ Value::Setting { found, .. }) if *(**found).borrow() => {
+                if self.is_from_env(value) {
+                    ValueSource::Env
+                } else if self.is_from_config(value) {
+                    ValueSource::Config
+                } else {
+                    ValueSource::Cli
+                }
+            }
+            Some(Value::KeyVal { found, .. }) if *(**found).borrow() => ValueSource::Cli,
+            Some(Value::MultiOpt { rhs, .. }) if !(*rhs.value).borrow().is_empty() => ValueSource::Cli,
+            Some(Value::Opt { default: Some(_), .. })
+            | Some(Value::Setting { default: Some(_), .. }) => ValueSource::Default,
+            _ => ValueSource::Unset,
+        }
+    }
+
+    /// Get the `key=value` pairs of a keyval Opt in the order they were given. If none were
+    /// found, it will return None.
+    pub fn get_opt_keyvals<P: ParamName>(&self, opt: P) -> Option<Vec<(String, String)>> {
+        if let Some(Value::KeyVal { rhs, found }) = self.get_param(opt) {
+            if *(**found).borrow() {
+                return Some((*rhs.value).borrow().clone());
+            }
+        }
+        None
+    }
+
+    /// Get the value of an Setting. If it has been set or defaulted, it will return a `Some(String)`
+    /// value otherwise it will return None.
+    pub fn get_setting<P: ParamName>(&self, setting: P) -> Option<String> {
+        if let Some(Value::Setting { rhs, found, .. }) = self.get_param(setting) {
+            // A setting registered with a default holds a non-empty value before being found
+            if *(**found).borrow() || !(*rhs.value).borrow().is_empty() {
+                return Some((*rhs.value).borrow().clone());
+            }
+        }
+        None
+    }
+
+    /// Get the value of an Opt as a number of bytes, parsed from a human-readable size such as
+    /// `500`, `10M` or `1.5G`. See `parse_human_readable` for the accepted suffixes.
+    pub fn get_opt_bytes<P: ParamName>(&self, opt: P) -> Option<u64> {
+        self.get_opt(opt).and_then(|value| parse_human_readable(&value))
+    }
+
+    /// Get the value of an Opt as a range of numbers, as in `--lines=5-10`. See `parse_range` for
+    /// the accepted forms; returns None if it's unset or malformed.
+    pub fn get_opt_range<P: ParamName>(&self, opt: P) -> Option<(Option<u64>, Option<u64>)> {
+        self.get_opt(opt).and_then(|value| parse_range(&value))
+    }
+
+    /// Get the value of a Setting interpreted as a boolean. Accepts `true`/`false`, `yes`/`no`,
+    /// `on`/`off` and `1`/`0` in any case; returns None if it's unset or not a boolean.
+    pub fn get_setting_bool<P: ParamName>(&self, setting: P) -> Option<bool> {
+        self.get_setting(setting).and_then(|value| parse_bool(&value))
+    }
+
+    /// Get the value of an Opt parsed as `T`, e.g. `get_opt_as::<u16, _>("port")`. Returns None
+    /// if it wasn't found, and the parse error if its value isn't a valid `T`.
+    pub fn get_opt_as<T: FromStr, P: ParamName>(&self, opt: P) -> Option<Result<T, T::Err>> {
+        self.map_opt(opt, str::parse)
+    }
+
+    /// Get the value of an Opt parsed as `T`, telling apart an opt that wasn't found, `Ok(None)`,
+    /// from one whose value isn't a valid `T`, an error like `Invalid value 'abc' for '--port'`.
+    pub fn try_get_opt_as<T: FromStr, P: ParamName>(&self, opt: P) -> Result<Option<T>, String> {
+        let param = opt.param_ref();
+        match self.get_opt_as::<T, _>(param) {
+            Some(Ok(value)) => Ok(Some(value)),
+            Some(Err(_)) => {
+                let value = self.get_opt(param).unwrap_or_default();
+                let param = match param {
+                    ParamRef::Short(ch) => Param::Short(ch),
+                    ParamRef::Long(long) => Param::Long(long.to_owned()),
+                };
+                Err(self.messages.0.rejected_value(&value, &self.display_name(&param)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Get the value of an Opt parsed as `T`, e.g. `parse_opt::<u16, _>("port")`, with an error
+    /// telling whether it is missing or invalid, like `Invalid value 'abc' for '--port': invalid
+    /// digit found in string`.
+    pub fn parse_opt<T: FromStr, P: ParamName>(&self, opt: P) -> Result<T, ParseValueError>
+        where T::Err: std::fmt::Display
+    {
+        let param = opt.param_ref();
+        let value = self.get_opt(param);
+        self.parse_value(param, value)
+    }
+
+    /// Get the value of a Setting parsed as `T`, with an error telling whether it is unset or
+    /// invalid, like `parse_opt` does.
+    pub fn parse_setting<T: FromStr, P: ParamName>(&self, setting: P) -> Result<T, ParseValueError>
+        where T::Err: std::fmt::Display
+    {
+        let param = setting.param_ref();
+        let value = self.get_setting(param);
+        self.parse_value(param, value)
+    }
+
+    /// Parse the value of `param` for `parse_opt` and `parse_setting`
+    fn parse_value<T: FromStr>(&self, param: ParamRef, value: Option<String>) -> Result<T, ParseValueError>
+        where T::Err: std::fmt::Display
+    {
+        let name = match param {
+            ParamRef::Short(ch) => self.display_name(&Param::Short(ch)),
+            ParamRef::Long(long) => self.display_name(&Param::Long(long.to_owned())),
+        };
+        let value = value.ok_or_else(|| ParseValueError::Missing(name.clone()))?;
+        value.parse().map_err(|error: T::Err| ParseValueError::Invalid { name, value, error: error.to_string() })
+    }
+
+    /// Get the value of a Setting parsed as `T`, e.g. `offset=-5` as `-5i64`. Returns None if
+    /// it's unset, and the parse error if its value isn't a valid `T`.
+    pub fn get_setting_as<T: FromStr, P: ParamName>(&self, setting: P) -> Option<Result<T, T::Err>> {
+        self.get_setting(setting).map(|value| value.parse())
+    }
+
+    /// Get the args that were given after the `--` terminator, or after the `end_marker` or
+    /// `verbatim_after` positionals, verbatim and in order.
+    ///
+    /// They are also appended to `args`; this is only them, ready to forward to a child process
+    /// as in `cargo run -- <child args>`.
+    pub fn trailing_args(&self) -> &[String] {
+        &self.trailing
+    }
+
+    /// Get the required opts and settings that haven't been found yet, in registration order.
+    ///
+    /// This can be called between partial parses, e.g. to prompt for each missing parameter.
+    pub fn missing_required(&self) -> Vec<Param> {
+        self.required.iter().filter(|param| !self.found(*param)).cloned().collect()
+    }
+
+    /// Get the opts and settings registered with `add_opt_choices` or `add_setting_choices` that
+    /// were given a value they don't accept, along with that value, in registration order.
+    pub fn invalid_values(&self) -> Vec<(Param, String)> {
+        self.choices.iter()
+            .filter_map(|(param, choices)| {
+                self.get_opt(param).or_else(|| self.get_setting(param)).map(|value| (param, choices, value))
+            })
+            .filter(|(_, choices, value)| !choices.contains(value))
+            .map(|(param, _, value)| (param.clone(), value))
+            .collect()
+    }
+
+    /// Get the opts registered with `add_opt_validated`, and the settings registered with
+    /// `add_setting_typed`, whose value failed its check, along with the error, as found by the
+    /// last parse.
+    pub fn validation_failures(&self) -> &[(Param, String)] {
+        &self.validation_failures
+    }
+
+    /// Get the opts that ended the args without a value, e.g. `--output` or `-o` as the last
+    /// arg, unless they were registered with `add_opt_implicit`.
+    pub fn missing_values(&self) -> &[Param] {
+        &self.missing_values
+    }
+
+    /// Group the registered parameters with their aliases, ordered by name
+    fn entries(&self) -> Vec<Entry<'_>> {
+        let mut entries: Vec<Entry> = Vec::new();
+        // The index in `entries` of the entry of each storage, aliases sharing theirs
+        let mut indices: HashMap<*const (), usize> = HashMap::new();
+        for (param, value) in self.params.iter() {
+            let storage = match value.storage() {
+                Some(storage) => storage,
+                None => continue,
+            };
+            let index = *indices.entry(storage).or_insert_with(|| {
+                entries.push(Entry { shorts: Vec::new(), longs: Vec::new(), value });
+                entries.len() - 1
+            });
+            match param {
+                Param::Short(short) => entries[index].shorts.push(*short),
+                Param::Long(long) => entries[index].longs.push(long),
+            }
+        }
+        for entry in entries.iter_mut() {
+            entry.shorts.sort();
+            entry.longs.sort();
+        }
+        entries.sort_by_key(|entry| entry.name());
+        entries
+    }
+
+    /// Group the registered parameters with their aliases like `entries`, but in the order they
+    /// were registered in, each at the place of its first name
+    fn registered_entries(&self) -> Vec<Entry<'_>> {
+        let mut entries = self.entries();
+        entries.sort_by_key(|entry| self.order.iter().position(|param| match param {
+            Param::Short(short) => entry.shorts.contains(short),
+            Param::Long(long) => entry.longs.contains(&long.as_str()),
+        }));
+        entries
+    }
+
+    /// Get the description given to a parameter under any of its aliases
+    fn description(&self, entry: &Entry) -> Option<&str> {
+        entry.longs.iter().filter_map(|long| self.descriptions.named(*long))
+            .chain(entry.shorts.iter().filter_map(|short| self.descriptions.named(short)))
+            .map(|help| help.as_str())
+            .next()
+    }
+
+    /// Render the usage text of the registered parameters, in sections for flags, opts and
+    /// settings, each listing its parameters in the order they were registered in. The aliases of
+    /// a parameter share a line, followed by its description and its default value, if any:
+    ///
+    /// ```text
+    /// Usage: prog [FLAGS] [OPTIONS]
+    ///
+    /// Flags:
+    ///   -v, --verbose        Print more details
+    ///
+    /// Options:
+    ///   -o, --output <VALUE> Write to a file [default: out.txt]
+    /// ```
+    pub fn usage(&self, program: &str) -> String {
+        let sections = self.usage_sections();
+        let width = sections.iter()
+            .flat_map(|section| section.lines.iter())
+            .map(|(names, _)| names.len())
+            .max()
+            .unwrap_or(0);
+        let mut usage = self.synopsis(self.messages.0.usage(program), &sections);
+        usage.push('\n');
+        for section in sections.iter().filter(|section| !section.lines.is_empty()) {
+            usage += &format!("\n{}:\n", section.title);
+            for (names, help) in section.lines.iter() {
+                match help {
+                    Some(help) => usage += &format!("  {:width$} {}\n", names, help, width = width),
+                    None => usage += &format!("  {}\n", names),
+                }
+            }
+        }
+        usage
+    }
+
+    /// Append to `start` the sections which have parameters, then the declared positionals,
+    /// as in `prog [FLAGS] [OPTIONS] <input>`. Groups count as options.
+    fn synopsis(&self, mut start: String, sections: &UsageSections) -> String {
+        for (index, section) in sections.iter().enumerate().filter(|(_, section)| !section.group) {
+            // The options section is listed if it, or any group, has parameters
+            let options = index == 1 && sections.iter().any(|section| section.group && !section.lines.is_empty());
+            if !section.lines.is_empty() || options {
+                start += &format!(" [{}]", section.title.to_uppercase());
+            }
+        }
+        for (name, arity) in self.positionals.iter() {
+            match arity {
+                Arity::One => start += &format!(" <{}>", name),
+                Arity::Optional => start += &format!(" [{}]", name),
+                Arity::Variadic => start += &format!(" [{}...]", name),
+            }
+        }
+        start
+    }
+
+    /// Group the registered parameters in the `USAGE_SECTIONS`, or those registered with `group`,
+    /// each line with the names of a parameter and its help
+    fn usage_sections(&self) -> UsageSections {
+        let mut sections: UsageSections = USAGE_SECTIONS.iter()
+            .map(|&section| self.messages.0.section_title(section))
+            .map(|title| UsageSection { title, group: false, lines: Vec::new() })
+            .chain(self.groups.iter()
+                .map(|(title, _)| UsageSection { title: title.clone(), group: true, lines: Vec::new() }))
+            .collect();
+        let mut grouped = Vec::new();
+        for entry in self.registered_entries().into_iter().filter(|entry| !self.is_hidden(entry.value)) {
+            let deprecated = |param: Param| self.deprecated.iter().any(|(old, _)| *old == param);
+            let shorts = entry.shorts.iter()
+                .filter(|&&short| !deprecated(Param::Short(short)))
+                .map(|short| format!("-{}", short));
+            let longs = entry.longs.iter()
+                .filter(|long| !deprecated(Param::Long((**long).to_owned())))
+                .map(|long| if self.negatable.iter().any(|flag| flag == long) {
+                    format!("--[no-]{}", long)
+                } else {
+                    format!("--{}", long)
+                });
+            let names = shorts.chain(longs)
+                .collect::<Vec<String>>()
+                .join(", ");
+            let counted = entry.shorts.iter().map(char::to_string)
+                .chain(entry.longs.iter().map(|long| (*long).to_owned()))
+                .any(|name| self.counted.contains(&name));
+            let names = if counted { names + "..." } else { names };
+            let (section, names) = match entry.value {
+                Value::Flag(_) => (0, names),
+                Value::Opt { .. } | Value::MultiOpt { .. } => (1, names + " <VALUE>"),
+                Value::KeyVal { .. } => (1, names + " <KEY=VALUE>"),
+                Value::Setting { .. } => (2, format!("{}=<VALUE>", entry.name())),
+                Value::NegatedOpt { .. } | Value::Trailing => continue,
+            };
+            let default = match entry.value {
+                Value::Opt { default: Some(default), .. } | Value::Setting { default: Some(default), .. } => {
+                    Some(self.messages.0.default_value(default))
+                }
+                _ => None,
+            };
+            let help = match (self.description(&entry), default) {
+                (Some(help), Some(default)) => Some(format!("{} {}", help, default)),
+                (help, default) => help.map(String::from).or(default),
+            };
+            match self.group_of(&entry) {
+                Some((group, place)) => grouped.push((group, place, (names, help))),
+                None => sections[section].lines.push((names, help)),
+            }
+        }
+        grouped.sort_by_key(|&(group, place, _)| (group, place));
+        for (group, _, line) in grouped {
+            sections[USAGE_SECTIONS.len() + group].lines.push(line);
+        }
+        sections
+    }
+
+    /// Render a man page in troff for the registered parameters, with their descriptions and
+    /// defaults, e.g. `generate_man("prog", 1, "do things")` for `prog.1`.
+    pub fn generate_man(&self, name: &str, section: u8, about: &str) -> String {
+        let sections = self.usage_sections();
+        let mut man = format!(".TH {} {}\n", roff_escape(&name.to_uppercase()), section);
+        man += &format!(".SH NAME\n{} \\- {}\n", roff_escape(name), roff_escape(about));
+        man += &format!(".SH SYNOPSIS\n.B {}\n", roff_escape(name));
+        let synopsis = self.synopsis(String::new(), &sections);
+        if !synopsis.is_empty() {
+            man += &format!("{}\n", roff_escape(synopsis.trim_start()));
+        }
+        for section in sections.iter().filter(|section| !section.lines.is_empty()) {
+            man += &format!(".SH {}\n", section.title.to_uppercase());
+            for (names, help) in section.lines.iter() {
+                man += &format!(".TP\n\\fB{}\\fR\n", roff_escape(names));
+                if let Some(help) = help {
+                    man += &format!("{}\n", roff_escape(help));
+                }
+            }
+        }
+        let commands: Vec<&String> = self.commands.iter()
+            .chain(self.subcommands.iter().map(|subcommand| &subcommand.name))
+            .collect();
+        if !commands.is_empty() {
+            man += ".SH COMMANDS\n";
+            for command in commands {
+                man += &format!(".TP\n\\fB{}\\fR\n", roff_escape(command));
+            }
+        }
+        man
+    }
+
+    /// Check whether the flag registered with `set_help_flag` was given.
+    pub fn help_requested(&self) -> bool {
+        self.help.as_ref().is_some_and(|help| self.found(help))
+    }
+
+    /// Check whether the flag registered with `set_version_flag` was given.
+    pub fn version_requested(&self) -> bool {
+        self.version.as_ref().is_some_and(|version| self.found(version))
+    }
+
+    /// Render the line printed for the version flag, the `name` followed by the `version`, as
+    /// in `mytool 1.2.0`. It is empty if no version was given.
+    pub fn version_text(&self) -> String {
+        match (&self.metadata.name, &self.metadata.version) {
+            (Some(name), Some(version)) => format!("{} {}", name, version),
+            (None, Some(version)) => version.clone(),
+            (_, None) => String::new(),
+        }
+    }
+
+    /// Parse args like `parse`, telling whether the help or the version was asked for, so the
+    /// caller can print `help` or `version_text` and exit. Asking for them takes precedence over
+    /// any problem with the other args.
+    pub fn parse_with_outcome<A>(&mut self, args: A) -> Result<ParseOutcome, Vec<ArgParseError>>
+        where A: Iterator<Item = String>
+    {
+        let parsed = self.parse(args);
+        if self.help_requested() {
+            Ok(ParseOutcome::Help)
+        } else if self.version_requested() {
+            Ok(ParseOutcome::Version)
+        } else {
+            parsed.map(|()| ParseOutcome::Parsed)
+        }
+    }
+
+    /// Parse args like `parse`, then `validate` them, and exit the way command line tools do
+    /// unless all went well: with status 0 after printing the help or the version if their
+    /// flag was given, else with `usage_exit_code` after printing every problem followed by the
+    /// usage to stderr.
+    ///
+    /// ```text
+    /// let mut parser = ArgParser::new(2).set_help_flag("h", "help").add_opt("o", "output");
+    /// parser.parse_or_exit(std::env::args());
+    /// ```
+    pub fn parse_or_exit<A: Iterator<Item = String>>(&mut self, args: A) {
+        match self.parse_outcome(args) {
+            Some((0, text)) => {
+                print!("{}", text);
+                process::exit(0)
+            }
+            Some((code, text)) => {
+                eprint!("{}", text);
+                process::exit(code)
+            }
+            None => (),
+        }
+    }
+
+    /// Parse and validate args for `parse_or_exit`, returning the status to exit with and what
+    /// to print, if it is to exit
+    fn parse_outcome<A: Iterator<Item = String>>(&mut self, args: A) -> Option<(i32, String)> {
+        let mut args = args.peekable();
+        let program = match &self.metadata.name {
+            Some(name) => name.clone(),
+            None => args.peek().map_or("", |program| program.rsplit(['/', '\\']).next().unwrap_or_default()).to_owned(),
+        };
+        let errors = match self.parse_with_outcome(args) {
+            Ok(ParseOutcome::Help) => return Some((0, self.help_text(&program))),
+            Ok(ParseOutcome::Version) => return Some((0, format!("{}\n", self.version_text()))),
+            Ok(ParseOutcome::Parsed) => Vec::new(),
+            Err(errors) => errors,
+        };
+        let mut text: String = errors.iter().map(|error| format!("{}\n", error)).collect();
+        if let Err(error) = self.validate() {
+            text += &error.to_string();
+        }
+        if text.is_empty() {
+            return None;
+        }
+        text.push('\n');
+        text += &self.usage(&program);
+        Some((self.config.usage_exit_code, text))
+    }
+
+    /// Render the complete help like `help_text`, for the program named with `name`. The program
+    /// is left unnamed if it wasn't; `help_text` takes the name instead.
+    pub fn help(&self) -> String {
+        self.help_text(self.metadata.name.as_deref().unwrap_or_default())
+    }
+
+    /// Render the complete help: the `usage` text, followed by the registered `commands` if
+    /// there are any. Meant to be printed when `help_requested`, before exiting.
+    ///
+    /// The `version_text`, the `author` and what the program is `about` come first, if given.
+    pub fn help_text(&self, program: &str) -> String {
+        let header: Vec<String> = Some(self.version_text()).filter(|version| !version.is_empty()).into_iter()
+            .chain(self.metadata.author.clone())
+            .chain(self.metadata.about.clone())
+            .collect();
+        let mut help = if header.is_empty() { String::new() } else { header.join("\n") + "\n\n" };
+        help += &self.usage(program);
+        if !self.commands.is_empty() || !self.subcommands.is_empty() {
+            help += &format!("\n{}:\n", self.messages.0.section_title(Section::Commands));
+            for command in self.commands.iter().chain(self.subcommands.iter().map(|subcommand| &subcommand.name)) {
+                help += &format!("  {}\n", command);
+            }
+        }
+        help
+    }
+
+    /// Export the found flags, opts and settings as shell statements, one per line.
+    ///
+    /// Each variable is named after the parameter's long name (or short one), upper-cased with
+    /// other characters than letters and digits turned into `_`, and prefixed by `prefix`, e.g.
+    /// `export APP_OUTPUT='file.txt'`. Flags are exported as `1`, values are single-quoted for
+    /// the shell.
+    pub fn to_shell_exports(&self, prefix: &str) -> String {
+        let mut exports = String::new();
+        for entry in self.registered_entries() {
+            let value = match entry.value {
+                Value::Flag(rhs) if *(*rhs.value).borrow() => "1".to_owned(),
+                Value::Opt { rhs, found, .. } | Value::Setting { rhs, found, .. } if *(**found).borrow() => {
+                    shell_quote(&(*rhs.value).borrow())
+                }
+                _ => continue,
+            };
+            let name: String = entry.name()
+                .chars()
+                .map(|ch| if ch.is_ascii_alphanumeric() { ch.to_ascii_uppercase() } else { '_' })
+                .collect();
+            exports += &format!("export {}{}={}\n", prefix, name, value);
+        }
+        exports
+    }
+
+    /// Describe every registered parameter, in registration order, e.g. to generate shell
+    /// completions. Aliases share an entry. Those registered with `group` come last, by group.
+    pub fn definitions(&self) -> Vec<ParamInfo> {
+        let mut definitions = Vec::new();
+        let mut grouped = Vec::new();
+        for entry in self.registered_entries().into_iter().filter(|entry| !self.is_hidden(entry.value)) {
+            let (kind, default) = match entry.value {
+                Value::Flag(_) => (ParamKind::Flag, None),
+                Value::Opt { default, .. } => (ParamKind::Opt, default.clone()),
+                Value::KeyVal { .. } | Value::MultiOpt { .. } => (ParamKind::Opt, None),
+                Value::Setting { default, .. } => (ParamKind::Setting, default.clone()),
+                Value::NegatedOpt { .. } | Value::Trailing => continue,
+            };
+            let group = self.group_of(&entry);
+            let info = ParamInfo {
+                long: entry.longs.first().map(|long| (*long).to_owned()),
+                short: entry.shorts.first().cloned(),
+                kind,
+                default,
+                group: group.map(|(group, _)| self.groups[group].0.clone()),
+            };
+            match group {
+                Some((group, place)) => grouped.push((group, place, info)),
+                None => definitions.push(info),
+            }
+        }
+        grouped.sort_by_key(|&(group, place, _)| (group, place));
+        definitions.extend(grouped.into_iter().map(|(_, _, info)| info));
+        definitions
+    }
+
+    /// Parse args with a fresh copy of this parser's registrations, leaving this parser as it
+    /// is, and return the results as a `ParsedArgs`. The same parser can so parse one command
+    /// line after the other without any state carrying over.
+    pub fn get_matches<A: Iterator<Item = String>>(&self, args: A) -> Result<ParsedArgs, Vec<ArgParseError>> {
+        let mut parser = self.clone_spec();
+        parser.parse(args)?;
+        Ok(parser.to_parsed())
+    }
+
+    /// Parse borrowed args without a program name like `parse`, after clearing what was parsed
+    /// before. The args are only copied as they're kept, e.g. as values or positionals.
+    ///
+    /// Unlike `reset`, this clears the storage of the parameters in place rather than making it
+    /// anew, so re-parsing short command lines many times, as a shell does for its builtins,
+    /// doesn't allocate once per registered parameter. The results still live in the parser's
+    /// `Rc<RefCell<..>>` storage: there is no view of them borrowing from `args`.
+    pub fn parse_slice(&mut self, args: &[&str]) -> Result<(), Vec<ArgParseError>> {
+        self.clear_results();
+        let args = args.iter().map(|&arg| Cow::Borrowed(arg));
+        self.parse_args(Some(Cow::Borrowed("")).into_iter().chain(args))
+    }
+
+    /// Clear everything parsed so far, keeping the storage of the parameters
+    fn clear_results(&mut self) {
+        for value in self.params.values_mut() {
+            value.clear();
+        }
+        for subcommand in self.subcommands.iter_mut() {
+            subcommand.parser.clear_results();
+        }
+        self.invalid.clear();
+        self.invalid_operands.clear();
+        self.missing_values.clear();
+        *self.garbage.0.borrow_mut() = false;
+        self.garbage.1.borrow_mut().clear();
+        self.trailing.clear();
+        self.skipped.clear();
+        self.unknown.clear();
+        self.warnings.clear();
+        self.diagnostics.clear();
+        self.events.clear();
+        self.positions.clear();
+        self.total_input = 0;
+        self.original = None;
+        self.os_args.clear();
+        self.os_values.clear();
+        self.from_env.clear();
+        self.validation_failures.clear();
+        self.subcommand = None;
+        self.args.clear();
+        self.apply_config();
+    }
+
+    /// Copy the results of the parse into a `ParsedArgs`, which can be sent to other threads
+    pub fn to_parsed(&self) -> ParsedArgs {
+        let set = self.iter_set().map(|(param, value)| (param.clone(), value)).collect();
+        let mut parsed = ParsedArgs { set, args: self.args.clone(), ..ParsedArgs::default() };
+        for entry in self.entries() {
+            let key = match (entry.longs.first(), entry.shorts.first()) {
+                (Some(long), _) => Param::Long((*long).to_owned()),
+                (None, Some(short)) => Param::Short(*short),
+                (None, None) => continue,
+            };
+            let index = parsed.slots.len();
+            parsed.slots.push(Slot {
+                found: self.found(&key),
+                opt: self.get_opt(&key),
+                setting: self.get_setting(&key),
+                values: self.get_opt_all(&key).unwrap_or_default(),
+            });
+            for short in entry.shorts.iter() {
+                parsed.names.insert(Param::Short(*short), (index, self.count(short)));
+            }
+            for long in entry.longs.iter() {
+                parsed.names.insert(Param::Long((*long).to_owned()), (index, self.count(*long)));
+            }
+        }
+        parsed
+    }
+
+    /// Iterate over the parameters that were found, in the order they were registered in, with
+    /// their values. Aliases appear once, under the long name if there is one.
+    pub fn iter_set(&self) -> impl Iterator<Item = (&Param, ParsedValue)> {
+        let mut set = Vec::new();
+        for entry in self.registered_entries() {
+            let value = match entry.value {
+                Value::Flag(rhs) if *(*rhs.value).borrow() => ParsedValue::Flag(true),
+                Value::Opt { rhs, found, .. } if *(**found).borrow() => ParsedValue::Opt((*rhs.value).borrow().clone()),
+                Value::Setting { rhs, found, .. } if *(**found).borrow() => {
+                    ParsedValue::Setting((*rhs.value).borrow().clone())
+                }
+                Value::KeyVal { rhs, found } if *(**found).borrow() => {
+                    let pairs = (*rhs.value).borrow().iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+                    ParsedValue::Multi(pairs)
+                }
+                Value::MultiOpt { rhs, .. } if !(*rhs.value).borrow().is_empty() => {
+                    ParsedValue::Multi((*rhs.value).borrow().clone())
+                }
+                _ => continue,
+            };
+            let param = match (entry.longs.first(), entry.shorts.first()) {
+                (Some(long), _) => self.params.named_key_value(*long),
+                (None, Some(short)) => self.params.named_key_value(short),
+                (None, None) => None,
+            };
+            if let Some((param, _)) = param {
+                set.push((param, value));
+            }
+        }
+        set.into_iter()
+    }
+
+    /// List the resolved value of every parameter as `name=value` lines, in the order they were
+    /// registered in, e.g. for a `--dump-config` flag. Flags are `true` or `false`, opts and
+    /// settings that weren't given show their default or nothing, and the values of repeated opts
+    /// are joined by `,`.
+    pub fn dump_config(&self) -> String {
+        let mut dump = String::new();
+        for entry in self.registered_entries() {
+            let value = match entry.value {
+                Value::Flag(rhs) => (*rhs.value).borrow().to_string(),
+                Value::Opt { rhs, .. } | Value::Setting { rhs, .. } => (*rhs.value).borrow().clone(),
+                Value::KeyVal { rhs, .. } => (*rhs.value).borrow().iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                Value::MultiOpt { rhs, .. } => (*rhs.value).borrow().join(","),
+                Value::NegatedOpt { .. } | Value::Trailing => continue,
+            };
+            dump += &format!("{}={}\n", entry.name(), value);
+        }
+        dump
+    }
+
+    /// Get the warnings recorded while parsing.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Get the args that were left unprocessed because `max_invalid` stopped the parse.
+    pub fn skipped_args(&self) -> &[String] {
+        &self.skipped
+    }
+
+    /// Split the args given after the `--` terminator into a command and its own args, as
+    /// needed by exec-style tools like `env` or `nice`.
+    pub fn command(&self) -> Option<(&str, &[String])> {
+        self.trailing.split_first().map(|(command, args)| (command.as_str(), args))
+    }
+
+    /// Run every validator registered with `add_post_validator`, in registration order,
+    /// collecting the error of each one that fails.
+    pub fn run_validators(&self) -> Result<(), Vec<String>> {
+        let errors: Vec<String> = self.validators.iter().filter_map(|validator| validator(self).err()).collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Check that at most one member of each group registered with `add_exclusive_group` was
+    /// found, failing with a line like `Conflicting parameters '--json' and '--yaml'` per group
+    /// that wasn't. Aliases of the same parameter don't conflict.
+    pub fn check_exclusive(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for group in self.exclusive.iter() {
+            let mut storages = Vec::new();
+            let mut names = Vec::new();
+            for param in group.iter().filter(|param| self.found(*param)) {
+                let storage = self.params.get(param).and_then(Value::storage);
+                if !storages.contains(&storage) {
+                    storages.push(storage);
+                    names.push(self.display_name(param));
+                }
+            }
+            if names.len() > 1 {
+                errors.push(self.messages.0.conflicting(&names));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// Find the registered long name closest to `name`, an unknown one given without its
+    /// dashes, as in `did you mean '--verbose'?`. Returns None if none is within a couple of
+    /// typos.
+    pub fn suggest(&self, name: &str) -> Option<String> {
+        let max = if name.chars().count() > 3 { 2 } else { 1 };
+        self.params.iter()
+            .filter_map(|(param, value)| match (param, value) {
+                (_, Value::Trailing) => None,
+                (_, value) if self.is_hidden(value) => None,
+                (Param::Long(long), _) => Some((edit_distance(name, long), long)),
+                (Param::Short(_), _) => None,
+            })
+            .filter(|(distance, _)| *distance > 0 && *distance <= max)
+            .min()
+            .map(|(_, long)| self.display_name(&Param::Long(long.clone())))
+    }
+
+    /// Render the first line of the `usage` text, like `Usage: prog [FLAGS] [OPTIONS]`, to be
+    /// printed along with an error.
+    pub fn usage_line(&self, program: &str) -> String {
+        self.usage(program).lines().next().unwrap_or_default().to_owned()
+    }
+
+    /// Check that no invalid parameter was found. The error lists them, and is displayed as
+    /// e.g. `Invalid parameters '-x' and '--bogus'`, followed by a `Did you mean '--verbose'
+    /// instead of '--verbos'?` line for each close enough to a registered long name.
+    pub fn found_invalid(&self) -> Result<(), InvalidParams> {
+        if self.invalid.is_empty() && self.invalid_operands.is_empty() {
+            Ok(())
+        } else {
+            let suggestions: Vec<(Param, String)> = self.invalid.iter()
+                .filter_map(|param| match param {
+                    Param::Long(name) if !self.params.has_named(name.as_str()) => {
+                        self.suggest(name).map(|suggestion| (param.clone(), suggestion))
+                    }
+                    _ => None,
+                })
+                .collect();
+            let name = |param: &Param| match param {
+                Param::Short(ch) => format!("-{}", ch),
+                Param::Long(long) => format!("--{}", long),
+            };
+            let messages = &self.messages.0;
+            let mut message = String::new();
+            if !self.invalid.is_empty() {
+                let names: Vec<String> = self.invalid.iter().map(name).collect();
+                message += &format!("{}\n", messages.invalid_parameters(&names));
+            }
+            for operand in self.invalid_operands.iter() {
+                message += &format!("{}\n", messages.unknown_operand(operand));
+            }
+            for (param, suggestion) in suggestions.iter() {
+                message += &format!("{}\n", messages.did_you_mean(suggestion, &name(param)));
+            }
+            let (params, operands) = (self.invalid.clone(), self.invalid_operands.clone());
+            Err(InvalidParams { params, suggestions, operands, message })
+        }
+    }
+}
+
+/// Register `flags` and `opts`, given as `(short, long)` pairs, then parse `args` and check that
+/// no invalid parameter was found. Returns the parser, or the `found_invalid` error as a string.
+pub fn quick_parse<A>(flags: &[&str], opts: &[(&str, &str)], args: A) -> Result<ArgParser, String>
+    where A: Iterator<Item = String>
+{
+    let mut parser = ArgParser::new(flags.len() + opts.len());
+    for flag in flags {
+        parser = parser.add_flag(&[flag]);
+    }
+    for &(short, long) in opts {
+        parser = parser.add_opt(short, long);
+    }
+    let _ = parser.parse(args);
+    parser.found_invalid().map_err(|err| err.to_string())?;
+    Ok(parser)
+}
+
+/// How many response files deep `response_files` follows `@file` args within files
+pub const MAX_RESPONSE_FILE_DEPTH: usize = 8;
+
+/// The binary (powers of 1024) unit suffixes used by `to_human_readable_string`
+pub const HUMAN_UNITS: &[&str] = &["", "K", "M", "G", "T", "P", "E"];
+
+/// Parse a human-readable size, the inverse of `to_human_readable_string`.
+///
+/// The `HUMAN_UNITS` suffixes `K`, `M`, `G`, `T`, `P` and `E` (optionally followed by `iB`) are
+/// powers of 1024, while `KB`, `MB`, etc. are powers of 1000. A bare number or a `B` suffix is in bytes.
+/// Returns None if the value is malformed or doesn't fit in a `u64`.
+pub fn parse_human_readable(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(value.len());
+    let (number, suffix) = value.split_at(split);
+    let suffix = suffix.trim_start().to_uppercase();
+    let mut units = suffix.chars();
+    let (exponent, base) = match units.next() {
+        None => (0, 1024),
+        Some('B') if units.as_str().is_empty() => (0, 1024),
+        Some(unit) => {
+            let exponent = HUMAN_UNITS.iter().position(|&known| known == unit.to_string())? as u32;
+            match units.as_str() {
+                "" | "IB" => (exponent, 1024),
+                "B" => (exponent, 1000),
+                _ => return None,
+            }
+        }
+    };
+    let multiplier = (base as u64).checked_pow(exponent)?;
+    if let Ok(number) = number.parse::<u64>() {
+        return number.checked_mul(multiplier);
+    }
+    let bytes = number.parse::<f64>().ok()? * multiplier as f64;
+    if bytes.is_finite() && bytes >= 0.0 && bytes < u64::MAX as f64 {
+        Some(bytes.round() as u64)
+    } else {
+        None
+    }
+}
+
+/// Parse an inclusive range of numbers into its start and end, either of which can be left open:
+/// `5` is `(Some(5), Some(5))`, `5-10` is `(Some(5), Some(10))`, `-10` is `(None, Some(10))` and
+/// `5-` is `(Some(5), None)`.
+///
+/// Returns None if a bound isn't a number or the start is past the end.
+pub fn parse_range(value: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let bound = |bound: &str| if bound.is_empty() { Ok(None) } else { bound.parse().map(Some) };
+    let (start, end) = match value.trim().split_once('-') {
+        Some(("", "")) => return None,
+        Some((start, end)) => (bound(start).ok()?, bound(end).ok()?),
+        None => {
+            let number = value.trim().parse().ok()?;
+            (Some(number), Some(number))
+        }
+    };
+    match (start, end) {
+        (Some(start), Some(end)) if start > end => None,
+        range => Some(range),
+    }
+}
+
+/// Build an `ArgParser` from a list of parameters, each expanding to its builder method
+///
+/// ```
+/// # #[macro_use] extern crate arg_parser;
+/// # fn main() {
+/// let mut parser = args! {
+///     flag("v", "verbose");
+///     opt("o", "output");
+///     setting("if");
+/// };
+/// parser.parse_str("-v --output=out if=in").unwrap();
+/// assert!(parser.found("verbose"));
+/// # }
+/// ```
+///
+/// `flag(..)` takes any number of names, like `add_flag`; `opt(short, long)` and
+/// `setting(name)` call `add_opt` and `add_setting`.
+#[macro_export]
+macro_rules! args {
+    (@build $parser:expr;) => { $parser };
+    (@build $parser:expr; flag($($name:expr),+ $(,)?) $(; $($rest:tt)*)?) => {
+        args!(@build $parser.add_flag(&[$($name),+]); $($($rest)*)?)
+    };
+    (@build $parser:expr; opt($short:expr, $long:expr $(,)?) $(; $($rest:tt)*)?) => {
+        args!(@build $parser.add_opt($short, $long); $($($rest)*)?)
+    };
+    (@build $parser:expr; setting($name:expr $(,)?) $(; $($rest:tt)*)?) => {
+        args!(@build $parser.add_setting($name); $($($rest)*)?)
+    };
+    ($($spec:tt)*) => { args!(@build $crate::ArgParser::new(0); $($spec)*) };
+}
+
+#[cfg(test)]
+// The first tests are kept as they were written, when names were looked up by reference and
+// `parse` returned nothing
+#[allow(unused_must_use, clippy::needless_borrows_for_generic_args)]
+mod tests {
+    use super::{ArgParseError, ArgParser, ParsedArgs, ParsedItem, ParseOutcome, Diagnostic, DiagnosticKind,
+                InvalidParams, ParseError, ParseValueError, Param, ParamInfo, ParamKind, ParamMap, ParamRef, English,
+                Messages, ParsedValue, ParserConfig, ParserStyle, Section, SettingType, Severity, Subcommand,
+                ValidationError, ValueSource, glob_match, parse_human_readable, parse_range, quick_parse, split_shell};
+    use std::collections::HashMap;
+    use std::collections::hash_map::DefaultHasher;
+    use std::env;
+    use std::hash::{Hash, Hasher};
+    use std::cell::RefCell;
+    #[cfg(feature = "prompt")]
+    use std::io;
+    use std::rc::Rc;
+
+    #[test]
+    fn stop_parsing() {
+        let args = vec![String::from("binname"), String::from("-a"), String::from("--"), String::from("-v")];
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_flag(&["a"]).add_flag(&["v"]);
+        parser.parse(args.into_iter());
+        assert!(parser.found(&'a'));
+        assert!(!parser.found(&'v'));
+        assert!(parser.args[0] == "-v");
+    }
+
+    #[test]
+    fn short_opts() {
+        let args = vec![String::from("binname"), String::from("-asdf"), String::from("-f"), String::from("foo")];
+        let mut parser = ArgParser::new(4);
+        parser = parser.add_flag(&["a"])
+            .add_flag(&["d"])
+            .add_opt("s", "")
+            .add_opt("f", "");
+        parser.parse(args.into_iter());
+        assert!(parser.found(&'a'));
+        assert!(!parser.found(&'d'));
+        assert!(parser.get_opt(&'s') == Some(String::from("df")));
+        assert!(parser.get_opt(&'f') == Some(String::from("foo")));
+    }
+
+    #[test]
+    fn long_opts() {
         let args = vec![String::from("binname"), String::from("--foo=bar")];
         let mut parser = ArgParser::new(4);
-        parser = parser.add_opt("", "foo");
-        parser.parse(args.into_iter());
-        assert!(parser.get_opt("foo") == Some(String::from("bar")));
+        parser = parser.add_opt("", "foo");
+        parser.parse(args.into_iter());
+        assert!(parser.get_opt("foo") == Some(String::from("bar")));
+    }
+
+    #[test]
+    fn settings() {
+        let args = vec![String::from("binname"), String::from("-h"), String::from("if=bar")];
+        let mut parser = ArgParser::new(4);
+        parser = parser.add_flag(&["h"]).add_setting("if").add_setting_default("of", "foo");
+        parser.parse(args.into_iter());
+        assert!(parser.found("if"));
+        assert!(parser.get_setting("if") == Some(String::from("bar")));
+        assert!(parser.get_setting("of") == Some(String::from("foo")));
+    }
+
+    #[test]
+    fn keyval_opts() {
+        let args = vec![String::from("binname"), String::from("--label"), String::from("a=1"),
+                        String::from("--label"), String::from("b=2"), String::from("-lc=3=4"),
+                        String::from("--label=bad")];
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_opt_keyval("l", "label");
+        parser.parse(args.into_iter()).unwrap_err();
+        assert_eq!(parser.get_opt_keyvals("label"),
+                   Some(vec![(String::from("a"), String::from("1")),
+                             (String::from("b"), String::from("2")),
+                             (String::from("c"), String::from("3=4"))]));
+        assert_eq!(parser.count("label"), 2);
+        assert!(parser.found_invalid().is_err());
+
+        let mut parser = ArgParser::new(2).add_opt_keyval("l", "label").add_flag(&["v", "verbose"]);
+        parser.parse_str("--label --verbose -l -v").unwrap();
+        assert_eq!(parser.get_opt_keyvals("label"), None);
+        assert_eq!(parser.get_count('v'), 2);
+        let invalid = parser.found_invalid().unwrap_err();
+        assert_eq!(invalid.params(), [Param::Long(String::from("label")), Param::Short('l')]);
+    }
+
+    #[test]
+    fn vendor_flags() {
+        let args = vec![String::from("binname"), String::from("--plugin:verbose"), String::from("--verbose")];
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_vendor_flag("plugin", &["verbose"]).add_vendor_flag("other", &["verbose"]);
+        parser.parse(args.into_iter()).unwrap_err();
+        assert!(parser.found_vendor("plugin", "verbose"));
+        assert!(parser.found("plugin:verbose"));
+        assert!(!parser.found_vendor("other", "verbose"));
+        assert!(parser.found_invalid().is_err());
+    }
+
+    #[test]
+    fn trailing_command() {
+        let args = vec![String::from("binname"), String::from("--opt=val"), String::from("file"),
+                        String::from("--"), String::from("ls"), String::from("-la")];
+        let mut parser = ArgParser::new(1);
+        parser = parser.add_opt("", "opt");
+        parser.parse(args.into_iter()).unwrap();
+        assert_eq!(parser.command(), Some(("ls", &[String::from("-la")][..])));
+        assert_eq!(parser.trailing_args(), &[String::from("ls"), String::from("-la")]);
+        assert_eq!(parser.args, vec!["file", "ls", "-la"]);
+    }
+
+    #[test]
+    fn adversarial_input_does_not_panic() {
+        let tokens = ["-", "--", "=", "==", "-=", "--=", "--=x", "-é", "--é=ü", "é=", "=é", "-aé",
+                      "--a=", "-b", "--kv", "k=", "\u{0}", "--\u{10FFFF}", "-\u{10FFFF}=", ""];
+        // Walk a deterministic pseudo-random sequence of token combinations
+        let mut seed: u32 = 0x2545_f491;
+        for _ in 0..2000 {
+            let mut args = vec![String::from("binname")];
+            for _ in 0..(seed % 6) {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                args.push(tokens[seed as usize % tokens.len()].to_owned());
+            }
+            let mut parser = ArgParser::new(4);
+            parser = parser.add_flag(&["a", "é"])
+                .add_opt("b", "")
+                .add_opt_keyval("", "kv")
+                .add_setting("k");
+            let _ = parser.parse(args.into_iter());
+            let _ = parser.found_invalid();
+        }
+    }
+
+    #[test]
+    fn expand_references() {
+        let args = vec![String::from("binname"), String::from("--output=foo"), String::from("--log=${output}.log"),
+                        String::from("--a=${b}"), String::from("--b=${a}"), String::from("--c=${missing}/${o}${")];
+        let mut parser = ArgParser::new(5);
+        parser = parser.add_opt("o", "output")
+            .add_opt("", "log")
+            .add_opt("", "a")
+            .add_opt("", "b")
+            .add_opt("", "c")
+            .expand_references(true);
+        parser.parse(args.into_iter()).unwrap();
+        assert_eq!(parser.get_opt("log"), Some(String::from("foo.log")));
+        assert_eq!(parser.get_opt("c"), Some(String::from("${missing}/foo${")));
+        assert_eq!(parser.get_opt("a"), Some(String::from("${a}")));
+        assert_eq!(parser.get_opt("b"), Some(String::from("${b}")));
+    }
+
+    #[test]
+    fn bool_settings() {
+        let args = vec![String::from("binname"), String::from("verbose=true"), String::from("color=0"),
+                        String::from("mode=fast")];
+        let mut parser = ArgParser::new(3);
+        parser = parser.add_setting("verbose").add_setting("color").add_setting("mode").add_setting("unset");
+        parser.parse(args.into_iter()).unwrap();
+        assert_eq!(parser.get_setting_bool("verbose"), Some(true));
+        assert_eq!(parser.get_setting_bool("color"), Some(false));
+        assert_eq!(parser.get_setting_bool("mode"), None);
+        assert_eq!(parser.get_setting_bool("unset"), None);
+    }
+
+    #[test]
+    fn byte_sizes() {
+        let args = vec![String::from("binname"), String::from("--limit=10M"), String::from("-s"), String::from("1.5G"),
+                        String::from("--count=500"), String::from("--bad=5Q")];
+        let mut parser = ArgParser::new(4);
+        parser = parser.add_opt("", "limit").add_opt("s", "size").add_opt("", "count").add_opt("", "bad");
+        parser.parse(args.into_iter()).unwrap();
+        assert_eq!(parser.get_opt_bytes("limit"), Some(10 * 1024 * 1024));
+        assert_eq!(parser.get_opt_bytes(&'s'), Some(1_610_612_736));
+        assert_eq!(parser.get_opt_bytes("count"), Some(500));
+        assert_eq!(parser.get_opt_bytes("bad"), None);
+        assert_eq!(parse_human_readable("2KB"), Some(2000));
+        assert_eq!(parse_human_readable("4KiB"), Some(4096));
+        assert_eq!(parse_human_readable("20E"), None);
+        assert_eq!(parse_human_readable("1é"), None);
+    }
+
+    #[test]
+    fn verbatim_after_positional() {
+        let args = vec![String::from("binname"), String::from("-a"), String::from("user"),
+                        String::from("cmd"), String::from("-x"), String::from("--"), String::from("-a")];
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_flag(&["a"]).add_flag(&["x"]).verbatim_after_positional(1);
+        parser.parse(args.into_iter()).unwrap();
+        assert!(parser.found('a'));
+        assert!(!parser.found('x'));
+        assert_eq!(parser.trailing_args(), &["cmd", "-x", "--", "-a"]);
+        assert_eq!(parser.args, vec!["user", "cmd", "-x", "--", "-a"]);
+    }
+
+    #[test]
+    fn negatable_opts() {
+        let args = vec![String::from("binname"), String::from("--output=a"), String::from("--no-output"),
+                        String::from("-t"), String::from("b")];
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_opt_negatable("o", "output").add_opt_negatable("t", "tag");
+        parser.parse(args.into_iter()).unwrap();
+        assert_eq!(parser.get_opt("output"), None);
+        assert!(!parser.found('o'));
+        assert_eq!(parser.get_opt("tag"), Some(String::from("b")));
+        assert!(parser.found_invalid().is_ok());
+    }
+
+    #[test]
+    fn opt_info() {
+        let args = vec![String::from("binname"), String::from("--output=a"), String::from("--output=b")];
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_opt("o", "output").add_opt_default("", "level", "3");
+        parser.parse(args.into_iter()).unwrap();
+        assert_eq!(parser.opt_info("output"), Some((String::from("b"), 2, true)));
+        assert_eq!(parser.opt_info("level"), Some((String::from("3"), 0, false)));
+        assert_eq!(parser.opt_info("missing"), None);
+    }
+
+    #[test]
+    fn max_invalid() {
+        let args = vec![String::from("binname"), String::from("-x"), String::from("--bogus"),
+                        String::from("--junk"), String::from("-a"), String::from("file")];
+        let mut parser = ArgParser::new(1);
+        parser = parser.add_flag(&["a"]).max_invalid(2);
+        assert_eq!(parser.parse(args.into_iter()),
+                   Err(vec![ArgParseError::UnknownFlag(String::from("-x")),
+                            ArgParseError::UnknownOpt(String::from("--bogus"))]));
+        assert_eq!(parser.found_invalid().unwrap_err().to_string(), "Invalid parameters '-x' and '--bogus'\n");
+        assert!(!parser.found('a'));
+        assert!(parser.args.is_empty());
+        assert_eq!(parser.skipped_args(), &["--junk", "-a", "file"]);
+
+        let mut parser = ArgParser::new(1).add_flag(&["a"]).max_invalid(2);
+        let errors = parser.parse(vec![String::from("binname"), String::from("-xyza"), String::from("-q")].into_iter());
+        assert_eq!(errors.unwrap_err().len(), 2);
+        assert_eq!(parser.found_invalid().unwrap_err().params(), [Param::Short('x'), Param::Short('y')]);
+        assert!(!parser.found('a'));
+        assert_eq!(parser.skipped_args(), &["-q"]);
+    }
+
+    #[test]
+    fn env_args() {
+        std::env::set_var("ARG_PARSER_TEST_ENV_ARGS", "--output=env -v --name='hello world'");
+        let args = vec![String::from("binname"), String::from("--output=cli")];
+        let mut parser = ArgParser::new(3);
+        parser = parser.add_opt("o", "output").add_flag(&["v"]).add_opt("", "name");
+        parser.parse_env_args("ARG_PARSER_TEST_ENV_ARGS").unwrap();
+        parser.parse(args.into_iter()).unwrap();
+        assert_eq!(parser.get_opt("output"), Some(String::from("cli")));
+        assert_eq!(parser.get_opt("name"), Some(String::from("hello world")));
+        assert!(parser.found('v'));
+    }
+
+    #[test]
+    fn monomorphic_lookups() {
+        let args = vec![String::from("binname"), String::from("-v"), String::from("--output=a")];
+        let mut parser = ArgParser::new(3);
+        parser = parser.add_flag(&["v", "verbose"]).add_opt("o", "output").add_flag(&["q"]);
+        parser.parse(args.into_iter()).unwrap();
+        assert!(parser.found_short('v'));
+        assert!(parser.found_long("verbose"));
+        assert!(!parser.found_short('q'));
+        assert_eq!(parser.get_opt_short('o'), Some(String::from("a")));
+        assert_eq!(parser.get_opt_long("output"), Some(String::from("a")));
+        assert_eq!(parser.get_opt_long("missing"), None);
+    }
+
+    #[test]
+    fn shell_words() {
+        assert_eq!(split_shell("'a b' c"), Ok(vec![String::from("a b"), String::from("c")]));
+        assert_eq!(split_shell("'a\\b'"), Ok(vec![String::from("a\\b")]));
+        assert_eq!(split_shell("\"a\\\"b\" \"c\\\\d\\e\""), Ok(vec![String::from("a\"b"), String::from("c\\d\\e")]));
+        assert_eq!(split_shell("a\\ b  ''"), Ok(vec![String::from("a b"), String::new()]));
+        assert_eq!(split_shell("--msg 'hello"), Err(String::from("Unterminated single quote in '--msg 'hello'")));
+        assert!(split_shell("\"a\\\"").is_err());
+    }
+
+    #[test]
+    fn parse_str() {
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_opt("m", "msg").add_flag(&["v"]);
+        assert!(parser.parse_str("-v --msg=\"hello world\" file").is_ok());
+        assert!(parser.found('v'));
+        assert_eq!(parser.get_opt("msg"), Some(String::from("hello world")));
+        assert_eq!(parser.args, vec!["file"]);
+        assert!(parser.parse_str("-m 'oops").is_err());
+    }
+
+    #[test]
+    fn parse_str_quotes() {
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_opt("m", "msg").add_flag(&["v"]);
+        parser.parse_str("-m \"say \\\"hi\\\" twice\" 'two words'").unwrap();
+        assert_eq!(parser.get_opt(&'m'), Some(String::from("say \"hi\" twice")));
+        assert_eq!(parser.args, vec!["two words"]);
+
+        assert!(parser.parse_str("-v \"unterminated").is_err());
+        assert!(!parser.found('v'));
+        assert_eq!(parser.total_input_args(), 3);
+    }
+
+    #[test]
+    fn warn_on_override() {
+        let args = vec![String::from("binname"), String::from("-o"), String::from("a"), String::from("-ob"),
+                        String::from("--output=c"), String::from("-t"), String::from("x")];
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_opt("o", "output").add_opt("t", "").warn_on_override(true);
+        parser.parse(args.into_iter()).unwrap();
+        assert_eq!(parser.get_opt("output"), Some(String::from("c")));
+        assert_eq!(parser.warnings(), &["--output overridden: 'a' replaced by 'b'",
+                                        "--output overridden: 'b' replaced by 'c'"]);
+    }
+
+    #[test]
+    fn trailing_name() {
+        let args = vec![String::from("binname"), String::from("-o"), String::from("a"), String::from("--"),
+                        String::from("b"), String::from("--rest")];
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_opt("o", "").trailing_name("rest");
+        assert_eq!(parser.get_opt_all("rest"), None);
+        parser.parse(args.into_iter()).unwrap();
+        assert_eq!(parser.get_opt_all("rest"), Some(vec![String::from("b"), String::from("--rest")]));
+        assert_eq!(parser.get_opt_all(&'o'), Some(vec![String::from("a")]));
+        assert!(parser.parse_str("--rest").is_ok());
+        assert!(parser.found_invalid().is_err());
+    }
+
+    #[test]
+    fn single_char_long_flags() {
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_flag(&["a"]).add_long_flag("a").add_long_flag("b");
+        parser.parse_str("--a").unwrap();
+        assert!(parser.found("a"));
+        assert!(!parser.found('a'));
+        parser.parse_str("-a -b").unwrap();
+        assert!(parser.found('a'));
+        assert!(!parser.found("b"));
+        assert_eq!(parser.found_invalid().unwrap_err().to_string(), "Invalid parameter '-b'\n");
+    }
+
+    #[test]
+    fn missing_required() {
+        let mut parser = ArgParser::new(4);
+        parser = parser.add_opt_required("u", "user")
+            .add_opt_required("p", "")
+            .add_setting_required("host")
+            .add_opt("o", "optional");
+        assert_eq!(parser.missing_required(),
+                   vec![Param::Long(String::from("user")), Param::Short('p'), Param::Long(String::from("host"))]);
+        parser.parse_str("-u me").unwrap();
+        assert_eq!(parser.missing_required(), vec![Param::Short('p'), Param::Long(String::from("host"))]);
+        parser.parse_str("-p secret host=example.org").unwrap();
+        assert!(parser.missing_required().is_empty());
+    }
+
+    #[test]
+    fn missing_value_at_end() {
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_opt("o", "output").add_opt_implicit("c", "color", "auto");
+        parser.parse_str("--color --output").unwrap();
+        assert_eq!(parser.get_opt("output"), None);
+        assert_eq!(parser.missing_values(), &[Param::Long(String::from("output"))]);
+        // Not the last arg, so the implicit value doesn't apply
+        assert_eq!(parser.get_opt("color"), Some(String::new()));
+
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_opt("o", "output").add_opt_implicit("c", "color", "auto");
+        parser.parse_str("--output=a --color").unwrap();
+        assert_eq!(parser.get_opt("color"), Some(String::from("auto")));
+        assert!(parser.missing_values().is_empty());
+        parser.parse_str("-c always -o").unwrap();
+        assert_eq!(parser.get_opt("color"), Some(String::from("always")));
+        assert_eq!(parser.missing_values(), &[Param::Short('o')]);
+    }
+
+    #[test]
+    fn shell_exports() {
+        let mut parser = ArgParser::new(4);
+        parser = parser.add_flag(&["v", "verbose"])
+            .add_flag(&["q"])
+            .add_opt("o", "output")
+            .add_opt("", "dry-run")
+            .add_setting("if");
+        parser.parse_str("-v -o file.txt --dry-run=\"it's here\" if=/dev/zero").unwrap();
+        assert_eq!(parser.to_shell_exports("APP_"),
+                   "export APP_VERBOSE=1\n\
+                    export APP_OUTPUT='file.txt'\n\
+                    export APP_DRY_RUN='it'\\''s here'\n\
+                    export APP_IF='/dev/zero'\n");
+    }
+
+    #[test]
+    fn value_separators() {
+        let mut parser = ArgParser::new(4);
+        parser = parser.add_opt("", "opt")
+            .add_opt("", "other")
+            .add_vendor_flag("plugin", &["verbose"])
+            .value_separators(&['=', ':']);
+        parser.parse_str("--opt:a=b --other=c:d --plugin:verbose").unwrap();
+        assert_eq!(parser.get_opt("opt"), Some(String::from("a=b")));
+        assert_eq!(parser.get_opt("other"), Some(String::from("c:d")));
+        assert!(parser.found_vendor("plugin", "verbose"));
+        parser.parse_str("--opt=e").unwrap();
+        assert_eq!(parser.get_opt("opt"), Some(String::from("e")));
+
+        let mut parser = ArgParser::default().add_opt("", "opt");
+        parser.parse_str("--opt:x").unwrap();
+        assert_eq!(parser.found_invalid().unwrap_err().to_string(),
+                   "Invalid parameter '--opt:x'\nDid you mean '--opt' instead of '--opt:x'?\n");
+    }
+
+    #[test]
+    fn greedy_words() {
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_opt_greedy_words("m", "message").add_flag(&["verbose"]);
+        parser.parse_str("--message a b c --verbose file").unwrap();
+        assert_eq!(parser.get_opt("message"), Some(String::from("a b c")));
+        assert!(parser.found("verbose"));
+        assert_eq!(parser.args, vec!["file"]);
+        parser.parse_str("-mhello there -- x").unwrap();
+        assert_eq!(parser.get_opt("message"), Some(String::from("hello there")));
+        parser.parse_str("--message=one two").unwrap();
+        assert_eq!(parser.get_opt("message"), Some(String::from("one two")));
+    }
+
+    #[test]
+    fn clone_spec() {
+        let mut parser = ArgParser::new(4);
+        parser = parser.add_flag(&["v", "verbose"])
+            .add_opt_default("o", "output", "out.txt")
+            .add_opt_negatable("c", "color")
+            .add_setting_default("if", "in");
+        parser.parse_str("-v --output=a --color=auto if=b file").unwrap();
+
+        let mut clone = parser.clone_spec();
+        assert!(!clone.found('v'));
+        assert_eq!(clone.get_opt("output"), Some(String::from("out.txt")));
+        assert_eq!(clone.opt_info(&'o'), Some((String::from("out.txt"), 0, false)));
+        assert_eq!(clone.get_setting("if"), Some(String::from("in")));
+        assert!(clone.args.is_empty());
+
+        clone.parse_str("--verbose -o c --no-color").unwrap();
+        assert!(clone.found('v'));
+        assert_eq!(clone.get_opt("output"), Some(String::from("c")));
+        assert_eq!(clone.get_opt("color"), None);
+        assert_eq!(parser.get_opt("output"), Some(String::from("a")));
+        assert_eq!(parser.get_opt("color"), Some(String::from("auto")));
+    }
+
+    #[test]
+    fn clone_isolated() {
+        let mut parser = ArgParser::new(4);
+        parser = parser.add_flag(&["v", "verbose"])
+            .add_opt("o", "output")
+            .add_opt_negatable("c", "color");
+        parser.parse_str("-v --output=a --color=auto").unwrap();
+
+        let mut clone = parser.clone();
+        assert_eq!(clone.count('v'), 1);
+        assert_eq!(clone.get_opt(&'o'), Some(String::from("a")));
+
+        clone.parse_str("--verbose -vo b --no-color").unwrap();
+        assert_eq!(clone.count('v'), 2);
+        assert_eq!(clone.get_opt("output"), Some(String::from("b")));
+        assert_eq!(clone.get_opt(&'c'), None);
+        assert_eq!(parser.count('v'), 1);
+        assert_eq!(parser.get_opt("output"), Some(String::from("a")));
+        assert_eq!(parser.get_opt(&'c'), Some(String::from("auto")));
+    }
+
+    #[test]
+    fn value_source() {
+        let mut parser = ArgParser::new(4);
+        parser = parser.add_flag(&["v"])
+            .add_opt("o", "output")
+            .add_opt_default("l", "level", "3")
+            .add_setting_default("if", "in");
+        assert_eq!(parser.value_source(&'o'), ValueSource::Unset);
+        assert_eq!(parser.value_source("level"), ValueSource::Default);
+
+        parser.parse_str("-v --level=4").unwrap();
+        assert_eq!(parser.value_source(&'v'), ValueSource::Cli);
+        assert_eq!(parser.value_source("output"), ValueSource::Unset);
+        assert_eq!(parser.value_source(&'l'), ValueSource::Cli);
+        assert_eq!(parser.value_source("if"), ValueSource::Default);
+        assert_eq!(parser.value_source("unknown"), ValueSource::Unset);
+    }
+
+    #[test]
+    fn negative_settings() {
+        let mut parser = ArgParser::new(3);
+        parser = parser.add_setting("offset").add_setting("scale").add_setting("count");
+        parser.parse_str("offset=-5 scale=-0.25 count=-x").unwrap();
+        assert_eq!(parser.get_setting("offset"), Some(String::from("-5")));
+        assert_eq!(parser.get_setting_as::<i64, _>("offset"), Some(Ok(-5)));
+        assert_eq!(parser.get_setting_as::<f64, _>("scale"), Some(Ok(-0.25)));
+        assert!(parser.get_setting_as::<i64, _>("count").is_some_and(|count| count.is_err()));
+        assert_eq!(parser.get_setting_as::<i64, _>("unknown"), None);
+        assert!(parser.found_invalid().is_ok());
+    }
+
+    #[test]
+    fn multi_settings() {
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_setting_multi("env").add_setting("if");
+        parser.parse_str("env=A=1 if=in env=B=2").unwrap();
+        assert_eq!(parser.get_setting_all("env"), Some(vec![String::from("A=1"), String::from("B=2")]));
+        assert_eq!(parser.get_setting("env"), Some(String::from("B=2")));
+        assert_eq!(parser.get_setting_all("if"), Some(vec![String::from("in")]));
+
+        let mut clone = parser.clone_spec();
+        assert_eq!(clone.get_setting_all("env"), None);
+        clone.parse_str("env=C=3").unwrap();
+        assert_eq!(clone.get_setting_all("env"), Some(vec![String::from("C=3")]));
+        assert_eq!(parser.get_setting_all("env").map(|values| values.len()), Some(2));
+    }
+
+    #[test]
+    fn validate_args() {
+        let parser = ArgParser::new(3)
+            .add_flag(&["v", "verbose"])
+            .add_opt("o", "output")
+            .add_setting_required("if");
+        let args = |line: &str| line.split_whitespace().map(String::from).collect::<Vec<_>>().into_iter();
+
+        assert_eq!(parser.validate_args(args("prog -v if=in -o out")), Ok(()));
+        assert_eq!(parser.validate_args(args("prog -x --output")), Err(vec![
+            String::from("Invalid parameter '-x'"),
+            String::from("Missing value for '--output'"),
+            String::from("Missing required parameter 'if'"),
+        ]));
+        assert!(!parser.found('v'));
+        assert_eq!(parser.get_setting("if"), None);
+        assert!(parser.found_invalid().is_ok());
+        assert!(parser.args.is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    fn owned_names() {
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_flag(&["v", "verbose"]).add_setting("if");
+        parser.parse_str("-vv if=in").unwrap();
+        let name = String::from("verbose");
+        assert!(parser.found('v'));
+        assert!(parser.found(&'v'));
+        assert!(parser.found("verbose"));
+        assert!(parser.found(&name));
+        assert!(parser.found(name.clone()));
+        assert!(parser.found(Param::Long(String::from("if"))));
+        assert!(!parser.found(ParamRef::Short('x')));
+        assert_eq!(parser.count('v'), 2);
+        assert_eq!(parser.count(&'v'), 2);
+        assert_eq!(parser.count(Param::Short('v')), 2);
+        assert_eq!(parser.count(String::from("if")), 1);
+    }
+
+    #[test]
+    fn names_by_value() {
+        let mut parser = ArgParser::new(3).add_flag(&["a"]).add_flag(&["d"]).add_opt("s", "");
+        parser.parse_str("-asdf -- -d").unwrap();
+        assert!(parser.found('a'));
+        assert!(!parser.found('d'));
+        assert_eq!(parser.count('a'), 1);
+        assert_eq!(parser.count('s'), 1);
+        assert_eq!(parser.get_opt('s'), Some(String::from("df")));
+        assert_eq!(parser.get_opt(&'s'), parser.get_opt(Param::Short('s')));
+        assert_eq!(parser.args, ["-d"]);
+
+        let mut parser = ArgParser::new(2).add_opt("o", "output").add_setting("if");
+        parser.parse_str("--output=x if=y").unwrap();
+        let (output, input) = (String::from("output"), String::from("if"));
+        assert_eq!(parser.get_opt(&output), Some(String::from("x")));
+        assert_eq!(parser.get_opt(output), Some(String::from("x")));
+        assert_eq!(parser.get_setting(input), Some(String::from("y")));
+        assert_eq!(parser.value_source('o'), ValueSource::Cli);
+    }
+
+    #[test]
+    fn post_validators() {
+        fn advanced_needs_key(parser: &ArgParser) -> Result<(), String> {
+            match (parser.get_opt("mode"), parser.found("key")) {
+                (Some(ref mode), false) if mode == "advanced" => Err(String::from("--mode=advanced requires --key")),
+                _ => Ok(()),
+            }
+        }
+        fn at_most_one_input(parser: &ArgParser) -> Result<(), String> {
+            if parser.args.len() > 1 {
+                return Err(String::from("Only one input may be given"));
+            }
+            Ok(())
+        }
+
+        let parser = ArgParser::new(2)
+            .add_opt("m", "mode")
+            .add_opt("k", "key")
+            .add_post_validator(advanced_needs_key)
+            .add_post_validator(at_most_one_input);
+
+        let mut good = parser.clone_spec();
+        good.parse_str("--mode=advanced -k secret input").unwrap();
+        assert_eq!(good.run_validators(), Ok(()));
+
+        let mut bad = parser.clone_spec();
+        bad.parse_str("--mode=advanced a b").unwrap();
+        assert_eq!(bad.run_validators(), Err(vec![
+            String::from("--mode=advanced requires --key"),
+            String::from("Only one input may be given"),
+        ]));
+    }
+
+    #[test]
+    fn dashed_registration() {
+        let mut parser = ArgParser::new(4);
+        parser = parser.add_opt("", "--foo")
+            .add_opt("-o", "--output")
+            .add_flag(&["-v", "--verbose"])
+            .add_opt_required("", "--name");
+        parser.parse_str("--foo=a -o b --verbose").unwrap();
+        assert_eq!(parser.get_opt("foo"), Some(String::from("a")));
+        assert_eq!(parser.get_opt("output"), Some(String::from("b")));
+        assert!(parser.found('v'));
+        assert_eq!(parser.missing_required(), vec![Param::Long(String::from("name"))]);
+        assert!(parser.found_invalid().is_ok());
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut parser = ArgParser::new(4);
+        parser = parser.add_flag(&["v", "verbose"])
+            .add_opt_keyval("l", "label")
+            .add_opt("o", "output");
+        parser.parse_str("file -vx --verbose=yes --label nopair -o").unwrap();
+        let diagnostic = |index, token: &str, param, message: &str, kind| Diagnostic {
+            index,
+            token: String::from(token),
+            param,
+            message: String::from(message),
+            kind,
+        };
+        let long = |name: &str| Param::Long(String::from(name));
+        assert_eq!(parser.diagnostics(), &[
+            diagnostic(2, "-vx", Param::Short('x'), "Unknown parameter '-x'", DiagnosticKind::Unknown),
+            diagnostic(3, "--verbose=yes", long("verbose"), "Invalid value for '--verbose'",
+                       DiagnosticKind::InvalidValue),
+            diagnostic(4, "--label", long("label"), "Invalid value for '--label'", DiagnosticKind::InvalidValue),
+            diagnostic(6, "-o", Param::Short('o'), "Missing value for '-o'", DiagnosticKind::MissingValue),
+        ][..]);
+    }
+
+    #[test]
+    fn short_opt_occurrences() {
+        let mut parser = ArgParser::new(2);
+        parser = parser.add_opt("f", "file").add_opt_default("l", "level", "1");
+        parser.parse_str("-f a.txt -fb.txt -f c.txt --level= --level=2 --level=3").unwrap();
+        assert_eq!(parser.count('f'), 3);
+        assert_eq!(parser.get_opt(&'f'), Some(String::from("c.txt")));
+        assert_eq!(parser.count("level"), 3);
+
+        let mut parser = ArgParser::new(1).add_opt("f", "file");
+        parser.parse_str("--file=a --file=b").unwrap();
+        assert_eq!(parser.count("file"), 2);
+        parser.parse_str("-f").unwrap();
+        assert_eq!(parser.count('f'), 0);
+
+        let mut parser = ArgParser::new(1).add_opt("f", "file");
+        parser.parse_str("-fa -f b --file=c").unwrap();
+        assert_eq!((parser.count('f'), parser.count("file")), (2, 1));
+    }
+
+    #[test]
+    fn flag_env() {
+        env::set_var("ARG_PARSER_TEST_VERBOSE", "1");
+        env::set_var("ARG_PARSER_TEST_QUIET", "no");
+        let parser = ArgParser::new(3)
+            .add_flag_env(&["v", "verbose"], "ARG_PARSER_TEST_VERBOSE")
+            .add_flag_env(&["q", "quiet"], "ARG_PARSER_TEST_QUIET")
+            .add_flag_env(&["d", "debug"], "ARG_PARSER_TEST_UNSET");
+
+        let mut env_only = parser.clone_spec();
+        env_only.parse_str("").unwrap();
+        assert!(env_only.found("verbose"));
+        assert!(*env_only.flag(&'v'));
+        assert_eq!(env_only.count('v'), 0);
+        assert_eq!(env_only.value_source(&'v'), ValueSource::Env);
+        assert!(!env_only.found('q'));
+        assert!(!env_only.found('d'));
+
+        let mut given = parser.clone_spec();
+        given.parse_str("-v -d").unwrap();
+        assert_eq!(given.count('v'), 1);
+        assert_eq!(given.value_source("verbose"), ValueSource::Cli);
+        assert!(given.found("debug"));
+    }
+
+    #[test]
+    fn found_while_borrowed() {
+        let mut parser = ArgParser::new(1).add_flag(&["a", "all"]);
+        parser.parse_str("-a").unwrap();
+        assert!(parser.found('a'));
+        // Reading the flag back under both names only shares the `a`/`all` cell
+        *parser.flag(&'a') = false;
+        assert!(!parser.found('a') && !parser.found("all"));
+        *parser.flag("all") = true;
+        assert!(parser.found('a') && parser.found("all"));
+    }
+
+    #[test]
+    fn positionals_with_index() {
+        let mut parser = ArgParser::new(2).add_flag(&["v"]).add_opt("o", "output");
+        parser.parse_str("a -v b -o out --output=x c -- -d e").unwrap();
+        assert_eq!(parser.positionals_with_index(), vec![
+            (1, String::from("a")),
+            (3, String::from("b")),
+            (7, String::from("c")),
+            (9, String::from("-d")),
+            (10, String::from("e")),
+        ]);
+    }
+
+    #[test]
+    fn get_opt_as() {
+        let mut parser = ArgParser::new(3)
+            .add_opt("p", "port")
+            .add_opt("r", "ratio")
+            .add_opt_default("t", "timeout", "30");
+        parser.parse_str("--port=8080 -r abc").unwrap();
+        assert_eq!(parser.get_opt_as::<u16, _>("port"), Some(Ok(8080)));
+        assert_eq!(parser.get_opt_as::<u8, _>(&'p').map(|port| port.is_err()), Some(true));
+        assert!(parser.get_opt_as::<f64, _>("ratio").is_some_and(|ratio| ratio.is_err()));
+        assert_eq!(parser.get_opt_as::<u32, _>("timeout"), Some(Ok(30)));
+        assert_eq!(parser.get_opt_as::<u32, _>("unknown"), None);
+    }
+
+    #[test]
+    fn unknown_severity() {
+        let parser = ArgParser::new(1).add_flag(&["v", "verbose"]);
+
+        let mut strict = parser.clone_spec();
+        strict.parse_str("-x --verbose").unwrap();
+        assert_eq!(strict.found_invalid().unwrap_err().to_string(), "Invalid parameter '-x'\n");
+        assert!(strict.warnings().is_empty());
+
+        let mut lenient = parser.unknown_severity(Severity::Warn);
+        lenient.parse_str("-x --verbose --other --verbose=yes").unwrap();
+        assert!(lenient.found("verbose"));
+        assert_eq!(lenient.warnings(),
+                   &[String::from("Unknown parameter '-x'"), String::from("Unknown parameter '--other'")][..]);
+        assert_eq!(lenient.found_invalid().unwrap_err().to_string(), "Invalid parameter '--verbose'\n");
+    }
+
+    #[test]
+    fn multi_opts() {
+        let mut parser = ArgParser::new(2).add_opt_multi("I", "include").add_flag(&["v"]);
+        parser.parse_str("-Ia -I b -v --include=c --include d").unwrap();
+        let expected = vec![String::from("a"), String::from("b"), String::from("c"), String::from("d")];
+        assert_eq!(parser.get_opt_all(&'I').unwrap(), expected);
+        assert_eq!(parser.get_opt_all("include"), Some(expected));
+        assert_eq!(parser.count('I'), 4);
+        assert!(parser.found("include"));
+        assert!(parser.args.is_empty());
+
+        let mut parser = parser.clone_spec();
+        assert!(parser.get_opt_all(&'I').is_none());
+        parser.parse_str("-I").unwrap();
+        assert!(!parser.found('I'));
+        assert_eq!(parser.missing_values(), &[Param::Short('I')][..]);
+    }
+
+    #[test]
+    fn multi_csv_opts() {
+        let mut parser = ArgParser::new(1).add_opt_multi_csv("t", "tag");
+        parser.parse_str("--tag a,b --tag c -td,,e --tag=f").unwrap();
+        let tags: Vec<String> = ["a", "b", "c", "d", "e", "f"].iter().map(|tag| tag.to_string()).collect();
+        assert_eq!(parser.get_opt_all("tag"), Some(tags));
+        assert_eq!(parser.count('t'), 6);
+    }
+
+    #[test]
+    fn negative_numbers() {
+        let parser = ArgParser::new(3).add_flag(&["v"]).add_opt("o", "offset").add_opt("s", "scale");
+
+        let mut positional = parser.clone_spec();
+        positional.parse_str("-5 -v -0.5 -1.2.3").unwrap();
+        assert_eq!(positional.args, vec![String::from("-5"), String::from("-0.5")]);
+        assert!(positional.found('v'));
+        assert!(positional.found_invalid().is_err());
+
+        let mut values = parser.clone_spec();
+        values.parse_str("--offset -3 -s -0.25 -- -4").unwrap();
+        assert_eq!(values.get_opt("offset"), Some(String::from("-3")));
+        assert_eq!(values.get_opt_as::<f64, _>(&'s'), Some(Ok(-0.25)));
+        assert_eq!(values.args, vec![String::from("-4")]);
+        assert!(values.found_invalid().is_ok());
+
+        let mut shadowed = ArgParser::new(1).add_flag(&["5"]);
+        shadowed.parse_str("-5").unwrap();
+        assert!(shadowed.found('5'));
+        assert!(shadowed.args.is_empty());
+    }
+
+    #[test]
+    fn usage() {
+        let parser = ArgParser::new(5)
+            .add_flag_desc(&["v", "verbose"], "Print more details")
+            .add_flag(&["q"])
+            .add_opt_desc("o", "output", "Write to a file")
+            .add_opt_keyval("l", "label")
+            .add_setting_desc("if", "Read from a file");
+        assert_eq!(parser.usage("prog"), "\
+Usage: prog [FLAGS] [OPTIONS] [SETTINGS]
+
+Flags:
+  -v, --verbose           Print more details
+  -q
+
+Options:
+  -o, --output <VALUE>    Write to a file
+  -l, --label <KEY=VALUE>
+
+Settings:
+  if=<VALUE>              Read from a file
+");
+    }
+
+    #[test]
+    fn total_input_args() {
+        let mut parser = ArgParser::new(2).add_flag(&["v"]).add_opt("o", "output");
+        assert_eq!(parser.total_input_args(), 0);
+        parser.parse_str("").unwrap();
+        assert_eq!(parser.total_input_args(), 0);
+        parser.parse_str("-v -o out file -- rest").unwrap();
+        assert_eq!(parser.total_input_args(), 6);
+        assert_eq!(parser.args.len(), 2);
+
+        parser.parse_str("-v").unwrap();
+        assert_eq!(parser.total_input_args(), 7);
+        parser.parse_slice(&["-v", "file"]).unwrap();
+        assert_eq!(parser.total_input_args(), 2);
+        parser.reset();
+        assert_eq!(parser.total_input_args(), 0);
+    }
+
+    #[test]
+    fn command_name() {
+        let parser = ArgParser::new(1).add_flag(&["v"]).commands(&["build", "run", "test"]);
+
+        let mut run = parser.clone_spec();
+        run.parse_str("-v run target").unwrap();
+        assert_eq!(run.command_name(), Some("run"));
+        assert_eq!(run.unknown_command(), None);
+
+        let mut unknown = parser.clone_spec();
+        unknown.parse_str("deploy run").unwrap();
+        assert_eq!(unknown.command_name(), None);
+        assert_eq!(unknown.unknown_command(), Some("deploy"));
+
+        let mut none = parser.clone_spec();
+        none.parse_str("-v").unwrap();
+        assert_eq!(none.command_name(), None);
+        assert_eq!(none.unknown_command(), None);
+    }
+
+    #[test]
+    fn parse_strict() {
+        let parser = ArgParser::new(2).add_flag(&["v"]).add_opt("f", "file");
+        let args = |line: &str| line.split_whitespace().map(String::from).collect::<Vec<_>>().into_iter();
+
+        let mut good = parser.clone_spec();
+        assert_eq!(good.parse_strict(args("prog -v -f a.txt b")), Ok(()));
+        assert_eq!(good.args, vec![String::from("b")]);
+
+        let mut unknown = parser.clone_spec();
+        let error = unknown.parse_strict(args("prog -v --bogus -x -f"));
+        assert_eq!(error, Err(ParseError::Unknown(String::from("--bogus"))));
+        assert_eq!(error.unwrap_err().to_string(), "Unknown parameter '--bogus'");
+        assert_eq!(unknown.skipped_args(), &[String::from("-x"), String::from("-f")][..]);
+
+        let mut missing = parser.clone_spec();
+        assert_eq!(missing.parse_strict(args("prog -v -f")), Err(ParseError::MissingValue(String::from("-f"))));
+
+        let mut clustered = parser.clone_spec();
+        assert_eq!(clustered.parse_strict(args("prog -vxy")), Err(ParseError::Unknown(String::from("-x"))));
+
+        let mut lenient = parser.unknown_severity(Severity::Warn);
+        assert_eq!(lenient.parse_strict(args("prog -x --file")), Err(ParseError::MissingValue(String::from("--file"))));
+    }
+
+    #[test]
+    fn abbreviations() {
+        let parser = ArgParser::new(4)
+            .add_flag(&["verbose"])
+            .add_flag(&["version"])
+            .add_opt("o", "output")
+            .add_flag(&["color", "colour"])
+            .allow_abbreviations(true);
+
+        let mut unique = parser.clone_spec();
+        unique.parse_str("--verb --out=a.txt --col").unwrap();
+        assert!(unique.found("verbose"));
+        assert_eq!(unique.get_opt("output"), Some(String::from("a.txt")));
+        assert!(unique.found("color"));
+        assert!(unique.found_invalid().is_ok());
+
+        let mut ambiguous = parser.clone_spec();
+        ambiguous.parse_str("--ver --o").unwrap();
+        assert!(!ambiguous.found("verbose"));
+        assert!(!ambiguous.found("version"));
+        assert_eq!(ambiguous.found_invalid().unwrap_err().to_string(), "Invalid parameter '--ver'\n");
+        assert_eq!(ambiguous.missing_values(), &[Param::Long(String::from("output"))][..]);
+    }
+
+    #[test]
+    fn expanduser() {
+        let mut parser = ArgParser::new(2)
+            .add_opt_expanduser("c", "config")
+            .add_opt_expanduser("o", "output")
+            .home_dir("/home/tester/");
+        parser.parse_str("-c ~/foo --output=/tmp/~/bar").unwrap();
+        assert_eq!(parser.get_opt("config"), Some(String::from("/home/tester/foo")));
+        assert_eq!(parser.get_opt(&'o'), Some(String::from("/tmp/~/bar")));
+    }
+
+    #[test]
+    fn config() {
+        let parser = ArgParser::new(1);
+        assert_eq!(parser.config(), &ParserConfig::default());
+
+        let parser = ArgParser::new(1)
+            .add_flag(&["v"])
+            .max_invalid(2)
+            .warn_on_override(true)
+            .unknown_severity(Severity::Warn)
+            .value_separators(&['=', ':']);
+        let config = parser.config();
+        assert_eq!(config.max_invalid, Some(2));
+        assert!(config.warn_on_override);
+        assert_eq!(config.unknown_severity, Severity::Warn);
+        assert_eq!(config.value_separators, vec!['=', ':']);
+        assert!(!config.expand_references);
+        assert_eq!(config.verbatim_after, None);
+        assert_eq!(parser.clone_spec().config(), config);
+    }
+
+    #[test]
+    fn negated_flag() {
+        let mut parser = ArgParser::new(2).add_flag_negatable(&["c", "color"]);
+        parser.parse_str("--color --no-color").unwrap();
+        assert!(!parser.found("color"));
+        assert!(!parser.found('c'));
+        assert_eq!(parser.count("color"), 2);
+        assert!(parser.found_invalid().is_ok());
+
+        let mut parser = ArgParser::new(2).add_flag_negatable(&["c", "color"]);
+        parser.parse_str("--no-color -c").unwrap();
+        assert!(parser.found("color"));
+
+        let mut parser = ArgParser::new(2).add_flag_negatable(&["c", "color"]);
+        parser.parse_str("--no-colour --no-c").unwrap();
+        assert!(!parser.found("color"));
+        assert_eq!(parser.count("color"), 0);
+        assert!(parser.found_invalid().is_err());
+    }
+
+    #[test]
+    fn opt_env() {
+        env::set_var("ARG_PARSER_TEST_OUTPUT", "env.txt");
+        env::set_var("ARG_PARSER_TEST_LEVEL", "2");
+        let parser = ArgParser::new(3)
+            .add_opt_env("o", "output", "ARG_PARSER_TEST_OUTPUT")
+            .add_opt_env("n", "", "ARG_PARSER_TEST_UNSET")
+            .add_setting_env("level", "ARG_PARSER_TEST_LEVEL");
+
+        let mut env_only = parser.clone_spec();
+        env_only.parse_str("").unwrap();
+        assert_eq!(env_only.get_opt(&'o'), Some(String::from("env.txt")));
+        assert!(env_only.found("output"));
+        assert_eq!(env_only.count("output"), 0);
+        assert_eq!(env_only.value_source(&'o'), ValueSource::Env);
+        assert_eq!(env_only.get_setting("level"), Some(String::from("2")));
+        assert_eq!(env_only.value_source("level"), ValueSource::Env);
+        assert!(!env_only.found('n'));
+
+        let mut given = parser.clone_spec();
+        given.parse_str("--output=cli.txt level=5").unwrap();
+        assert_eq!(given.get_opt("output"), Some(String::from("cli.txt")));
+        assert_eq!(given.value_source("output"), ValueSource::Cli);
+        assert_eq!(given.get_setting("level"), Some(String::from("5")));
+    }
+
+    #[test]
+    fn parse_auto() {
+        let parser = ArgParser::new(3).add_flag(&["v"]).add_setting("if").commands(&["build"]);
+        let words = |line: &str| split_shell(line).unwrap().into_iter();
+
+        let mut with_program = parser.clone_spec();
+        with_program.parse_auto(words("/usr/bin/prog -v file.txt")).unwrap();
+        assert!(with_program.found('v'));
+        assert_eq!(with_program.args, vec!["file.txt"]);
+
+        let mut stripped = parser.clone_spec();
+        stripped.parse_auto(words("-v file.txt")).unwrap();
+        assert!(stripped.found('v'));
+        assert_eq!(stripped.args, vec!["file.txt"]);
+
+        let mut setting = parser.clone_spec();
+        setting.parse_auto(words("if=in.txt")).unwrap();
+        assert_eq!(setting.get_setting("if"), Some(String::from("in.txt")));
+
+        let mut command = parser.clone_spec();
+        command.parse_auto(words("build -v")).unwrap();
+        assert_eq!(command.command_name(), Some("build"));
+
+        let mut empty = parser.clone_spec();
+        empty.parse_auto(words("")).unwrap();
+        assert!(empty.args.is_empty());
+    }
+
+    #[test]
+    fn map_opt() {
+        let mut parser = ArgParser::new(3).add_opt("p", "port").add_opt("o", "output");
+        parser.parse_str("--port=8080").unwrap();
+        assert_eq!(parser.map_opt("port", |port| port.parse::<u16>()), Some(Ok(8080)));
+        assert_eq!(parser.map_opt(&'p', str::len), Some(4));
+        assert_eq!(parser.map_opt("output", str::len), None);
+        assert_eq!(parser.map_opt("unknown", str::len), None);
+    }
+
+    #[test]
+    fn exclusive_group() {
+        let parser = ArgParser::new(4)
+            .add_flag(&["j", "json"])
+            .add_flag(&["yaml"])
+            .add_opt("f", "format")
+            .add_exclusive_group(&["json", "--yaml", "f"]);
+
+        let mut both = parser.clone_spec();
+        both.parse_str("--json --yaml").unwrap();
+        assert_eq!(both.check_exclusive(), Err(String::from("Conflicting parameters '--json' and '--yaml'")));
+
+        let mut all = parser.clone_spec();
+        all.parse_str("-j --yaml -f=toml").unwrap();
+        assert_eq!(all.check_exclusive(), Err(String::from("Conflicting parameters '--json' and '--yaml' and '-f'")));
+
+        let mut one = parser.clone_spec();
+        one.parse_str("-j --json").unwrap();
+        assert_eq!(one.check_exclusive(), Ok(()));
+
+        let mut none = parser.clone_spec();
+        none.parse_str("").unwrap();
+        assert_eq!(none.check_exclusive(), Ok(()));
+    }
+
+    #[test]
+    fn end_marker() {
+        let parser = ArgParser::new(2).add_flag(&["v"]).end_marker("END");
+
+        let mut marked = parser.clone_spec();
+        marked.parse_str("-v file END -x --no END").unwrap();
+        assert!(marked.found('v'));
+        assert_eq!(marked.args, vec!["file", "-x", "--no", "END"]);
+        assert_eq!(marked.trailing_args(), ["-x", "--no", "END"]);
+        assert!(marked.found_invalid().is_ok());
+
+        let mut dashes = parser.clone_spec();
+        dashes.parse_str("-- -v").unwrap();
+        assert!(!dashes.found('v'));
+        assert_eq!(dashes.args, vec!["-v"]);
+    }
+
+    #[test]
+    fn count_settings() {
+        let mut parser = ArgParser::new(2).add_setting("if").add_setting_default("of", "out");
+        parser.parse_str("if=a if=b").unwrap();
+        assert_eq!(parser.count("if"), 2);
+        assert_eq!(parser.get_setting("if"), Some(String::from("b")));
+        assert_eq!(parser.count("of"), 0);
+
+        parser.parse_str("of=c").unwrap();
+        assert_eq!(parser.count("of"), 1);
+    }
+
+    #[test]
+    fn error_report() {
+        let parser = ArgParser::new(6)
+            .add_flag(&["json"])
+            .add_flag(&["yaml"])
+            .add_opt("o", "output")
+            .add_opt("k", "key")
+            .add_setting_required("if")
+            .add_exclusive_group(&["json", "yaml"])
+            .add_post_validator(|parser| {
+                if parser.found("key") { Ok(()) } else { Err(String::from("--key is required")) }
+            });
+
+        let mut wrong = parser.clone_spec();
+        wrong.parse_str("-x --json --yaml --output").unwrap();
+        assert_eq!(wrong.error_report(), Some(String::from("Invalid parameter '-x'\n\
+                                                            Missing value for '--output'\n\
+                                                            Missing required parameter 'if'\n\
+                                                            Conflicting parameters '--json' and '--yaml'\n\
+                                                            --key is required")));
+
+        let mut right = parser.clone_spec();
+        right.parse_str("--json -k=secret if=in").unwrap();
+        assert_eq!(right.error_report(), None);
+    }
+
+    #[test]
+    fn get_opt_first_of() {
+        let mut parser = ArgParser::new(2).add_opt("", "output").add_opt("", "out-file");
+        assert_eq!(parser.get_opt_first_of(&["output", "out-file"]), None);
+        parser.parse_str("--out-file=old.txt").unwrap();
+        assert_eq!(parser.get_opt_first_of(&["output", "out-file"]), Some(String::from("old.txt")));
+        parser.parse_str("--output=new.txt").unwrap();
+        assert_eq!(parser.get_opt_first_of(&["output", "out-file"]), Some(String::from("new.txt")));
+        assert_eq!(parser.get_opt_first_of(&[]), None);
+    }
+
+    #[test]
+    fn args_macro() {
+        let mut parser = args! {
+            flag("v", "verbose");
+            flag("q");
+            opt("o", "output");
+            setting("if");
+        };
+        parser.parse_str("-v --output=out if=in file").unwrap();
+        assert!(parser.found("verbose"));
+        assert!(!parser.found('q'));
+        assert_eq!(parser.get_opt(&'o'), Some(String::from("out")));
+        assert_eq!(parser.get_setting("if"), Some(String::from("in")));
+        assert_eq!(parser.args, vec!["file"]);
+        assert!(parser.found_invalid().is_ok());
+
+        let mut last = args! { opt("o", "output"); setting("of") };
+        last.parse_str("of=x").unwrap();
+        assert_eq!(last.get_setting("of"), Some(String::from("x")));
+        let mut empty = args! {};
+        empty.parse_str("-v").unwrap();
+        assert!(empty.found_invalid().is_err());
+    }
+
+    #[test]
+    fn invalid_params() {
+        let mut parser = ArgParser::new(1).add_flag(&["verbose"]);
+        parser.parse_str("--verbse -x").unwrap();
+        let err: InvalidParams = parser.found_invalid().unwrap_err();
+        match err.params() {
+            [Param::Long(long), Param::Short('x')] => assert_eq!(long, "verbse"),
+            params => panic!("unexpected params {:?}", params),
+        }
+        assert_eq!(err.to_string(),
+                   "Invalid parameters '--verbse' and '-x'\nDid you mean '--verbose' instead of '--verbse'?\n");
+    }
+
+    #[test]
+    fn distinct_found() {
+        let mut parser = ArgParser::new(4)
+            .add_flag(&["v", "verbose"])
+            .add_flag(&["q"])
+            .add_opt("o", "output")
+            .add_opt_default("l", "level", "3")
+            .add_setting("if");
+        assert_eq!(parser.distinct_found(), 0);
+        parser.parse_str("-vv --verbose -o a --output=b if=in file").unwrap();
+        assert_eq!(parser.distinct_found(), 3);
+    }
+
+    #[test]
+    fn windows_text() {
+        assert_eq!(split_shell("\u{feff}--verbose\r\n-q\r\n"), Ok(vec![String::from("--verbose"), String::from("-q")]));
+        assert_eq!(split_shell("'a\r\nb'\r\n"), Ok(vec![String::from("a\r\nb")]));
+
+        let mut parser = ArgParser::new(2).add_flag(&["verbose"]).add_opt("o", "output");
+        parser.parse_str("\u{feff}--verbose\r\n--output=out.txt\r\n").unwrap();
+        assert!(parser.found("verbose"));
+        assert_eq!(parser.get_opt("output"), Some(String::from("out.txt")));
+        assert!(parser.found_invalid().is_ok());
+    }
+
+    #[test]
+    fn opt_choices() {
+        let parser = ArgParser::new(2).add_opt_choices("c", "color", &["always", "never", "auto"]);
+
+        let mut accepted = parser.clone_spec();
+        accepted.parse_str("--color=never").unwrap();
+        assert_eq!(accepted.get_opt("color"), Some(String::from("never")));
+        assert!(accepted.invalid_values().is_empty());
+
+        let mut rejected = parser.clone_spec();
+        rejected.parse_str("-c sometimes").unwrap();
+        assert!(rejected.found('c'));
+        assert_eq!(rejected.get_opt("color"), Some(String::from("sometimes")));
+        assert_eq!(rejected.invalid_values(), vec![(Param::Long(String::from("color")), String::from("sometimes"))]);
+        assert_eq!(rejected.error_report(), Some(String::from("Invalid value 'sometimes' for '--color'")));
+        assert!(rejected.found_invalid().is_ok());
+
+        assert_eq!(parser.validate_args(vec![String::new(), String::from("--color=auto")].into_iter()), Ok(()));
+    }
+
+    #[test]
+    fn short_opt_equals() {
+        let parser = ArgParser::new(3).add_flag(&["a"]).add_flag(&["b"]).add_opt("f", "file");
+
+        let mut separated = parser.clone_spec();
+        separated.parse_str("-f=value").unwrap();
+        assert_eq!(separated.get_opt(&'f'), Some(String::from("value")));
+
+        let mut cluster = parser.clone_spec();
+        cluster.parse_str("-abf=value -f==x").unwrap();
+        assert!(cluster.found('a'));
+        assert!(cluster.found('b'));
+        assert_eq!(cluster.get_opt("file"), Some(String::from("=x")));
+        assert_eq!(cluster.count('f'), 2);
+
+        let mut empty = parser.clone_spec();
+        empty.parse_str("-f= next").unwrap();
+        assert_eq!(empty.get_opt(&'f'), Some(String::new()));
+        assert_eq!(empty.args, vec!["next"]);
+        assert!(empty.found_invalid().is_ok());
+    }
+
+    #[test]
+    fn dump_config() {
+        let mut parser = ArgParser::new(7)
+            .add_flag(&["dump-config"])
+            .add_flag(&["v", "verbose"])
+            .add_opt("o", "output")
+            .add_opt_default("l", "level", "3")
+            .add_opt_multi("I", "include")
+            .add_opt_keyval("D", "define")
+            .add_setting("if")
+            .add_opt("q", "");
+        parser.parse_str("--dump-config -o out -I a -I b -D x=1 if=in").unwrap();
+        assert!(parser.found("dump-config"));
+        assert_eq!(parser.dump_config(), "dump-config=true\n\
+                                          verbose=false\n\
+                                          output=out\n\
+                                          level=3\n\
+                                          include=a,b\n\
+                                          define=x=1\n\
+                                          if=in\n\
+                                          q=\n");
+    }
+
+    #[test]
+    fn reset() {
+        let mut parser = ArgParser::new(5)
+            .add_flag(&["v", "verbose"])
+            .add_opt("o", "output")
+            .add_opt_default("l", "level", "3")
+            .add_setting("if")
+            .add_opt_required("k", "key")
+            .max_invalid(4);
+        parser.parse_str("-vv -o a --level=1 if=in -k secret -x file -- rest").unwrap();
+        assert!(parser.found_invalid().is_err());
+
+        parser.reset();
+        assert!(!parser.found('v'));
+        assert_eq!(parser.count('v'), 0);
+        assert_eq!(parser.get_opt("output"), None);
+        assert_eq!(parser.opt_info("level"), Some((String::from("3"), 0, false)));
+        assert_eq!(parser.get_setting("if"), None);
+        assert!(parser.args.is_empty());
+        assert!(parser.trailing_args().is_empty());
+        assert!(parser.found_invalid().is_ok());
+        assert_eq!(parser.total_input_args(), 0);
+
+        parser.parse_str("--output=b").unwrap();
+        assert_eq!(parser.get_opt(&'o'), Some(String::from("b")));
+        assert!(!parser.found("verbose"));
+        assert_eq!(parser.missing_required(), vec![Param::Long(String::from("key"))]);
+        assert_eq!(parser.config().max_invalid, Some(4));
+    }
+
+    #[test]
+    fn hybrid() {
+        let parser = ArgParser::new(2).add_hybrid("d", "debug").add_flag(&["v"]);
+
+        let mut counted = parser.clone_spec();
+        counted.parse_str("-dd file").unwrap();
+        assert_eq!(counted.count('d'), 2);
+        assert!(counted.found("debug"));
+        assert_eq!(counted.get_opt(&'d'), None);
+        assert_eq!(counted.args, vec!["file"]);
+
+        let mut valued = parser.clone_spec();
+        valued.parse_str("-d3").unwrap();
+        assert_eq!(valued.count('d'), 1);
+        assert_eq!(valued.get_opt("debug"), Some(String::from("3")));
+
+        let mut mixed = parser.clone_spec();
+        mixed.parse_str("-dv -d=2 --debug -dd5").unwrap();
+        assert!(mixed.found('v'));
+        assert_eq!(mixed.count('d'), 4);
+        assert_eq!(mixed.count("debug"), 1);
+        assert_eq!(mixed.get_opt(&'d'), Some(String::from("5")));
+        assert!(mixed.found_invalid().is_ok());
+    }
+
+    #[test]
+    fn iter_set() {
+        let mut parser = ArgParser::new(6)
+            .add_flag(&["v", "verbose"])
+            .add_flag(&["q"])
+            .add_opt("o", "output")
+            .add_opt("n", "")
+            .add_opt_multi("I", "include")
+            .add_setting("if")
+            .add_setting("of");
+        parser.parse_str("-v --verbose -o a -n 4 -I x -I y if=in").unwrap();
+        let set: Vec<(&Param, ParsedValue)> = parser.iter_set().collect();
+        assert_eq!(set, vec![
+            (&Param::Long(String::from("verbose")), ParsedValue::Flag(true)),
+            (&Param::Long(String::from("output")), ParsedValue::Opt(String::from("a"))),
+            (&Param::Short('n'), ParsedValue::Opt(String::from("4"))),
+            (&Param::Long(String::from("include")), ParsedValue::Multi(vec![String::from("x"), String::from("y")])),
+            (&Param::Long(String::from("if")), ParsedValue::Setting(String::from("in"))),
+        ]);
+    }
+
+    #[test]
+    fn try_get_opt_as() {
+        let mut parser = ArgParser::new(3).add_opt("p", "port").add_opt("t", "timeout").add_opt("r", "retries");
+        parser.parse_str("-p 8080 --timeout=soon").unwrap();
+        assert_eq!(parser.try_get_opt_as::<u16, _>("port"), Ok(Some(8080)));
+        assert_eq!(parser.try_get_opt_as::<u16, _>(&'t'), Err(String::from("Invalid value 'soon' for '-t'")));
+        assert_eq!(parser.try_get_opt_as::<u16, _>("timeout"),
+                   Err(String::from("Invalid value 'soon' for '--timeout'")));
+        assert_eq!(parser.try_get_opt_as::<u16, _>("retries"), Ok(None));
+        assert_eq!(parser.try_get_opt_as::<u16, _>("unknown"), Ok(None));
+    }
+
+    #[test]
+    fn case_insensitive_long() {
+        let parser = ArgParser::new(3).add_opt("c", "color").add_flag(&["v", "verbose"]).add_flag(&["V"]);
+
+        let mut sensitive = parser.clone_spec();
+        sensitive.parse_str("--COLOR=Always --Verbose").unwrap();
+        assert!(!sensitive.found("color"));
+        assert!(!sensitive.found("verbose"));
+        assert!(sensitive.found_invalid().is_err());
+
+        let mut insensitive = parser.clone_spec().case_insensitive_long(true);
+        insensitive.parse_str("--COLOR=Always --Verbose").unwrap();
+        assert_eq!(insensitive.get_opt("color"), Some(String::from("Always")));
+        assert!(insensitive.found('v'));
+        assert!(!insensitive.found('V'));
+        assert!(insensitive.found_invalid().is_ok());
+
+        let mut short = parser.clone_spec().case_insensitive_long(true).allow_abbreviations(true);
+        short.parse_str("-V --verb").unwrap();
+        assert!(short.found('V'));
+        assert!(short.found("verbose"));
+    }
+
+    #[test]
+    fn collect_unknown_matching() {
+        assert!(glob_match("--x-*", "--x-foo=1"));
+        assert!(glob_match("-?", "-x"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbc"));
+        assert!(!glob_match("--x-*", "--bogus"));
+        assert!(!glob_match("-?", "-xy"));
+
+        let mut parser = ArgParser::new(1)
+            .add_flag(&["v"])
+            .collect_unknown_matching("--x-*")
+            .collect_unknown_matching("-Z");
+        parser.parse_str("--x-foo --x-bar=1 -vZ --bogus -Y file").unwrap();
+        assert_eq!(parser.unknown_args(),
+                   &[String::from("--x-foo"), String::from("--x-bar=1"), String::from("-Z")][..]);
+        assert!(parser.found('v'));
+        assert_eq!(parser.args, vec!["file"]);
+        assert_eq!(parser.found_invalid().unwrap_err().params(),
+                   &[Param::Long(String::from("bogus")), Param::Short('Y')][..]);
+    }
+
+    #[test]
+    fn reset_param() {
+        let mut parser = ArgParser::new(4)
+            .add_flag(&["v", "verbose"])
+            .add_opt("o", "output")
+            .add_opt_default("l", "level", "3")
+            .add_setting("if");
+        parser.parse_str("-v -o a --level=1 -l 2 if=in").unwrap();
+
+        assert!(parser.reset_param("level"));
+        assert_eq!(parser.opt_info(&'l'), Some((String::from("3"), 0, false)));
+        assert_eq!(parser.opt_info("level"), Some((String::from("3"), 0, false)));
+        assert_eq!(parser.get_opt(&'o'), Some(String::from("a")));
+        assert!(parser.found("verbose"));
+        assert_eq!(parser.get_setting("if"), Some(String::from("in")));
+
+        assert!(parser.reset_param('v'));
+        assert!(!parser.found("verbose"));
+        assert_eq!(parser.count('v'), 0);
+        assert!(!parser.reset_param("unknown"));
+
+        parser.parse_str("--level=5").unwrap();
+        assert_eq!(parser.get_opt("level"), Some(String::from("5")));
+    }
+
+    #[test]
+    fn alias() {
+        let mut parser = ArgParser::new(4)
+            .add_flag(&["v", "verbose"])
+            .add_opt("o", "output")
+            .alias("verbose", "loud")
+            .alias("-o", "--out")
+            .alias("O", "out")
+            .alias("missing", "m");
+        parser.parse_str("--loud --out=a").unwrap();
+        assert!(parser.found('v'));
+        assert!(parser.found("verbose"));
+        assert_eq!(parser.count("loud"), 1);
+        assert_eq!(parser.count('v'), 0);
+        assert_eq!(parser.get_opt("output"), Some(String::from("a")));
+        assert_eq!(parser.get_opt(&'o'), Some(String::from("a")));
+        assert!(!parser.found('m'));
+
+        parser.parse_str("-o b").unwrap();
+        assert_eq!(parser.get_opt("out"), Some(String::from("b")));
+        assert!(parser.found_invalid().is_ok());
+    }
+
+    #[test]
+    fn help_flag() {
+        let parser = ArgParser::new(3)
+            .set_help_flag("h", "help")
+            .add_flag_desc(&["v", "verbose"], "Print more details")
+            .add_opt_desc("o", "output", "Write to a file")
+            .commands(&["build", "run"]);
+
+        let mut quiet = parser.clone_spec();
+        quiet.parse_str("-v").unwrap();
+        assert!(!quiet.help_requested());
+
+        let mut asking = parser.clone_spec();
+        asking.parse_str("--help").unwrap();
+        assert!(asking.help_requested());
+        assert_eq!(asking.help_text("prog"), "Usage: prog [FLAGS] [OPTIONS]\n\
+                                              \n\
+                                              Flags:\n  \
+                                              -h, --help           Print this help\n  \
+                                              -v, --verbose        Print more details\n\
+                                              \n\
+                                              Options:\n  \
+                                              -o, --output <VALUE> Write to a file\n\
+                                              \n\
+                                              Commands:\n  \
+                                              build\n  \
+                                              run\n");
+        assert!(!ArgParser::new(0).help_requested());
+    }
+
+    #[test]
+    fn opt_validated() {
+        let parser = ArgParser::new(2)
+            .add_opt_validated("p", "port", |port| match port.parse::<u16>() {
+                Ok(port) if port > 0 => Ok(()),
+                _ => Err(String::from("must be between 1 and 65535")),
+            })
+            .add_opt_validated("", "host", |host| if host.is_empty() { Err(String::from("empty")) } else { Ok(()) });
+
+        let mut passing = parser.clone_spec();
+        passing.parse_str("--port=8080").unwrap();
+        assert!(passing.validation_failures().is_empty());
+        assert_eq!(passing.error_report(), None);
+
+        let mut failing = parser.clone();
+        failing.parse_str("-p 0").unwrap();
+        assert_eq!(failing.get_opt("port"), Some(String::from("0")));
+        assert_eq!(failing.validation_failures(),
+                   &[(Param::Long(String::from("port")), String::from("must be between 1 and 65535"))][..]);
+        assert_eq!(failing.error_report(),
+                   Some(String::from("Invalid value for '--port': must be between 1 and 65535")));
+
+        failing.parse_str("-p 22").unwrap();
+        assert!(failing.validation_failures().is_empty());
+    }
+
+    #[test]
+    fn param_styles() {
+        let mut params = HashMap::new();
+        params.insert(Param::Short('a'), 1);
+        params.insert(Param::Long(String::from("a")), 2);
+        params.insert(Param::Short('\0'), 3);
+        params.insert(Param::Long(String::from("\0")), 4);
+        assert_eq!(params.len(), 4);
+        assert_eq!(params.named('a'), Some(&1));
+        assert_eq!(params.named("a"), Some(&2));
+        assert_eq!(params.named(ParamRef::Long("a")), Some(&2));
+        assert_eq!(params.named('\0'), Some(&3));
+        assert_eq!(params.named("\0"), Some(&4));
+        assert_eq!(params.named(""), None);
+        assert_ne!(Param::Short('a'), Param::Long(String::from("a")));
+
+        let mut hashes = Vec::new();
+        for param in &[Param::Short('a'), Param::Long(String::from("a"))] {
+            let mut hasher = DefaultHasher::new();
+            param.hash(&mut hasher);
+            hashes.push(hasher.finish());
+        }
+        assert_ne!(hashes[0], hashes[1]);
+
+        let mut parser = ArgParser::new(2).add_flag(&["a"]).add_long_flag("a");
+        parser.parse_str("-a").unwrap();
+        assert!(parser.found('a'));
+        assert!(!parser.found("a"));
+        parser.parse_str("--a --=x").unwrap();
+        assert!(parser.found("a"));
+        assert_eq!(parser.found_invalid().unwrap_err().params(), &[Param::Long(String::new())][..]);
+
+        // A NUL is a name like any other, which finds no parameter of the other style
+        let mut parser = ArgParser::new(2).add_flag(&["\0"]).add_long_flag("z");
+        let args = ["", "--\0", "-z"].iter().map(|arg| arg.to_string());
+        assert_eq!(parser.parse(args), Err(vec![ArgParseError::UnknownOpt(String::from("--\0")),
+                                                ArgParseError::UnknownFlag(String::from("-z"))]));
+        assert!(!parser.found('\0') && !parser.found("z"));
+    }
+
+    #[test]
+    fn definitions() {
+        let parser = ArgParser::new(5)
+            .add_flag(&["v", "verbose"])
+            .add_opt_default("l", "level", "3")
+            .add_opt("o", "")
+            .add_opt_multi("", "include")
+            .add_setting_default("if", "in")
+            .trailing_name("rest");
+        let info = |long: Option<&str>, short, kind, default: Option<&str>| ParamInfo {
+            long: long.map(String::from),
+            short,
+            kind,
+            default: default.map(String::from),
+            group: None,
+        };
+        assert_eq!(parser.definitions(), vec![
+            info(Some("verbose"), Some('v'), ParamKind::Flag, None),
+            info(Some("level"), Some('l'), ParamKind::Opt, Some("3")),
+            info(None, Some('o'), ParamKind::Opt, None),
+            info(Some("include"), None, ParamKind::Opt, None),
+            info(Some("if"), None, ParamKind::Setting, Some("in")),
+        ]);
+    }
+
+    #[test]
+    fn get_opt_all_positioned() {
+        let mut parser = ArgParser::new(1).add_opt_multi("i", "inc").add_opt_multi_csv("t", "tag");
+        parser.parse_str("--inc=a other --inc b -t x,y").unwrap();
+        assert_eq!(parser.get_opt_all_positioned("inc"),
+                   Some(vec![(1, "a".to_owned()), (3, "b".to_owned())]));
+        assert_eq!(parser.get_opt_all_positioned(&'t'),
+                   Some(vec![(5, "x".to_owned()), (5, "y".to_owned())]));
+        parser.reset_param("inc");
+        assert_eq!(parser.get_opt_all_positioned("inc"), None);
+        assert_eq!(parser.get_opt_all_positioned("other"), None);
+
+        let mut parser = ArgParser::new(3)
+            .add_opt("o", "output")
+            .add_opt_multi("I", "include")
+            .add_setting("level");
+        parser.parse_str("-o a.txt file1 --output=b.txt file2 -Isrc level=1 -ob -I lib level=2").unwrap();
+        assert_eq!(parser.get_opt(&'o'), Some(String::from("b")));
+        assert_eq!(parser.get_opt_all_positioned("output"), Some(vec![
+            (1, String::from("a.txt")),
+            (4, String::from("b.txt")),
+            (8, String::from("b")),
+        ]));
+        assert_eq!(parser.get_opt_all_positioned('I'), Some(vec![(6, String::from("src")), (9, String::from("lib"))]));
+        assert_eq!(parser.get_opt_all_positioned("level"), Some(vec![(7, String::from("1")), (11, String::from("2"))]));
+        assert_eq!(parser.positionals_with_index(), vec![(3, String::from("file1")), (5, String::from("file2"))]);
+        assert_eq!(parser.get_opt_all_positioned("missing"), None);
+    }
+
+    #[test]
+    fn retain_input() {
+        let mut parser = ArgParser::new(1).add_flag(&["v"]);
+        parser.parse_str("-v -x").unwrap();
+        assert_eq!(parser.original_args(), None);
+        assert_eq!(parser.highlight(&parser.diagnostics()[0]), None);
+
+        let mut parser = ArgParser::new(1).add_flag(&["v"]).retain_input(true);
+        parser.parse_str("-v file -x").unwrap();
+        assert_eq!(parser.original_args(),
+                   Some(&["".to_owned(), "-v".to_owned(), "file".to_owned(), "-x".to_owned()][..]));
+        assert_eq!(parser.highlight(&parser.diagnostics()[0]).unwrap(), " -v file -x\n         ^^");
     }
 
     #[test]
-    fn settings() {
-        let args = vec![String::from("binname"), String::from("-h"), String::from("if=bar")];
-        let mut parser = ArgParser::new(4);
-        parser = parser.add_flag(&["h"]).add_setting("if").add_setting_default("of", "foo");
-        parser.parse(args.into_iter());
-        assert!(parser.found("if"));
-        assert!(parser.get_setting("if") == Some(String::from("bar")));
-        assert!(parser.get_setting("of") == Some(String::from("foo")));
+    fn add_opt_existing_path() {
+        let dir = env::temp_dir().join(format!("arg_parser_existing_path_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("input.txt");
+        std::fs::write(&file, "").unwrap();
+        let bogus = dir.join("bogus");
+        let (dir, file, bogus) = (dir.to_str().unwrap(), file.to_str().unwrap(), bogus.to_str().unwrap());
+        let parser = ArgParser::new(0)
+            .add_opt_existing_path("p", "path")
+            .add_opt_existing_file("f", "file")
+            .add_opt_existing_dir("d", "dir");
+
+        let mut passing = parser.clone_spec();
+        let args = vec![String::new(), format!("--path={}", dir), format!("--file={}", file), format!("-d{}", dir)];
+        passing.parse(args.into_iter()).unwrap();
+        assert!(passing.found_invalid().is_ok());
+
+        let mut failing = parser.clone_spec();
+        let args = vec![String::new(), format!("--path={}", bogus), String::from("-f"), dir.to_owned(),
+                        format!("--dir={}", file)];
+        failing.parse(args.into_iter()).unwrap_err();
+        let param = |name: &str| Param::Long(name.to_owned());
+        assert_eq!(failing.found_invalid().unwrap_err().params(), [param("path"), Param::Short('f'), param("dir")]);
+        let diagnostics: Vec<(usize, String)> = failing.diagnostics().iter()
+            .map(|diagnostic| (diagnostic.index, diagnostic.message.clone()))
+            .collect();
+        assert_eq!(diagnostics, [
+            (1, format!("Invalid value for '--path': '{}' does not exist", bogus)),
+            (2, format!("Invalid value for '-f': '{}' is not a file", dir)),
+            (4, format!("Invalid value for '--dir': '{}' is not a directory", file)),
+        ]);
+        assert!(failing.diagnostics().iter().all(|diagnostic| diagnostic.kind == DiagnosticKind::InvalidValue));
+        assert!(failing.validation_failures().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn quick_parse_args() {
+        let args = |line: &str| Some(String::new()).into_iter().chain(split_shell(line).unwrap());
+        let parser = quick_parse(&["v", "verbose"], &[("o", "output")], args("-v --output=out.txt file")).unwrap();
+        assert!(parser.found('v'));
+        assert!(!parser.found("verbose"));
+        assert_eq!(parser.get_opt("output"), Some("out.txt".to_owned()));
+        assert_eq!(parser.args, vec!["file".to_owned()]);
+
+        let err = quick_parse(&["v"], &[("o", "output")], args("-v --bogus")).err().unwrap();
+        assert_eq!(err, "Invalid parameter '--bogus'\n");
+    }
+
+    #[test]
+    fn help() {
+        let parser = ArgParser::new(4)
+            .add_flag(&["v", "verbose"])
+            .add_opt_default("o", "output", "out.txt")
+            .add_setting("if")
+            .describe("verbose", "Print more details")
+            .describe('o', "Write to a file")
+            .set_help_flag("h", "help");
+        assert_eq!(parser.help_text("prog"), "\
+Usage: prog [FLAGS] [OPTIONS] [SETTINGS]
+
+Flags:
+  -v, --verbose        Print more details
+  -h, --help           Print this help
+
+Options:
+  -o, --output <VALUE> Write to a file [default: out.txt]
+
+Settings:
+  if=<VALUE>
+");
+        assert_eq!(parser.help(), parser.help_text(""));
+        let parser = parser.name("prog");
+        assert_eq!(parser.help(), parser.help_text("prog"));
+    }
+
+    #[test]
+    fn subcommands() {
+        let parser = ArgParser::new(1).add_flag(&["v", "verbose"])
+            .add_subcommand("fetch", ArgParser::new(1).add_flag(&["all"]).add_opt("d", "depth"))
+            .add_subcommand("push", ArgParser::new(1).add_flag(&["f", "force"]));
+
+        let mut fetch = parser.clone_spec();
+        fetch.parse_str("-v fetch --all --depth=3 origin").unwrap();
+        assert_eq!(fetch.which_subcommand(), Some("fetch"));
+        assert!(fetch.found('v'));
+        assert!(fetch.args.is_empty());
+        let matches = fetch.subcommand_matches("fetch").unwrap();
+        assert!(matches.found("all"));
+        assert_eq!(matches.get_opt(&'d'), Some("3".to_owned()));
+        assert_eq!(matches.args, vec!["origin".to_owned()]);
+        assert!(fetch.subcommand_matches("push").is_none());
+        assert!(parser.subcommand_matches("fetch").is_none());
+
+        let mut none = parser.clone_spec();
+        none.parse_str("origin fetch --all").unwrap();
+        assert_eq!(none.which_subcommand(), None);
+        assert_eq!(none.args, vec!["origin".to_owned(), "fetch".to_owned()]);
+        assert!(none.found_invalid().is_err());
+        assert!(none.help_text("git").ends_with("\nCommands:\n  fetch\n  push\n"));
+
+        let subcommand: &Subcommand = &fetch.subcommands()[1];
+        assert_eq!(subcommand.name(), "push");
+        assert!(!subcommand.parser().found('f'));
+    }
+
+    #[test]
+    fn parse_opt() {
+        let mut parser = ArgParser::new(4)
+            .add_opt("p", "port")
+            .add_opt("r", "ratio")
+            .add_opt("a", "addr")
+            .add_setting_default("retries", "3");
+        parser.parse_str("--port=abc -r 0.5 --addr=127.0.0.1").unwrap();
+        assert_eq!(parser.parse_opt::<f32, _>('r'), Ok(0.5));
+        assert_eq!(parser.parse_opt::<std::net::IpAddr, _>("addr"), Ok(std::net::IpAddr::from([127, 0, 0, 1])));
+        assert_eq!(parser.parse_setting::<u8, _>("retries"), Ok(3));
+
+        let err = parser.parse_opt::<u16, _>("port").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid value 'abc' for '--port': invalid digit found in string");
+        assert_eq!(parser.parse_setting::<u8, _>("bogus"), Err(ParseValueError::Missing("--bogus".to_owned())));
+        parser.reset_param('r');
+        assert_eq!(parser.parse_opt::<f32, _>('r').unwrap_err().to_string(), "Missing value for '-r'");
+    }
+
+    #[test]
+    fn validate() {
+        let parser = ArgParser::new(3)
+            .add_opt("u", "user")
+            .required("user")
+            .add_setting("host")
+            .required("host")
+            .add_flag(&["v"])
+            .max_positionals(1);
+
+        let mut complete = parser.clone_spec();
+        complete.parse_str("--user=me host=example.org -v file").unwrap();
+        assert_eq!(complete.validate(), Ok(()));
+
+        let mut incomplete = parser.clone_spec();
+        incomplete.parse_str("-u me file extra more").unwrap();
+        let err: ValidationError = incomplete.validate().unwrap_err();
+        assert_eq!(err.missing(), &[Param::Long(String::from("host"))][..]);
+        assert_eq!(err.unexpected(), &[String::from("extra"), String::from("more")][..]);
+        assert_eq!(err.to_string(), "\
+Missing required parameter 'host'
+Unexpected argument 'extra'
+Unexpected argument 'more'
+");
+        assert_eq!(ArgParser::new(0).validate(), Ok(()));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let words = |line: &str| Some(String::new()).into_iter().chain(split_shell(line).unwrap());
+        let parser = ArgParser::new(3)
+            .add_flag(&["v", "verbose"])
+            .add_opt("o", "output")
+            .add_opt_choices("c", "color", &["auto", "never"]);
+
+        let mut valid = parser.clone_spec();
+        assert_eq!(valid.parse(words("-v --color=auto -o out file")), Ok(()));
+
+        let mut invalid = parser.clone_spec();
+        let errors = invalid.parse(words("-x --bogus=1 size=2 --verbose=yes -o")).unwrap_err();
+        assert_eq!(errors, vec![
+            ArgParseError::UnknownFlag(String::from("-x")),
+            ArgParseError::UnknownOpt(String::from("--bogus")),
+            ArgParseError::InvalidSetting(String::from("size")),
+            ArgParseError::InvalidValue(String::from("--verbose")),
+            ArgParseError::MissingValue(String::from("-o")),
+        ]);
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(messages, vec!["Unknown parameter '-x'", "Unknown parameter '--bogus'", "Unknown setting 'size'",
+                                  "Invalid value for '--verbose'", "Missing value for '-o'"]);
+
+        let mut warned = parser.clone_spec().unknown_severity(Severity::Warn);
+        assert_eq!(warned.parse(words("-x file")), Ok(()));
+
+        let mut subcommand = ArgParser::new(1).add_subcommand("run", parser.clone_spec());
+        assert_eq!(subcommand.parse(words("run -o")), Err(vec![ArgParseError::MissingValue(String::from("-o"))]));
+        assert_eq!(subcommand.diagnostics()[0].index, 2);
+    }
+
+    #[test]
+    fn multi_opts_collect_every_occurrence() {
+        let mut parser = ArgParser::new(2).add_opt_multi("I", "include").add_opt_multi("D", "define");
+        parser.parse_str("-I include1 -I include2 --define=A --define=B").unwrap();
+        assert_eq!(parser.get_opt_all("include"), Some(vec![String::from("include1"), String::from("include2")]));
+        assert_eq!(parser.get_opt_all(&'D'), Some(vec![String::from("A"), String::from("B")]));
+        assert_eq!(parser.count("define"), 2);
+    }
+
+    #[test]
+    fn env_over_default() {
+        let parser = ArgParser::new(2)
+            .add_opt_default("p", "port", "80")
+            .env("port", "ARG_PARSER_TEST_PORT")
+            .add_setting_default("host", "localhost")
+            .env("host", "ARG_PARSER_TEST_HOST_UNSET");
+        env::set_var("ARG_PARSER_TEST_PORT", "8080");
+
+        let mut from_env = parser.clone_spec();
+        from_env.parse_str("").unwrap();
+        assert_eq!(from_env.get_opt("port"), Some(String::from("8080")));
+        assert_eq!(from_env.value_source(&'p'), ValueSource::Env);
+        assert_eq!(from_env.get_setting("host"), Some(String::from("localhost")));
+
+        let mut from_cli = parser.clone_spec();
+        from_cli.parse_str("--port=443").unwrap();
+        assert_eq!(from_cli.get_opt("port"), Some(String::from("443")));
+        env::remove_var("ARG_PARSER_TEST_PORT");
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derive_arg_parse() {
+        use super::ArgParse;
+
+        #[derive(ArgParse, Debug, PartialEq)]
+        struct Opts {
+            #[flag(short = 'v', long = "verbose")]
+            verbose: bool,
+            #[flag(short = 'q')]
+            quiet: bool,
+            /// Where to write
+            #[opt(short = 'o', long = "output")]
+            output: Option<String>,
+            #[opt]
+            level: Option<String>,
+            #[setting]
+            r#if: Option<String>,
+        }
+
+        let words = |line: &str| Some(String::new()).into_iter().chain(split_shell(line).unwrap());
+        assert_eq!(Opts::parse(words("-v -o out.txt --level=3 if=in.txt")), Ok(Opts {
+            verbose: true,
+            quiet: false,
+            output: Some(String::from("out.txt")),
+            level: Some(String::from("3")),
+            r#if: Some(String::from("in.txt")),
+        }));
+        assert_eq!(Opts::parse(words("-q --bogus")), Err(vec![ArgParseError::UnknownOpt(String::from("--bogus"))]));
+    }
+
+    #[test]
+    fn positionals() {
+        let parser = ArgParser::new(1).add_flag(&["v"])
+            .add_positional("input")
+            .add_positional_optional("output");
+        assert_eq!(parser.usage("prog"), "Usage: prog [FLAGS] <input> [output]\n\nFlags:\n  -v\n");
+
+        let mut both = parser.clone_spec();
+        both.parse_str("in.txt -v out.txt").unwrap();
+        assert_eq!(both.get_positional("input"), Some("in.txt"));
+        assert_eq!(both.get_positional("output"), Some("out.txt"));
+        assert_eq!(both.get_positional("bogus"), None);
+        assert_eq!(both.validate(), Ok(()));
+
+        let mut excess = parser.clone_spec();
+        excess.parse_str("a b c").unwrap();
+        assert_eq!(excess.validate().unwrap_err().unexpected(), &[String::from("c")][..]);
+
+        let mut none = parser.clone_spec();
+        none.parse_str("-v").unwrap();
+        assert_eq!(none.get_positional("output"), None);
+        let err = none.validate().unwrap_err();
+        assert_eq!(err.missing_positionals(), &[String::from("input")][..]);
+        assert_eq!(err.to_string(), "Missing argument '<input>'\n");
+
+        let mut multi = ArgParser::new(0).add_positional("dest").add_positional_multi("sources");
+        multi.parse_str("dir a b c").unwrap();
+        assert_eq!(multi.get_positional("dest"), Some("dir"));
+        assert_eq!(multi.get_positional_all("sources"), &["a", "b", "c"]);
+        assert_eq!(multi.validate(), Ok(()));
+        multi.reset();
+        assert!(multi.get_positional_all("sources").is_empty());
+        assert!(multi.usage("cp").starts_with("Usage: cp <dest> [sources...]\n"));
+    }
+
+    #[test]
+    fn parse_os() {
+        use std::ffi::OsString;
+
+        let mut parser = ArgParser::new(2).add_opt("o", "output").add_opt("i", "input");
+        let args = vec![OsString::from("prog"), OsString::from("--input=in.txt"), OsString::from("file")];
+        assert_eq!(parser.parse_os(args.into_iter()), Ok(()));
+        assert_eq!(parser.get_opt_os("input"), Some(OsString::from("in.txt")));
+        assert_eq!(parser.get_opt_os(&'o'), None);
+        assert_eq!(parser.args_os(), vec![OsString::from("file")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_os_non_utf8() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let os = |bytes: &[u8]| OsString::from_vec(bytes.to_vec());
+        let mut parser = ArgParser::new(2).add_opt("o", "output").add_opt("i", "input");
+        let args = vec![os(b"prog"), os(b"--output=out\xff.txt"), os(b"-iin\xfe"), os(b"\xfdfile")];
+        assert_eq!(parser.parse_os(args.into_iter()), Ok(()));
+        assert_eq!(parser.get_opt("output"), Some(String::from("out\u{fffd}.txt")));
+        assert_eq!(parser.get_opt_os("output"), Some(os(b"out\xff.txt")));
+        assert_eq!(parser.get_opt_os(&'i'), Some(os(b"in\xfe")));
+        assert_eq!(parser.args_os(), vec![os(b"\xfdfile")]);
+        parser.reset();
+        assert_eq!(parser.get_opt_os("output"), None);
+        assert!(parser.args_os().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_os_same_lossy_values() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let os = |bytes: &[u8]| OsString::from_vec(bytes.to_vec());
+        let mut parser = ArgParser::new(3).add_flag(&["v"]).add_opt("o", "output").add_opt("i", "input");
+        let args = vec![os(b"prog"), os(b"--input=a\xff"), os(b"--output=a\xfe"), os(b"a\xfd")];
+        assert_eq!(parser.parse_os(args.into_iter()), Ok(()));
+        assert_eq!(parser.get_opt_os("input"), Some(os(b"a\xff")));
+        assert_eq!(parser.get_opt_os(&'o'), Some(os(b"a\xfe")));
+        assert_eq!(parser.args_os(), vec![os(b"a\xfd")]);
+
+        let mut parser = parser.clone_spec();
+        let args = vec![os(b"prog"), os(b"-vo\xff"), os(b"-i\xe2\x82\xff")];
+        assert_eq!(parser.parse_os(args.into_iter()), Ok(()));
+        assert!(parser.found('v'));
+        assert_eq!(parser.get_opt_os("output"), Some(os(b"\xff")));
+        assert_eq!(parser.get_opt_os("input"), Some(os(b"\xe2\x82\xff")));
+    }
+
+    #[test]
+    fn add_flag_negatable() {
+        let parser = ArgParser::new(1).add_flag_negatable(&["c", "color"]);
+        assert_eq!(parser.usage("prog"), "Usage: prog [FLAGS]\n\nFlags:\n  -c, --[no-]color\n");
+
+        let mut last_off = parser.clone_spec();
+        last_off.parse_str("--color --no-color").unwrap();
+        assert!(!last_off.found("color"));
+        assert!(!last_off.found('c'));
+
+        let mut last_on = parser.clone_spec();
+        last_on.parse_str("--no-color -c").unwrap();
+        assert!(last_on.found("color"));
+
+        let mut plain = ArgParser::new(1).add_flag(&["c", "color"]);
+        let errors = plain.parse(["", "--color", "--no-color"].iter().map(|arg| arg.to_string())).unwrap_err();
+        assert_eq!(errors, vec![ArgParseError::UnknownOpt(String::from("--no-color"))]);
+        assert!(plain.found("color"));
+    }
+
+    #[test]
+    fn validate_values() {
+        let parser = ArgParser::new(2)
+            .add_opt_choices("m", "mode", &["fast", "slow"])
+            .add_opt_with("n", "name", |name| name.len() <= 4);
+
+        let mut valid = parser.clone_spec();
+        valid.parse_str("--mode=fast -n abc").unwrap();
+        assert_eq!(valid.validate(), Ok(()));
+
+        let mut invalid = parser.clone_spec();
+        invalid.parse_str("--mode=medium --name=toolong").unwrap();
+        assert_eq!(invalid.validate().unwrap_err().to_string(), "\
+Invalid value 'medium' for '--mode', expected one of: fast, slow
+Invalid value for '--name': 'toolong' isn't accepted
+");
+    }
+
+    #[test]
+    fn constraints() {
+        let parser = ArgParser::new(7)
+            .add_flag(&["q", "quiet"])
+            .add_flag(&["v", "verbose"])
+            .add_opt("o", "output")
+            .add_opt("f", "format")
+            .add_flag(&["json"])
+            .add_flag(&["yaml"])
+            .add_flag(&["toml"])
+            .conflicts("quiet", "verbose")
+            .requires("output", "format")
+            .one_of(&["json", "yaml", "toml"]);
+
+        let mut valid = parser.clone_spec();
+        valid.parse_str("-q --output=out --format=csv --json").unwrap();
+        assert_eq!(valid.validate(), Ok(()));
+
+        let mut invalid = parser.clone_spec();
+        invalid.parse_str("-q -v -o out").unwrap();
+        assert_eq!(invalid.validate().unwrap_err().constraints(), &[
+            String::from("Conflicting parameters '--quiet' and '--verbose'"),
+            String::from("'--output' requires '--format'"),
+            String::from("One of '--json', '--yaml' or '--toml' is required"),
+        ][..]);
+
+        let mut both = parser.clone_spec();
+        both.parse_str("--json --yaml").unwrap();
+        assert_eq!(both.validate().unwrap_err().to_string(), "Conflicting parameters '--json' and '--yaml'\n");
+    }
+
+    #[test]
+    fn to_parsed() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let mut parser = ArgParser::new(4)
+            .add_flag(&["v", "verbose"])
+            .add_opt("o", "output")
+            .add_opt_multi("I", "include")
+            .add_setting_default("if", "in.txt");
+        parser.parse_str("-v --verbose -o out -I a --include=b file").unwrap();
+        let parsed: ParsedArgs = parser.to_parsed();
+        assert_send_sync(&parsed);
+
+        let parsed = std::thread::spawn(move || parsed).join().unwrap();
+        assert_eq!(parsed.iter().collect::<Vec<(&Param, &ParsedValue)>>(), vec![
+            (&Param::Long(String::from("verbose")), &ParsedValue::Flag(true)),
+            (&Param::Long(String::from("output")), &ParsedValue::Opt(String::from("out"))),
+            (&Param::Long(String::from("include")), &ParsedValue::Multi(vec![String::from("a"), String::from("b")])),
+        ]);
+        assert!(parsed.found('v') && parsed.found("verbose"));
+        assert_eq!((parsed.count('v'), parsed.count("verbose")), (1, 1));
+        assert_eq!(parsed.get_opt("output"), Some("out"));
+        assert_eq!(parsed.get_opt('o'), Some("out"));
+        assert_eq!(parsed.get_opt_all('I'), &["a", "b"]);
+        assert_eq!(parsed.get_setting("if"), Some("in.txt"));
+        assert!(!parsed.found("if"));
+        assert!(!parsed.found("bogus"));
+        assert_eq!(parsed.args, vec![String::from("file")]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_parsed() {
+        #[derive(::serde::Deserialize, Debug, PartialEq)]
+        struct Config {
+            verbose: bool,
+            output: String,
+            n: String,
+            include: Vec<String>,
+            args: Vec<String>,
+        }
+
+        let mut parser = ArgParser::new(5)
+            .add_flag(&["v", "verbose"])
+            .add_flag(&["q"])
+            .add_opt("o", "output")
+            .add_opt("n", "")
+            .add_opt_multi("I", "include");
+        parser.parse_str("-v -o out -n 4 -I a --include=b file").unwrap();
+        let json = serde_json::to_string(&parser.to_parsed()).unwrap();
+        assert_eq!(json, r#"{"verbose":true,"output":"out","n":"4","include":["a","b"],"args":["file"]}"#);
+        let config: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(config, Config {
+            verbose: true,
+            output: String::from("out"),
+            n: String::from("4"),
+            include: vec![String::from("a"), String::from("b")],
+            args: vec![String::from("file")],
+        });
+    }
+
+    #[test]
+    fn get_matches() {
+        let words = |line: &str| Some(String::new()).into_iter().chain(split_shell(line).unwrap());
+        let parser = ArgParser::new(2).add_flag(&["v"]).add_opt("o", "output");
+
+        let first = parser.get_matches(words("-v -o a file")).unwrap();
+        assert!(first.found('v'));
+        assert_eq!(first.get_opt("output"), Some("a"));
+
+        let second = parser.get_matches(words("other")).unwrap();
+        assert!(!second.found('v'));
+        assert_eq!(second.count('v'), 0);
+        assert_eq!(second.get_opt("output"), None);
+        assert_eq!(second.args, vec![String::from("other")]);
+        assert!(parser.args.is_empty());
+
+        assert_eq!(parser.get_matches(words("-x")), Err(vec![ArgParseError::UnknownFlag(String::from("-x"))]));
+    }
+
+    #[test]
+    fn suggestions() {
+        let mut parser = ArgParser::new(3).add_flag(&["v", "verbose"]).add_opt("o", "output").add_setting("level");
+        assert_eq!(parser.suggest("verbos"), Some(String::from("--verbose")));
+        assert_eq!(parser.suggest("outptu"), Some(String::from("--output")));
+        assert_eq!(parser.suggest("levle"), Some(String::from("level")));
+        assert_eq!(parser.suggest("bogus"), None);
+        assert_eq!(parser.usage_line("prog"), "Usage: prog [FLAGS] [OPTIONS] [SETTINGS]");
+
+        parser.parse_str("--verbsoe --bogus -x").unwrap();
+        let err = parser.found_invalid().unwrap_err();
+        assert_eq!(err.suggestion(&Param::Long(String::from("verbsoe"))), Some("--verbose"));
+        assert_eq!(err.suggestion("verbsoe"), Some("--verbose"));
+        assert_eq!(err.suggestion("bogus"), None);
+        assert_eq!(err.to_string(), "\
+Invalid parameters '--verbsoe' and '--bogus' and '-x'
+Did you mean '--verbose' instead of '--verbsoe'?
+");
+    }
+
+    #[test]
+    fn generate_man() {
+        let parser = ArgParser::new(3)
+            .add_flag_desc(&["v", "verbose"], "Print more details")
+            .add_opt_default("o", "output", "out.txt")
+            .add_setting_desc("if", ".hidden files are read too")
+            .add_positional("input")
+            .commands(&["build"]);
+        assert_eq!(parser.generate_man("my-tool", 1, "do things"), "\
+.TH MY\\-TOOL 1
+.SH NAME
+my\\-tool \\- do things
+.SH SYNOPSIS
+.B my\\-tool
+[FLAGS] [OPTIONS] [SETTINGS] <input>
+.SH FLAGS
+.TP
+\\fB\\-v, \\-\\-verbose\\fR
+Print more details
+.SH OPTIONS
+.TP
+\\fB\\-o, \\-\\-output <VALUE>\\fR
+[default: out.txt]
+.SH SETTINGS
+.TP
+\\fBif=<VALUE>\\fR
+\\&.hidden files are read too
+.SH COMMANDS
+.TP
+\\fBbuild\\fR
+");
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn load_config() {
+        let path = env::temp_dir().join(format!("arg_parser_load_config_{}.toml", std::process::id()));
+        std::fs::write(&path, "\
+# Written by hand
+verbose = true
+output = \"from file.txt\" # quoted
+level = 2 ; inline comment
+
+[net]
+port = 8080
+").unwrap();
+        let parser = ArgParser::new(5)
+            .add_flag(&["v", "verbose"])
+            .add_opt_default("o", "output", "default.txt")
+            .add_opt_default("l", "level", "1")
+            .add_opt("", "net.port")
+            .add_opt_env("", "net.host", "ARG_PARSER_TEST_CONFIG_HOST");
+
+        let mut from_file = parser.clone_spec();
+        from_file.load_config(&path).unwrap();
+        from_file.parse_str("-o cli.txt").unwrap();
+        assert!(from_file.found("verbose"));
+        assert_eq!(from_file.value_source("verbose"), ValueSource::Config);
+        assert_eq!(from_file.get_opt("output"), Some(String::from("cli.txt")));
+        assert_eq!(from_file.value_source("output"), ValueSource::Cli);
+        assert_eq!(from_file.get_opt("level"), Some(String::from("2")));
+        assert_eq!(from_file.get_opt("net.port"), Some(String::from("8080")));
+        assert_eq!(from_file.value_source("net.port"), ValueSource::Config);
+
+        let mut after = parser.clone_spec();
+        after.parse_str("--level=3").unwrap();
+        after.load_config(&path).unwrap();
+        assert_eq!(after.get_opt("level"), Some(String::from("3")));
+        assert_eq!(after.get_opt("output"), Some(String::from("from file.txt")));
+        after.reset();
+        assert_eq!(after.get_opt("level"), Some(String::from("2")));
+        assert_eq!(after.clone_spec().value_source("output"), ValueSource::Config);
+
+        std::fs::write(&path, "bogus = 1\n").unwrap();
+        assert_eq!(parser.clone_spec().load_config(&path),
+                   Err(format!("Unknown parameter 'bogus' on line 1 of '{}'", path.display())));
+        std::fs::write(&path, "verbose\n").unwrap();
+        assert_eq!(parser.clone_spec().load_config(&path), Err(String::from("Expected 'key = value' on line 1")));
+        std::fs::write(&path, "output = 'a' b\n").unwrap();
+        assert_eq!(parser.clone_spec().load_config(&path),
+                   Err(String::from("Unexpected 'b' after the value on line 1")));
+        std::fs::write(&path, "output = \"a\n").unwrap();
+        assert_eq!(parser.clone_spec().load_config(&path), Err(String::from("Unterminated string on line 1")));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn counted_flags() {
+        let mut parser = ArgParser::new(2)
+            .add_counted_flag(&["v", "verbose"])
+            .add_flag(&["q", "quiet"]);
+        parser.parse_str("-vvv --verbose -q").unwrap();
+        assert_eq!(parser.count('v'), 3);
+        assert_eq!(parser.count("verbose"), 1);
+        assert_eq!(parser.get_count('v'), 4);
+        assert_eq!(parser.get_count("verbose"), 4);
+        assert_eq!(parser.get_count("quiet"), 1);
+        assert_eq!(parser.get_count("missing"), 0);
+        assert!(parser.usage("prog").contains("-v, --verbose..."));
+        assert!(!parser.usage("prog").contains("--quiet..."));
+    }
+
+    #[test]
+    fn long_opt_space_separated() {
+        let parser = ArgParser::new(3)
+            .add_opt("o", "output")
+            .add_opt_implicit("c", "color", "always")
+            .add_flag(&["v", "verbose"]);
+        let mut spaced = parser.clone_spec();
+        spaced.parse_str("--output result.txt file --output --verbose --color").unwrap();
+        assert_eq!(spaced.get_opt("output"), Some(String::from("result.txt")));
+        assert_eq!(spaced.get_opt("color"), Some(String::from("always")));
+        assert_eq!(spaced.args, vec![String::from("file")]);
+        assert!(spaced.found("verbose"));
+
+        let mut strict = parser.clone_spec().strict_long_values(true);
+        strict.parse_str("--output result.txt").unwrap();
+        assert!(strict.found("output"));
+        assert_eq!(strict.get_opt("output"), Some(String::new()));
+        assert_eq!(strict.args, vec![String::from("result.txt")]);
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("5"), Some((Some(5), Some(5))));
+        assert_eq!(parse_range("5-10"), Some((Some(5), Some(10))));
+        assert_eq!(parse_range("-10"), Some((None, Some(10))));
+        assert_eq!(parse_range("5-"), Some((Some(5), None)));
+        assert_eq!(parse_range("10-5"), None);
+        assert_eq!(parse_range("-"), None);
+        assert_eq!(parse_range(""), None);
+        assert_eq!(parse_range("a-b"), None);
+        assert_eq!(parse_range("1-2-3"), None);
+
+        let mut parser = ArgParser::new(2).add_opt("n", "lines").add_opt("", "bs");
+        parser.parse_str("--lines=-20 --bs=4KiB").unwrap();
+        assert_eq!(parser.get_opt_range("lines"), Some((None, Some(20))));
+        assert_eq!(parser.get_opt_bytes("bs"), Some(4096));
+    }
+
+    #[test]
+    fn deprecated_names() {
+        let mut parser = ArgParser::new(3)
+            .add_opt("c", "color")
+            .alias("color", "colour")
+            .add_flag(&["v", "verbose"])
+            .add_deprecated("loud", "verbose")
+            .add_deprecated("gone", "missing");
+        parser.parse_str("--colour=red --loud").unwrap();
+        assert_eq!(parser.get_opt("color"), Some(String::from("red")));
+        assert!(parser.found("verbose"));
+        assert!(!parser.found("gone"));
+        assert_eq!(parser.warnings(), ["'--loud' is deprecated, use '--verbose' instead"]);
+        assert!(parser.usage("prog").contains("-v, --verbose\n"));
+
+        parser.parse_str("--verbose").unwrap();
+        assert_eq!(parser.warnings().len(), 1);
+    }
+
+    #[test]
+    fn windows_style() {
+        let parser = ArgParser::new(3)
+            .add_flag(&["v", "verbose"])
+            .add_opt("o", "out")
+            .add_flag(&["help"]);
+
+        let mut windows = parser.clone_spec().style(ParserStyle::Windows);
+        windows.parse_str("/v /out:file.txt /help -x").unwrap();
+        assert!(windows.found('v'));
+        assert!(windows.found("help"));
+        assert_eq!(windows.get_opt("out"), Some(String::from("file.txt")));
+        assert_eq!(windows.args, ["-x"]);
+        windows.parse_str("/bogus").unwrap();
+        assert!(windows.found_invalid().is_err());
+
+        let mut mixed = parser.clone_spec().style(ParserStyle::Mixed);
+        mixed.parse_str("/o:a.txt -v /tmp/file").unwrap();
+        assert!(mixed.found("verbose"));
+        assert_eq!(mixed.get_opt("out"), Some(String::from("a.txt")));
+        assert_eq!(mixed.args, ["/tmp/file"]);
+
+        let mut gnu = parser.clone_spec();
+        gnu.parse_str("/v").unwrap();
+        assert!(!gnu.found('v'));
+        assert_eq!(gnu.args, ["/v"]);
+    }
+
+    #[test]
+    fn strict() {
+        let args = |args: &[&str]| {
+            Some("prog").iter().chain(args).map(|arg| arg.to_string()).collect::<Vec<String>>().into_iter()
+        };
+        let parser = ArgParser::new(2)
+            .add_flag(&["v", "verbose"])
+            .add_flag(&["q"])
+            .unknown_severity(Severity::Warn)
+            .strict(true);
+        let mut unknown = parser.clone_spec();
+        assert_eq!(unknown.parse(args(&["--bogus", "-v", "-x"])),
+                   Err(vec![ArgParseError::UnknownOpt(String::from("--bogus"))]));
+        assert!(!unknown.found('v'));
+        assert_eq!(unknown.skipped_args(), ["-v", "-x"]);
+
+        let mut positionals = parser.clone_spec().max_positionals(1);
+        let errors = positionals.parse(args(&["a", "-v", "b", "-q"])).unwrap_err();
+        assert_eq!(errors, vec![ArgParseError::UnexpectedArg(String::from("b"))]);
+        assert_eq!(errors[0].to_string(), "Unexpected argument 'b'");
+        assert_eq!(positionals.args, ["a"]);
+        assert!(positionals.found('v'));
+        assert!(!positionals.found('q'));
+
+        let mut lenient = ArgParser::new(1).add_flag(&["v"]).max_positionals(0);
+        assert!(lenient.parse(args(&["--bogus", "a", "-v"])).is_err());
+        assert!(lenient.found('v'));
+        assert_eq!(lenient.args, ["a"]);
+    }
+
+    #[test]
+    fn stop_at_first_positional() {
+        let parser = ArgParser::new(2).add_flag(&["v"]).add_flag(&["l"]);
+        let mut wrapper = parser.clone_spec().stop_at_first_positional(true);
+        wrapper.parse_str("-v ls -l -- x").unwrap();
+        assert!(wrapper.found('v'));
+        assert!(!wrapper.found('l'));
+        assert_eq!(wrapper.command(), Some(("ls", &["-l".to_owned(), "--".to_owned(), "x".to_owned()][..])));
+        assert_eq!(wrapper.args, ["ls", "-l", "--", "x"]);
+
+        let mut interspersed = parser.clone_spec();
+        interspersed.parse_str("-v ls -l").unwrap();
+        assert!(interspersed.found('l'));
+        assert_eq!(interspersed.command(), None);
+    }
+
+    #[test]
+    fn opt_lists() {
+        let mut parser = ArgParser::new(3)
+            .add_opt("f", "features")
+            .add_opt_multi("p", "path")
+            .add_opt("", "empty")
+            .delimiter('p', ':');
+        parser.parse_str(r"-f 'a,,b\,c,d\\' --path=/bin:/usr/bin -p '/opt\:x' --empty=").unwrap();
+        assert_eq!(parser.get_opt_list("features"), ["a", "b,c", "d\\"]);
+        assert_eq!(parser.get_opt_list('f'), parser.get_opt_list("features"));
+        assert_eq!(parser.get_opt_list("path"), ["/bin", "/usr/bin", "/opt:x"]);
+        assert!(parser.get_opt_list("empty").is_empty());
+        assert!(parser.get_opt_list("missing").is_empty());
+    }
+
+    #[test]
+    fn response_files() {
+        let dir = env::temp_dir();
+        let outer = dir.join("arg_parser_outer.rsp");
+        let inner = dir.join("arg_parser_inner.rsp");
+        let looping = dir.join("arg_parser_loop.rsp");
+        std::fs::write(&outer, format!("-v\n--output 'out file.txt'\n@{}\n", inner.display())).unwrap();
+        std::fs::write(&inner, "input.c\n").unwrap();
+        std::fs::write(&looping, format!("@{}", looping.display())).unwrap();
+        let parser = ArgParser::new(2).add_flag(&["v"]).add_opt("o", "output").response_files(true);
+
+        let mut expanded = parser.clone_spec();
+        let args = vec![String::new(), format!("@{}", outer.display()), String::from("last.c")];
+        expanded.parse(args.into_iter()).unwrap();
+        assert!(expanded.found('v'));
+        assert_eq!(expanded.get_opt("output"), Some(String::from("out file.txt")));
+        assert_eq!(expanded.args, ["input.c", "last.c"]);
+        assert!(expanded.warnings().is_empty());
+
+        // The args read from a file are at the position of their `@file`, and so are their errors
+        let mut positioned = parser.clone_spec().retain_input(true);
+        let args = vec![String::new(), format!("@{}", outer.display()),
+                        String::from("last.c"), String::from("--bogus")];
+        positioned.parse(args.into_iter()).unwrap_err();
+        assert_eq!(positioned.events()[0], (1, ParsedItem::Flag(Param::Short('v'))));
+        assert_eq!(positioned.positions, [1, 2]);
+        assert_eq!(positioned.diagnostics()[0].index, 3);
+        let highlight = positioned.highlight(&positioned.diagnostics()[0]).unwrap();
+        let (line, underline) = highlight.split_once('\n').unwrap();
+        assert_eq!(underline, format!("{}^^^^^^^", " ".repeat(line.len() - "--bogus".len())));
+
+        let mut missing = parser.clone_spec();
+        missing.parse_str("@/nonexistent/arg_parser.rsp").unwrap();
+        assert_eq!(missing.args, ["@/nonexistent/arg_parser.rsp"]);
+        assert_eq!(missing.warnings().len(), 1);
+
+        let mut nested = parser.clone_spec();
+        nested.parse(vec![String::new(), format!("@{}", looping.display())].into_iter()).unwrap();
+        assert_eq!(nested.args, [format!("@{}", looping.display())]);
+        assert_eq!(nested.warnings(), [format!("Response file '{}' is nested too deeply", looping.display())]);
+
+        let mut disabled = ArgParser::new(1).add_flag(&["v"]);
+        disabled.parse(vec![String::new(), format!("@{}", outer.display())].into_iter()).unwrap();
+        assert!(!disabled.found('v'));
+    }
+
+    #[test]
+    fn callbacks() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let (libs, dirs, verbose) = (events.clone(), events.clone(), events.clone());
+        let mut parser = ArgParser::new(3)
+            .add_opt_multi("l", "lib")
+            .add_opt_multi_csv("L", "")
+            .add_flag(&["v", "verbose"])
+            .on_opt('l', move |lib| libs.borrow_mut().push(format!("lib {}", lib)))
+            .on_opt('L', move |dir| dirs.borrow_mut().push(format!("dir {}", dir)))
+            .on_flag("verbose", move |count| verbose.borrow_mut().push(format!("verbose {}", count)));
+        parser.parse_str("-lm -L a,b --lib=c -vv x -L d --verbose").unwrap();
+        assert_eq!(*events.borrow(), ["lib m", "dir a", "dir b", "lib c", "verbose 2", "dir d", "verbose 3"]);
+        assert_eq!(parser.get_count('l'), 2);
+    }
+
+    #[test]
+    fn events() {
+        let mut parser = ArgParser::new(5)
+            .add_flag(&["v", "verbose"])
+            .add_flag(&["x"])
+            .add_opt("n", "name")
+            .add_opt_multi("t", "type")
+            .add_setting("if");
+        parser.parse_str("-xvv --name a b -t f if=in --verbose -- -n c").unwrap();
+        let long = |name: &str| Param::Long(name.to_owned());
+        assert_eq!(parser.events(), [
+            (1, ParsedItem::Flag(Param::Short('x'))),
+            (1, ParsedItem::Flag(Param::Short('v'))),
+            (1, ParsedItem::Flag(Param::Short('v'))),
+            (2, ParsedItem::Opt(long("name"), String::from("a"))),
+            (4, ParsedItem::Positional(String::from("b"))),
+            (5, ParsedItem::Opt(Param::Short('t'), String::from("f"))),
+            (7, ParsedItem::Setting(String::from("if"), String::from("in"))),
+            (8, ParsedItem::Flag(long("verbose"))),
+            (10, ParsedItem::Positional(String::from("-n"))),
+            (11, ParsedItem::Positional(String::from("c"))),
+        ]);
+        parser.reset();
+        assert!(parser.events().is_empty());
+    }
+
+    #[test]
+    fn hidden_and_unstable() {
+        let parser = ArgParser::new(4)
+            .add_flag(&["v", "verbose"])
+            .add_flag(&["d", "debug-dump"])
+            .add_opt("", "trace")
+            .hidden('d')
+            .unstable("trace")
+            .unstable_opt_in("unstable", "ARG_PARSER_TEST_UNSTABLE");
+        let usage = parser.usage("prog");
+        assert!(!usage.contains("debug-dump") && !usage.contains("trace"));
+        assert!(usage.contains("--unstable"));
+        assert_eq!(parser.definitions().len(), 2);
+        assert_eq!(parser.suggest("debug-dumb"), None);
+
+        let mut hidden = parser.clone_spec();
+        hidden.parse_str("--debug-dump").unwrap();
+        assert!(hidden.found('d'));
+        assert!(hidden.validate().is_ok());
+
+        let mut refused = parser.clone_spec();
+        refused.parse_str("--trace=all").unwrap();
+        assert_eq!(refused.get_opt("trace"), Some(String::from("all")));
+        assert_eq!(refused.validate().unwrap_err().constraints(),
+                   ["'--trace' is unstable, pass '--unstable' to use it"]);
+
+        let mut opted_in = parser.clone_spec();
+        opted_in.parse_str("--trace=all --unstable").unwrap();
+        assert!(opted_in.validate().is_ok());
+
+        env::set_var("ARG_PARSER_TEST_UNSTABLE", "1");
+        let mut from_env = parser.clone_spec();
+        from_env.parse_str("--trace=all").unwrap();
+        assert!(from_env.validate().is_ok());
+        env::remove_var("ARG_PARSER_TEST_UNSTABLE");
+    }
+
+    #[test]
+    fn setting_operands() {
+        let parser = ArgParser::new(3)
+            .add_setting_typed("count", SettingType::U64)
+            .add_setting_typed("bs", SettingType::Bytes)
+            .add_setting_choices("conv", &["notrunc", "sync"]);
+        let mut valid = parser.clone_spec();
+        valid.parse_str("count=3 bs=4K conv=sync").unwrap();
+        assert!(valid.validate().is_ok());
+        assert_eq!(valid.get_setting_as::<u64, _>("count"), Some(Ok(3)));
+
+        let mut invalid = parser.clone_spec();
+        invalid.parse_str("count=many bs=4K conv=fast").unwrap();
+        assert_eq!(invalid.get_setting("count"), Some(String::from("many")));
+        assert_eq!(invalid.validate().unwrap_err().invalid(), [
+            "Invalid value 'fast' for 'conv', expected one of: notrunc, sync",
+            "Invalid value for 'count': 'many' isn't an unsigned integer",
+        ]);
+
+        let mut unknown = parser.clone_spec();
+        assert_eq!(unknown.parse(vec![String::new(), String::from("skip=2")].into_iter()),
+                   Err(vec![ArgParseError::InvalidSetting(String::from("skip"))]));
+        assert_eq!(unknown.diagnostics()[0].message, "Unknown operand 'skip'");
+        let error = unknown.found_invalid().unwrap_err();
+        assert!(error.params().is_empty());
+        assert_eq!(error.operands(), ["skip"]);
+        assert_eq!(error.to_string(), "Unknown operand 'skip'\n");
+    }
+
+    #[test]
+    fn translated_messages() {
+        struct French;
+        impl Messages for French {
+            fn unknown_parameter(&self, name: &str) -> String {
+                format!("Paramètre inconnu '{}'", name)
+            }
+            fn invalid_parameters(&self, names: &[String]) -> String {
+                format!("Paramètres invalides : {}", names.join(", "))
+            }
+            fn usage(&self, program: &str) -> String {
+                format!("Utilisation : {}", program)
+            }
+            fn section_title(&self, section: Section) -> String {
+                match section {
+                    Section::Flags => String::from("Drapeaux"),
+                    _ => English.section_title(section),
+                }
+            }
+            fn missing_required(&self, name: &str) -> String {
+                format!("Paramètre requis manquant '{}'", name)
+            }
+            fn deprecated(&self, old: &str, new: &str) -> String {
+                format!("'{}' est obsolète, utilisez '{}'", old, new)
+            }
+        }
+        let mut parser = ArgParser::new(2)
+            .add_flag(&["v", "verbose"])
+            .add_opt_default("o", "output", "a.txt")
+            .messages(French);
+        parser.parse_str("-x --bogus -o").unwrap();
+        assert_eq!(parser.diagnostics()[0].message, "Paramètre inconnu '-x'");
+        assert_eq!(parser.diagnostics()[2].message, "Missing value for '-o'");
+        assert_eq!(parser.found_invalid().unwrap_err().to_string(), "Paramètres invalides : -x, --bogus\n");
+        let usage = parser.clone_spec().usage("prog");
+        assert!(usage.starts_with("Utilisation : prog [DRAPEAUX] [OPTIONS]\n"));
+        assert!(usage.contains("\nDrapeaux:\n"));
+        assert!(usage.contains("[default: a.txt]"));
+
+        let mut parser = ArgParser::new(3)
+            .add_opt("o", "output")
+            .add_deprecated("out", "output")
+            .add_opt("", "host")
+            .required("host")
+            .messages(French);
+        parser.parse_str("--out=a").unwrap();
+        assert_eq!(parser.warnings(), ["'--out' est obsolète, utilisez '--output'"]);
+        assert_eq!(parser.validate().unwrap_err().to_string(), "Paramètre requis manquant '--host'\n");
+        assert_eq!(ArgParseError::UnknownOpt(String::from("--bogus")).message(&French), "Paramètre inconnu '--bogus'");
+        assert_eq!(ArgParseError::UnknownOpt(String::from("--bogus")).to_string(), "Unknown parameter '--bogus'");
+        assert_eq!(ParseError::Unknown(String::from("-x")).message(&French), "Paramètre inconnu '-x'");
+    }
+
+    #[test]
+    fn multibyte_args() {
+        let mut parser = ArgParser::new(3)
+            .add_flag(&["é", "ünïcode"])
+            .add_opt("€", "währung")
+            .add_setting("größe")
+            .value_separators(&['=', '→'])
+            .style(ParserStyle::Mixed)
+            .allow_abbreviations(true);
+        parser.parse_str("-é --währung→10€ größe=𝄞 /€:x --ü -𝄞é --€=").unwrap();
+        assert!(parser.found("ünïcode"));
+        assert_eq!(parser.get_opt(&'€'), Some(String::from("x")));
+        assert_eq!(parser.get_setting("größe"), Some(String::from("𝄞")));
+        let invalid = parser.found_invalid().unwrap_err();
+        assert_eq!(invalid.params(), [Param::Short('𝄞'), Param::Long(String::from("€"))]);
+        for diagnostic in parser.diagnostics() {
+            assert!(parser.highlight(diagnostic).is_none());
+        }
+    }
+
+    #[test]
+    fn parse_slice() {
+        let mut parser = ArgParser::new(5)
+            .add_flag(&["v", "verbose"])
+            .add_opt_default("o", "output", "a.out")
+            .add_opt_multi_csv("I", "include")
+            .add_opt("n", "")
+            .add_setting("if");
+        let args = [
+            "-vv", "--output", "--verbose", "-Isrc,lib", "--include", "tests",
+            "if=in", "x", "-n", "-5", "-q", "--", "-v",
+        ];
+        assert!(parser.parse_slice(&args).is_err());
+        assert_eq!(parser.count('v'), 2);
+        assert!(parser.found("verbose"));
+        assert_eq!(parser.get_opt("output"), Some(String::from("a.out")));
+        assert_eq!(parser.get_opt_all("include").unwrap(), ["src", "lib", "tests"]);
+        assert_eq!(parser.get_opt(&'n'), Some(String::from("-5")));
+        assert_eq!(parser.get_setting("if"), Some(String::from("in")));
+        assert_eq!(parser.args, ["x", "-v"]);
+        assert_eq!(parser.found_invalid().unwrap_err().params(), [Param::Short('q')]);
+
+        // Each parse starts over from the registrations
+        assert!(parser.parse_slice(&["--output=b", "-I", "lib"]).is_ok());
+        assert!(!parser.found('v'));
+        assert_eq!(parser.get_opt("output"), Some(String::from("b")));
+        assert_eq!(parser.get_opt_all("include").unwrap(), ["lib"]);
+        assert!(parser.args.is_empty());
+        assert!(parser.found_invalid().is_ok());
+        assert!(parser.parse_slice(&["-o"]).is_err());
+        assert_eq!(parser.get_opt("output"), Some(String::from("a.out")));
+    }
+
+    #[test]
+    fn get_default() {
+        let mut parser = ArgParser::new(3)
+            .add_opt_default("o", "output", "a.out")
+            .add_setting_default("level", "info")
+            .add_opt("j", "jobs");
+        assert_eq!(parser.get_opt("output"), Some(String::from("a.out")));
+        assert_eq!(parser.get_default('o'), Some("a.out"));
+        assert!(parser.is_default("output"));
+        assert_eq!(parser.get_default("jobs"), None);
+        assert!(!parser.is_default('j'));
+        parser.parse_str("-o b.out level=debug").unwrap();
+        assert_eq!(parser.get_opt(&'o'), Some(String::from("b.out")));
+        assert_eq!(parser.get_default("output"), Some("a.out"));
+        assert!(!parser.is_default("output"));
+        assert_eq!(parser.get_default("level"), Some("info"));
+        assert!(!parser.is_default("level"));
+        assert_eq!(parser.value_source("output"), ValueSource::Cli);
+    }
+
+    #[test]
+    fn group() {
+        let parser = ArgParser::new(6)
+            .add_flag(&["v", "verbose"])
+            .group("Output options", |g| g.add_opt("o", "output").add_flag(&["q", "quiet"]).add_opt("", "color"))
+            .group("Input options", |g| g.add_setting("if"))
+            .group("Output options", |g| g.add_flag(&["a", "append"]))
+            .describe("output", "Write to a file");
+        assert_eq!(parser.usage("prog"), "Usage: prog [FLAGS] [OPTIONS]\n\
+            \nFlags:\n  -v, --verbose\n\
+            \nOutput options:\n  -o, --output <VALUE> Write to a file\n  -q, --quiet\n  --color <VALUE>\
+            \n  -a, --append\n\
+            \nInput options:\n  if=<VALUE>\n");
+        let groups: Vec<(Option<String>, Option<String>)> = parser.definitions().into_iter()
+            .map(|info| (info.long, info.group))
+            .collect();
+        let named = |long: &str, group: Option<&str>| (Some(long.to_owned()), group.map(String::from));
+        assert_eq!(groups, vec![
+            named("verbose", None),
+            named("output", Some("Output options")),
+            named("quiet", Some("Output options")),
+            named("color", Some("Output options")),
+            named("append", Some("Output options")),
+            named("if", Some("Input options")),
+        ]);
+        assert!(parser.generate_man("prog", 1, "do things").contains(".SH OUTPUT OPTIONS\n"));
+        assert_eq!(parser.clone_spec().usage("prog"), parser.usage("prog"));
+    }
+
+    #[test]
+    fn parse_line() {
+        let mut parser = ArgParser::new(2)
+            .add_flag(&["v", "verbose"])
+            .add_opt("b", "break");
+        let parsed = parser.parse_line(r#"-v --break "main.rs:12" 'two words' a\ b"#).unwrap();
+        assert!(parsed.found('v'));
+        assert_eq!(parsed.get_opt("break"), Some("main.rs:12"));
+        assert_eq!(parsed.args, ["two words", "a b"]);
+
+        let parsed = parser.parse_line("next").unwrap();
+        assert!(!parsed.found('v') && !parser.found('v'));
+        assert_eq!(parsed.get_opt('b'), None);
+        assert_eq!(parsed.args, ["next"]);
+
+        parser.parse_line("-x").unwrap();
+        assert!(parser.found_invalid().is_err());
+        assert!(parser.parse_line("-v 'open").is_err());
+        assert!(!parser.found('v') && parser.args.is_empty());
+    }
+
+    #[test]
+    fn allow_abbreviations() {
+        let parser = ArgParser::new(3)
+            .add_flag(&["verbose"])
+            .add_flag(&["version"])
+            .add_flag(&["color", "colour"])
+            .allow_abbreviations(true);
+        let candidates = vec![String::from("--verbose"), String::from("--version")];
+
+        let mut ambiguous = parser.clone_spec();
+        let errors = ambiguous.parse(["", "--ver", "--co"].iter().map(|arg| arg.to_string())).unwrap_err();
+        assert_eq!(errors, vec![ArgParseError::AmbiguousOpt(String::from("--ver"), candidates.clone())]);
+        assert_eq!(errors[0].to_string(), "Ambiguous parameter '--ver', could be '--verbose' or '--version'");
+        assert_eq!(ambiguous.diagnostics()[0].kind, DiagnosticKind::Ambiguous);
+        assert!(ambiguous.found("color"));
+
+        let mut strict = parser.clone_spec();
+        let error = strict.parse_strict(["", "--ver"].iter().map(|arg| arg.to_string())).unwrap_err();
+        assert_eq!(error, ParseError::Ambiguous(String::from("--ver"), candidates));
+
+        let mut full = parser.clone_spec().allow_abbreviations(false);
+        let errors = full.parse(["", "--ver", "--verb", "--colour"].iter().map(|arg| arg.to_string())).unwrap_err();
+        assert_eq!(errors, vec![ArgParseError::UnknownOpt(String::from("--ver")),
+                                ArgParseError::UnknownOpt(String::from("--verb"))]);
+        assert!(full.found("color"));
+
+        let mut default = ArgParser::new(1).add_flag(&["verbose"]);
+        assert!(!ParserConfig::default().abbreviations);
+        let errors = default.parse(["", "--verb"].iter().map(|arg| arg.to_string())).unwrap_err();
+        assert_eq!(errors, vec![ArgParseError::UnknownOpt(String::from("--verb"))]);
+    }
+
+    #[test]
+    fn global_params() {
+        let parser = ArgParser::new(3)
+            .add_global_flag(&["v", "verbose"])
+            .add_global_opt("c", "config")
+            .add_subcommand("fetch", ArgParser::new(1).add_flag(&["all"])
+                .add_subcommand("tags", ArgParser::new(0)))
+            .add_subcommand("push", ArgParser::new(1).add_opt("c", "commit"));
+
+        let mut fetch = parser.clone_spec();
+        fetch.parse_str("-v fetch -v --all --config=git.toml").unwrap();
+        assert!(fetch.found_invalid().is_ok());
+        assert_eq!(fetch.count('v'), 2);
+        assert_eq!(fetch.get_opt("config"), Some(String::from("git.toml")));
+        let matches = fetch.subcommand_matches("fetch").unwrap();
+        assert!(matches.found("all") && matches.found('v'));
+        assert_eq!(matches.get_opt(&'c'), Some(String::from("git.toml")));
+
+        let mut nested = parser.clone_spec();
+        nested.parse_str("fetch tags -v").unwrap();
+        assert!(nested.found_invalid().is_ok());
+        assert_eq!(nested.count('v'), 1);
+
+        let mut push = parser.clone_spec();
+        push.parse_str("push -c abc --config=a.toml").unwrap();
+        assert_eq!(push.subcommand_matches("push").unwrap().get_opt(&'c'), Some(String::from("abc")));
+        assert_eq!(push.get_opt("config"), Some(String::from("a.toml")));
+        assert!(!parser.clone_spec().found('v'));
+    }
+
+    #[test]
+    fn parse_or_exit() {
+        let parser = ArgParser::new(3)
+            .set_help_flag("h", "help")
+            .set_version_flag("V", "version", "prog 1.2.0")
+            .add_opt("o", "output")
+            .add_positional("input");
+        let outcome = |args: &str| parser.clone_spec().parse_outcome(args.split(' ').map(String::from));
+        assert_eq!(outcome("./bin/prog file"), None);
+        assert_eq!(outcome("prog --bogus -h"), Some((0, parser.help_text("prog"))));
+        assert_eq!(outcome("prog -V"), Some((0, String::from("prog 1.2.0\n"))));
+        let (code, text) = outcome("./bin/prog --bogus").unwrap();
+        assert_eq!(code, 2);
+        assert!(text.starts_with("Unknown parameter '--bogus'\nMissing argument '<input>'\n\nUsage: prog "));
+        let mut exiting = parser.clone_spec().usage_exit_code(64);
+        assert_eq!(exiting.parse_outcome(["prog"].iter().map(|arg| arg.to_string())).map(|(code, _)| code), Some(64));
+    }
+
+    #[test]
+    fn metadata() {
+        let parser = ArgParser::new(2)
+            .name("mytool")
+            .version("1.2.0")
+            .author("Jane Doe <jane@example.org>")
+            .about("Does things")
+            .set_help_flag("h", "help");
+        assert_eq!(parser.version_text(), "mytool 1.2.0");
+        assert!(parser.help_text("mytool")
+            .starts_with("mytool 1.2.0\nJane Doe <jane@example.org>\nDoes things\n\nUsage: mytool [FLAGS]\n"));
+        assert!(parser.help().starts_with("mytool 1.2.0\n"));
+
+        let args = |line: &str| line.split(' ').map(String::from).collect::<Vec<String>>().into_iter();
+        assert_eq!(parser.clone_spec().parse_with_outcome(args("prog -V --bogus")), Ok(ParseOutcome::Version));
+        assert_eq!(parser.clone_spec().parse_with_outcome(args("prog --version --help")), Ok(ParseOutcome::Help));
+        assert_eq!(parser.clone_spec().parse_with_outcome(args("prog")), Ok(ParseOutcome::Parsed));
+        assert!(parser.clone_spec().parse_with_outcome(args("prog --bogus")).is_err());
+        assert_eq!(parser.clone_spec().parse_outcome(args("./prog --version")),
+                   Some((0, String::from("mytool 1.2.0\n"))));
+
+        let custom = ArgParser::new(1).set_version_flag("v", "show-version", "1.0").version("2.0");
+        assert_eq!(custom.version_text(), "2.0");
+        assert!(custom.clone_spec().parse_with_outcome(args("prog -V")).is_err());
+        assert_eq!(ArgParser::new(0).version_text(), "");
+    }
+
+    #[test]
+    fn collect_unknown() {
+        let parser = ArgParser::new(2)
+            .add_flag(&["v", "verbose"])
+            .add_opt("o", "output")
+            .collect_unknown(true);
+        let mut wrapper = parser.clone_spec();
+        wrapper.parse_str("-v --inner=1 -o out -vZfoo jobs=4 --verbose --bogus file").unwrap();
+        assert_eq!(wrapper.unparsed(), &["--inner=1", "-Zfoo", "jobs=4", "--bogus"]);
+        assert_eq!(wrapper.count('v'), 2);
+        assert_eq!(wrapper.get_opt(&'o'), Some(String::from("out")));
+        assert_eq!(wrapper.args, vec!["file"]);
+        assert!(wrapper.found_invalid().is_ok());
+
+        let mut invalid = parser.clone_spec().collect_unknown(false);
+        invalid.parse_str("--inner=1").unwrap();
+        assert!(invalid.unparsed().is_empty());
+        assert!(invalid.found_invalid().is_err());
+    }
+
+    #[test]
+    fn underscores_as_dashes() {
+        let parser = ArgParser::new(3)
+            .add_opt("", "log-level")
+            .add_flag(&["dry_run"])
+            .add_setting("block_size");
+
+        let mut exact = parser.clone_spec();
+        exact.parse_str("--log_level=debug --dry-run block-size=4").unwrap();
+        assert!(!exact.found("log-level") && !exact.found("dry_run") && !exact.found("block_size"));
+        assert!(exact.found_invalid().is_err());
+
+        let mut normalized = parser.clone_spec().underscores_as_dashes(true);
+        normalized.parse_str("--log_level=debug --dry-run block-size=4").unwrap();
+        assert_eq!(normalized.get_opt("log-level"), Some(String::from("debug")));
+        assert!(normalized.found("dry_run"));
+        assert_eq!(normalized.get_setting("block_size"), Some(String::from("4")));
+        assert!(normalized.found_invalid().is_ok());
+
+        let mut both = parser.clone_spec().underscores_as_dashes(true).case_insensitive_long(true);
+        both.parse_str("--Log_Level info BLOCK-SIZE=8").unwrap();
+        assert_eq!(both.get_opt("log-level"), Some(String::from("info")));
+        assert_eq!(both.get_setting("block_size"), Some(String::from("8")));
+        assert!(both.found_invalid().is_ok());
+
+        // Each parser hashes its names with its own seed, so the first registered name has to win
+        // whatever order they are stored in
+        for _ in 0..8 {
+            let mut colliding = ArgParser::new(2)
+                .add_flag(&["dry-run"])
+                .add_flag(&["dry_run"])
+                .underscores_as_dashes(true)
+                .case_insensitive_long(true);
+            colliding.parse_str("--Dry_Run").unwrap();
+            assert!(colliding.found("dry-run") && !colliding.found("dry_run"));
+        }
+    }
+
+    #[cfg(feature = "prompt")]
+    #[test]
+    fn resolve_prompts() {
+        let mut parser = ArgParser::new(2)
+            .add_opt("u", "user")
+            .add_opt("p", "password")
+            .prompt_if_missing("user", "User: ")
+            .prompt_secret_if_missing("password", "Password: ")
+            .add_flag(&["v"])
+            .prompt_if_missing('v', "Verbose? ");
+        parser.parse("prog --user=me".split_whitespace().map(String::from)).unwrap();
+        let mut output = Vec::new();
+        parser.resolve_prompts_from(&mut "hunter2\r\n".as_bytes(), &mut output, false).unwrap();
+        assert_eq!(output, b"Password: ");
+        assert_eq!(parser.get_opt(&'u'), Some(String::from("me")));
+        assert_eq!(parser.get_opt(&'p'), Some(String::from("hunter2")));
+        assert_eq!(parser.count('p'), 0);
+
+        let mut parser = parser.clone_spec();
+        parser.parse("prog".split_whitespace().map(String::from)).unwrap();
+        let err = parser.resolve_prompts_from(&mut "me\n".as_bytes(), &mut Vec::new(), false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(parser.get_opt(&'u'), Some(String::from("me")));
+    }
+
+    #[test]
+    fn implicit_long_opt_keeps_next_arg() {
+        let parser = ArgParser::new(1).add_opt_implicit("c", "color", "always");
+        let mut spaced = parser.clone_spec();
+        spaced.parse_str("--color file").unwrap();
+        assert!(spaced.found("color"));
+        assert_eq!(spaced.args, vec![String::from("file")]);
+
+        let mut attached = parser.clone_spec();
+        attached.parse_str("--color=never -5").unwrap();
+        assert_eq!(attached.get_opt("color"), Some(String::from("never")));
+        assert_eq!(attached.args, vec![String::from("-5")]);
     }
 }
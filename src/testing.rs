@@ -0,0 +1,159 @@
+//! Helpers for testing the command line of a program built on `ArgParser`
+//!
+//! A `Snapshot` holds everything a parse found, so each case of a table-driven test is a single
+//! comparison rather than a getter call per parameter:
+//!
+//! ```
+//! use arg_parser::ArgParser;
+//! use arg_parser::testing::{self, Snapshot};
+//!
+//! let parser = ArgParser::new(2).add_flag(&["v", "verbose"]).add_opt("o", "out");
+//! let cases = [
+//!     ("prog -v file", Snapshot::default().flag("--verbose").arg("file")),
+//!     ("prog --out=x -z", Snapshot::default().opt("--out", "x").invalid("-z")),
+//! ];
+//! for (line, expected) in cases.iter() {
+//!     assert_eq!(&testing::parse(&parser, line).unwrap(), expected);
+//! }
+//! ```
+
+use std::fmt;
+
+use super::{split_shell, ArgParser, ParsedValue};
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+/// The results of a parse, with every parameter named the way it's written on the command line
+/// and preferably by its long name, e.g. `--verbose` or `level`
+///
+/// Parameters are in the order they were registered in, and its `Display` renders one line per
+/// non-empty field, like `flags: --verbose`, for comparing against a string.
+pub struct Snapshot {
+    /// The flags found
+    pub flags: Vec<String>,
+    /// The values of the opts found, one pair per value of a repeated opt
+    pub opts: Vec<(String, String)>,
+    /// The values of the settings found
+    pub settings: Vec<(String, String)>,
+    /// The positionals
+    pub args: Vec<String>,
+    /// The invalid parameters, followed by the unknown operands
+    pub invalid: Vec<String>,
+    /// The opts that ended the args without being given a value
+    pub missing: Vec<String>,
+}
+
+impl Snapshot {
+    /// Builder method for expecting the flag `name`
+    pub fn flag(mut self, name: &str) -> Self {
+        self.flags.push(String::from(name));
+        self
+    }
+
+    /// Builder method for expecting the opt `name` to hold `value`
+    pub fn opt(mut self, name: &str, value: &str) -> Self {
+        self.opts.push((String::from(name), String::from(value)));
+        self
+    }
+
+    /// Builder method for expecting the setting `name` to hold `value`
+    pub fn setting(mut self, name: &str, value: &str) -> Self {
+        self.settings.push((String::from(name), String::from(value)));
+        self
+    }
+
+    /// Builder method for expecting the positional `arg`
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(String::from(arg));
+        self
+    }
+
+    /// Builder method for expecting the invalid parameter or unknown operand `name`
+    pub fn invalid(mut self, name: &str) -> Self {
+        self.invalid.push(String::from(name));
+        self
+    }
+
+    /// Builder method for expecting the opt `name` to end the args without a value
+    pub fn missing(mut self, name: &str) -> Self {
+        self.missing.push(String::from(name));
+        self
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pairs = |pairs: &[(String, String)]| {
+            pairs.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<String>>()
+        };
+        let lines = [
+            ("flags", self.flags.clone()),
+            ("opts", pairs(&self.opts)),
+            ("settings", pairs(&self.settings)),
+            ("args", self.args.clone()),
+            ("invalid", self.invalid.clone()),
+            ("missing", self.missing.clone()),
+        ];
+        for (title, words) in lines.iter().filter(|(_, words)| !words.is_empty()) {
+            writeln!(f, "{}: {}", title, words.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Take a `Snapshot` of what `parser` found so far
+pub fn snapshot(parser: &ArgParser) -> Snapshot {
+    let mut snapshot = Snapshot { args: parser.args.clone(), ..Snapshot::default() };
+    for (param, value) in parser.iter_set() {
+        let name = parser.display_name(param);
+        match value {
+            ParsedValue::Flag(_) => snapshot.flags.push(name),
+            ParsedValue::Opt(value) => snapshot.opts.push((name, value)),
+            ParsedValue::Setting(value) => snapshot.settings.push((name, value)),
+            ParsedValue::Multi(values) => snapshot.opts.extend(values.into_iter().map(|value| (name.clone(), value))),
+        }
+    }
+    snapshot.invalid = parser.invalid.iter().map(|param| parser.display_name(param))
+        .chain(parser.invalid_operands.iter().cloned())
+        .collect();
+    snapshot.missing = parser.missing_values.iter().map(|param| parser.display_name(param)).collect();
+    snapshot
+}
+
+/// Parse the command line `line` with a fresh copy of the registrations of `parser`, and take a
+/// `Snapshot` of the results
+///
+/// Unlike `ArgParser::parse_str`, `line` starts with the program name, as in `prog -v file`. It
+/// is split the way a shell would, and an unterminated quote is an error.
+pub fn parse(parser: &ArgParser, line: &str) -> Result<Snapshot, String> {
+    let mut parser = parser.clone_spec();
+    let _ = parser.parse(split_shell(line)?.into_iter());
+    Ok(snapshot(&parser))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, snapshot, ArgParser, Snapshot};
+
+    #[test]
+    fn table() {
+        let parser = ArgParser::new(4)
+            .add_flag(&["v", "verbose"])
+            .add_flag(&["q"])
+            .add_opt("o", "out")
+            .add_setting("level");
+        let cases = [
+            ("prog", Snapshot::default()),
+            ("prog -vq 'a file'", Snapshot::default().flag("--verbose").flag("-q").arg("a file")),
+            ("prog --out=x level=3", Snapshot::default().opt("--out", "x").setting("level", "3")),
+            ("prog --bogus -o", Snapshot::default().invalid("--bogus").missing("-o")),
+        ];
+        for (line, expected) in cases.iter() {
+            assert_eq!(&parse(&parser, line).unwrap(), expected, "{}", line);
+        }
+        assert!(parse(&parser, "prog 'unterminated").is_err());
+
+        let mut parser = parser;
+        parser.parse_str("-v file --out=x").unwrap();
+        assert_eq!(snapshot(&parser).to_string(), "flags: --verbose\nopts: --out=x\nargs: file\n");
+    }
+}